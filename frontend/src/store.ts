@@ -15,6 +15,16 @@ export interface Coin {
 	symbol: string;
 	price: number;
 	amount: number;
+	metadata: CoinMetadata;
+}
+
+export interface CoinMetadata {
+	decimals: number;
+	display_precision: number;
+	icon: string;
+	category: string;
+	active: boolean;
+	sort_order: number;
 }
 
 export const users: Writable<User[]> = writable([]);
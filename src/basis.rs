@@ -0,0 +1,837 @@
+//! Cost basis engine, builds a tax lot from every acquisition and matches each disposal
+//! against the open lots of the coin with a [`CostBasisMethod`]. Nothing is stored, the
+//! result is derived from the transactions so the same log and method always match the same
+//! way
+
+use std::collections::BTreeMap;
+
+use crate::{
+    tax, CoinBasis, CostBasis, CostBasisMethod, Lot, LotMatch, LotSelection, MatchRule, PoolEvent,
+    Transaction, Treatment,
+};
+
+/// Amounts within this of 0 are treated as 0, so a lot sold in several parts is closed even
+/// with floating point error
+pub(crate) const AMOUNT_TOLERANCE: f64 = 1e-9;
+
+/// Coins taken from a single lot, or from no lot when more was disposed of than was held
+struct Taken {
+    lot_id: String,
+    acquired_at: i64,
+    amount: f64,
+    cost: f64,
+}
+
+/// Order the open lots are used in by the method, as indexes into `lots`
+pub(crate) fn match_order(lots: &[Lot], method: CostBasisMethod) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..lots.len()).collect();
+    match method {
+        CostBasisMethod::Lifo => order.reverse(),
+        // Stable so lots that cost the same are used oldest first
+        CostBasisMethod::Hifo => {
+            order.sort_by(|a, b| lots[*b].unit_cost.total_cmp(&lots[*a].unit_cost))
+        }
+        CostBasisMethod::Fifo
+        | CostBasisMethod::AverageCost
+        | CostBasisMethod::SpecificLot
+        | CostBasisMethod::Section104 => {}
+    }
+    order
+}
+
+/// Takes up to `amount` from the lot at `index`, returns how much was taken
+fn take(lots: &mut [Lot], index: usize, amount: f64, taken: &mut Vec<Taken>) -> f64 {
+    let lot = &mut lots[index];
+    let mut amount = amount.min(lot.amount);
+    if lot.amount - amount <= AMOUNT_TOLERANCE {
+        amount = lot.amount;
+    }
+    if amount <= 0.0 {
+        return 0.0;
+    }
+    lot.amount -= amount;
+    taken.push(Taken {
+        lot_id: lot.id.clone(),
+        acquired_at: lot.acquired_at,
+        amount,
+        cost: amount * lot.unit_cost,
+    });
+    amount
+}
+
+/// Removes `amount` of coins from the open lots, closing any that are used up. `selections`
+/// are only used with specific lot identification
+fn dispose(
+    lots: &mut Vec<Lot>,
+    method: CostBasisMethod,
+    amount: f64,
+    selections: &[LotSelection],
+) -> Vec<Taken> {
+    let mut taken = vec![];
+    let mut remaining = amount;
+
+    if method == CostBasisMethod::AverageCost {
+        let held: f64 = lots.iter().map(|lot| lot.amount).sum();
+        if held > 0.0 {
+            let fraction = (amount / held).min(1.0);
+            for index in 0..lots.len() {
+                let share = lots[index].amount * fraction;
+                remaining -= take(lots, index, share, &mut taken);
+            }
+        }
+    } else {
+        if method == CostBasisMethod::SpecificLot {
+            for selection in selections {
+                if let Some(index) = lots.iter().position(|lot| lot.id == selection.id) {
+                    remaining -= take(lots, index, selection.amount.min(remaining), &mut taken);
+                }
+            }
+        }
+        for index in match_order(lots, method) {
+            if remaining <= AMOUNT_TOLERANCE {
+                break;
+            }
+            remaining -= take(lots, index, remaining, &mut taken);
+        }
+    }
+
+    if remaining > AMOUNT_TOLERANCE {
+        taken.push(Taken {
+            lot_id: String::new(),
+            acquired_at: 0,
+            amount: remaining,
+            cost: 0.0,
+        });
+    }
+    lots.retain(|lot| lot.amount > AMOUNT_TOLERANCE);
+    taken
+}
+
+/// Adds a match for each part of a disposal, `proceeds` is shared by the amount of each part
+fn report(
+    matches: &mut Vec<LotMatch>,
+    transaction: &Transaction,
+    coin: &str,
+    proceeds: f64,
+    taken: Vec<Taken>,
+) {
+    let amount: f64 = taken.iter().map(|t| t.amount).sum();
+    for part in taken {
+        matches.push(LotMatch {
+            disposal_id: transaction.id.clone(),
+            coin: coin.to_string(),
+            disposed_at: transaction.executed_at,
+            lot_id: part.lot_id,
+            acquired_at: part.acquired_at,
+            amount: part.amount,
+            cost: part.cost,
+            proceeds: proceeds * part.amount / amount,
+            rule: None,
+        });
+    }
+}
+
+/// Builds the lots and matches for a user's transactions, which must already have corrections
+/// applied with [`crate::ledger::effective`] so they're in execution order. Acquisitions,
+/// income and deposits open lots, the cost of a deposit is its `price`. Disposals are matched
+/// and reported, withdrawals remove lots without being reported. A fee paid in another coin is
+/// a disposal of that coin at `fee_price`
+pub fn cost_basis(transactions: &[Transaction], method: CostBasisMethod) -> CostBasis {
+    if method == CostBasisMethod::Section104 {
+        return section_104(transactions);
+    }
+
+    let mut pools: BTreeMap<String, Vec<Lot>> = BTreeMap::new();
+    let mut matches = vec![];
+    for transaction in transactions {
+        let lots = pools.entry(transaction.coin.clone()).or_default();
+        match transaction.kind.treatment() {
+            Treatment::Acquisition | Treatment::Income | Treatment::TransferIn => lots.push(Lot {
+                id: transaction.id.clone(),
+                coin: transaction.coin.clone(),
+                acquired_at: transaction.executed_at,
+                amount: transaction.amount,
+                unit_cost: transaction.cost() / transaction.amount,
+            }),
+            Treatment::Disposal => {
+                let taken = dispose(lots, method, transaction.amount, &transaction.lots);
+                report(
+                    &mut matches,
+                    transaction,
+                    &transaction.coin,
+                    transaction.proceeds(),
+                    taken,
+                );
+            }
+            Treatment::TransferOut => {
+                dispose(lots, method, transaction.amount, &transaction.lots);
+            }
+        }
+
+        if !transaction.fee_asset.is_empty() && transaction.fee > 0.0 {
+            let lots = pools.entry(transaction.fee_asset.clone()).or_default();
+            let taken = dispose(lots, method, transaction.fee, &[]);
+            report(
+                &mut matches,
+                transaction,
+                &transaction.fee_asset,
+                transaction.fee_value(),
+                taken,
+            );
+        }
+    }
+
+    let coins = pools
+        .into_iter()
+        .map(|(coin, lots)| {
+            let amount: f64 = lots.iter().map(|lot| lot.amount).sum();
+            let cost: f64 = lots.iter().map(|lot| lot.amount * lot.unit_cost).sum();
+            CoinBasis {
+                coin,
+                amount,
+                cost,
+                average_cost: if amount > AMOUNT_TOLERANCE {
+                    cost / amount
+                } else {
+                    0.0
+                },
+                lots,
+            }
+        })
+        .collect();
+
+    CostBasis {
+        method,
+        coins,
+        matches,
+        pool: vec![],
+    }
+}
+
+/// Id used as the lot of everything matched from a Section 104 pool
+pub const SECTION_104_LOT: &str = "section_104";
+
+/// A change to the amount of a coin held, for HMRC share identification. `left` counts down
+/// as the event is matched, whatever is left of it goes through the pool
+struct ShareEvent<'a> {
+    transaction: &'a Transaction,
+    coin: &'a str,
+    // UK day of the transaction
+    day: i64,
+    treatment: Treatment,
+    amount: f64,
+    left: f64,
+    // Cost of an acquisition, proceeds of a disposal
+    value: f64,
+    // Cost per coin of all acquisitions of the coin on the same day, HMRC treats them as one
+    unit_cost: f64,
+    matches: Vec<LotMatch>,
+}
+
+impl ShareEvent<'_> {
+    fn acquires(&self) -> bool {
+        matches!(
+            self.treatment,
+            Treatment::Acquisition | Treatment::Income | Treatment::TransferIn
+        )
+    }
+
+    /// Records part of this disposal as matched
+    fn matched(
+        &mut self,
+        lot_id: &str,
+        acquired_at: i64,
+        amount: f64,
+        cost: f64,
+        rule: Option<MatchRule>,
+    ) {
+        self.left -= amount;
+        self.matches.push(LotMatch {
+            disposal_id: self.transaction.id.clone(),
+            coin: self.coin.to_string(),
+            disposed_at: self.transaction.executed_at,
+            lot_id: lot_id.to_string(),
+            acquired_at,
+            amount,
+            cost,
+            proceeds: self.value * amount / self.amount,
+            rule,
+        });
+    }
+}
+
+/// Amount that can be matched between what's left of a disposal and an acquisition, taking
+/// all of the acquisition when it's within the tolerance
+fn matchable(disposal: f64, acquisition: f64) -> f64 {
+    if acquisition - disposal <= AMOUNT_TOLERANCE {
+        acquisition.min(disposal + AMOUNT_TOLERANCE)
+    } else {
+        disposal
+    }
+}
+
+/// Matches every disposal of `rule` against acquisitions on the days `days` returns for the
+/// disposal's day, in the order the acquisitions were executed
+fn match_acquisitions(events: &mut [ShareEvent], rule: MatchRule, days: impl Fn(i64, i64) -> bool) {
+    for disposal in 0..events.len() {
+        if events[disposal].treatment != Treatment::Disposal {
+            continue;
+        }
+        for acquisition in 0..events.len() {
+            if events[disposal].left <= AMOUNT_TOLERANCE {
+                break;
+            }
+            let (d, a) = (&events[disposal], &events[acquisition]);
+            if !a.acquires() || a.coin != d.coin || a.left <= 0.0 || !days(d.day, a.day) {
+                continue;
+            }
+            let amount = matchable(d.left, a.left);
+            let (id, acquired_at, cost) = (
+                a.transaction.id.clone(),
+                a.transaction.executed_at,
+                amount * a.unit_cost,
+            );
+            events[acquisition].left -= amount;
+            events[disposal].matched(&id, acquired_at, amount, cost, Some(rule));
+        }
+    }
+}
+
+/// HMRC share identification. Disposals are matched against acquisitions on the same UK day,
+/// then acquisitions in the 30 days after, then the Section 104 pool of everything else at its
+/// average cost. Withdrawals leave the pool without being matched
+fn section_104(transactions: &[Transaction]) -> CostBasis {
+    let mut events = vec![];
+    for transaction in transactions {
+        let value = match transaction.kind.treatment() {
+            Treatment::Disposal => transaction.proceeds(),
+            Treatment::TransferOut => 0.0,
+            _ => transaction.cost(),
+        };
+        let mut legs = vec![(
+            transaction.coin.as_str(),
+            transaction.kind.treatment(),
+            transaction.amount,
+            value,
+        )];
+        if !transaction.fee_asset.is_empty() && transaction.fee > 0.0 {
+            legs.push((
+                transaction.fee_asset.as_str(),
+                Treatment::Disposal,
+                transaction.fee,
+                transaction.fee_value(),
+            ));
+        }
+        for (coin, treatment, amount, value) in legs {
+            events.push(ShareEvent {
+                transaction,
+                coin,
+                day: tax::uk::date(transaction.executed_at).days(),
+                treatment,
+                amount,
+                left: amount,
+                value,
+                unit_cost: 0.0,
+                matches: vec![],
+            });
+        }
+    }
+
+    let mut days: BTreeMap<(&str, i64), (f64, f64)> = BTreeMap::new();
+    for event in events.iter().filter(|e| e.acquires()) {
+        let day = days.entry((event.coin, event.day)).or_default();
+        day.0 += event.amount;
+        day.1 += event.value;
+    }
+    for event in events.iter_mut().filter(|e| e.acquires()) {
+        let (amount, cost) = days[&(event.coin, event.day)];
+        event.unit_cost = cost / amount;
+    }
+
+    match_acquisitions(&mut events, MatchRule::SameDay, |disposed, acquired| {
+        acquired == disposed
+    });
+    match_acquisitions(
+        &mut events,
+        MatchRule::BedAndBreakfast,
+        |disposed, acquired| acquired > disposed && acquired <= disposed + 30,
+    );
+
+    let mut pools: BTreeMap<&str, (f64, f64)> = BTreeMap::new();
+    let mut pool = vec![];
+    for event in &mut events {
+        let (pool_amount, pool_cost) = pools.entry(event.coin).or_default();
+        let (amount, cost) = if event.acquires() {
+            (event.left, event.left * event.unit_cost)
+        } else {
+            let amount = matchable(event.left, *pool_amount);
+            let cost = if amount > 0.0 {
+                *pool_cost * amount / *pool_amount
+            } else {
+                0.0
+            };
+            if event.treatment == Treatment::Disposal && amount > 0.0 {
+                event.matched(
+                    SECTION_104_LOT,
+                    0,
+                    amount,
+                    cost,
+                    Some(MatchRule::Section104),
+                );
+            } else {
+                event.left -= amount;
+            }
+            (-amount, -cost)
+        };
+        if amount == 0.0 {
+            continue;
+        }
+        *pool_amount += amount;
+        *pool_cost += cost;
+        if *pool_amount <= AMOUNT_TOLERANCE {
+            (*pool_amount, *pool_cost) = (0.0, 0.0);
+        }
+        pool.push(PoolEvent {
+            id: event.transaction.id.clone(),
+            coin: event.coin.to_string(),
+            executed_at: event.transaction.executed_at,
+            amount,
+            cost,
+            pool_amount: *pool_amount,
+            pool_cost: *pool_cost,
+        });
+    }
+
+    let mut matches = vec![];
+    for mut event in events {
+        if event.treatment == Treatment::Disposal && event.left > AMOUNT_TOLERANCE {
+            // Disposed of without being held, so there's no allowable cost
+            event.matched("", 0, event.left, 0.0, None);
+        }
+        matches.extend(event.matches);
+    }
+
+    let coins = pools
+        .into_iter()
+        .map(|(coin, (amount, cost))| CoinBasis {
+            coin: coin.to_string(),
+            amount,
+            cost,
+            average_cost: if amount > AMOUNT_TOLERANCE {
+                cost / amount
+            } else {
+                0.0
+            },
+            lots: if amount > AMOUNT_TOLERANCE {
+                vec![Lot {
+                    id: SECTION_104_LOT.to_string(),
+                    coin: coin.to_string(),
+                    acquired_at: 0,
+                    amount,
+                    unit_cost: cost / amount,
+                }]
+            } else {
+                vec![]
+            },
+        })
+        .collect();
+
+    CostBasis {
+        method: CostBasisMethod::Section104,
+        coins,
+        matches,
+        pool,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::transaction;
+    use crate::TransactionKind;
+
+    /// Three buys at different prices then a sell of 1.5
+    fn log() -> Vec<Transaction> {
+        vec![
+            transaction("a", "ETHAUD", TransactionKind::Buy, 1.0, 1000.0, 1),
+            transaction("b", "ETHAUD", TransactionKind::Buy, 1.0, 3000.0, 2),
+            transaction("c", "ETHAUD", TransactionKind::Buy, 1.0, 2000.0, 3),
+            transaction("d", "ETHAUD", TransactionKind::Sell, 1.5, 4000.0, 4),
+        ]
+    }
+
+    fn matched(basis: &CostBasis) -> Vec<(&str, f64, f64)> {
+        basis
+            .matches
+            .iter()
+            .map(|m| (m.lot_id.as_str(), m.amount, m.cost))
+            .collect()
+    }
+
+    fn open(basis: &CostBasis, coin: &str) -> Vec<(String, f64)> {
+        basis
+            .coins
+            .iter()
+            .find(|c| c.coin == coin)
+            .expect("coin should have a basis")
+            .lots
+            .iter()
+            .map(|lot| (lot.id.clone(), lot.amount))
+            .collect()
+    }
+
+    #[test]
+    fn fifo_matches_oldest_first() {
+        let basis = cost_basis(&log(), CostBasisMethod::Fifo);
+
+        assert_eq!(
+            matched(&basis),
+            vec![("a", 1.0, 1000.0), ("b", 0.5, 1500.0)]
+        );
+        assert_eq!(
+            open(&basis, "ETHAUD"),
+            vec![("b".to_string(), 0.5), ("c".to_string(), 1.0)]
+        );
+        assert_eq!(basis.coins[0].cost, 3500.0);
+    }
+
+    #[test]
+    fn lifo_matches_newest_first() {
+        let basis = cost_basis(&log(), CostBasisMethod::Lifo);
+
+        assert_eq!(
+            matched(&basis),
+            vec![("c", 1.0, 2000.0), ("b", 0.5, 1500.0)]
+        );
+        assert_eq!(
+            open(&basis, "ETHAUD"),
+            vec![("a".to_string(), 1.0), ("b".to_string(), 0.5)]
+        );
+    }
+
+    #[test]
+    fn hifo_matches_most_expensive_first() {
+        let basis = cost_basis(&log(), CostBasisMethod::Hifo);
+
+        assert_eq!(
+            matched(&basis),
+            vec![("b", 1.0, 3000.0), ("c", 0.5, 1000.0)]
+        );
+        assert_eq!(
+            open(&basis, "ETHAUD"),
+            vec![("a".to_string(), 1.0), ("c".to_string(), 0.5)]
+        );
+    }
+
+    #[test]
+    fn hifo_uses_oldest_lot_when_costs_are_equal() {
+        let log = vec![
+            transaction("a", "ETHAUD", TransactionKind::Buy, 1.0, 1000.0, 1),
+            transaction("b", "ETHAUD", TransactionKind::Buy, 1.0, 1000.0, 2),
+            transaction("c", "ETHAUD", TransactionKind::Sell, 1.0, 1000.0, 3),
+        ];
+
+        let basis = cost_basis(&log, CostBasisMethod::Hifo);
+
+        assert_eq!(matched(&basis), vec![("a", 1.0, 1000.0)]);
+    }
+
+    #[test]
+    fn average_cost_reduces_every_lot() {
+        let basis = cost_basis(&log(), CostBasisMethod::AverageCost);
+
+        let cost: f64 = basis.matches.iter().map(|m| m.cost).sum();
+        assert!((cost - 3000.0).abs() < 1e-9);
+        assert_eq!(basis.matches.len(), 3);
+        assert_eq!(
+            open(&basis, "ETHAUD"),
+            vec![
+                ("a".to_string(), 0.5),
+                ("b".to_string(), 0.5),
+                ("c".to_string(), 0.5)
+            ]
+        );
+        assert!((basis.coins[0].average_cost - 2000.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn specific_lot_uses_chosen_lots_then_fifo() {
+        let mut log = log();
+        log[3].lots = vec![LotSelection {
+            id: "c".to_string(),
+            amount: 1.0,
+        }];
+
+        let basis = cost_basis(&log, CostBasisMethod::SpecificLot);
+        assert_eq!(matched(&basis), vec![("c", 1.0, 2000.0), ("a", 0.5, 500.0)]);
+
+        // Chosen lots are ignored by every other method
+        let basis = cost_basis(&log, CostBasisMethod::Fifo);
+        assert_eq!(
+            matched(&basis),
+            vec![("a", 1.0, 1000.0), ("b", 0.5, 1500.0)]
+        );
+    }
+
+    #[test]
+    fn specific_lot_skips_closed_lots() {
+        let mut log = log();
+        let mut second_sell = transaction("e", "ETHAUD", TransactionKind::Sell, 1.0, 4000.0, 5);
+        second_sell.lots = vec![LotSelection {
+            id: "a".to_string(),
+            amount: 1.0,
+        }];
+        log.push(second_sell);
+
+        let basis = cost_basis(&log, CostBasisMethod::SpecificLot);
+
+        // The first sell used all of `a` first in first out, so the second continues from `b`
+        assert_eq!(
+            matched(&basis),
+            vec![
+                ("a", 1.0, 1000.0),
+                ("b", 0.5, 1500.0),
+                ("b", 0.5, 1500.0),
+                ("c", 0.5, 1000.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn proceeds_are_shared_between_lots() {
+        let basis = cost_basis(&log(), CostBasisMethod::Fifo);
+
+        let proceeds: Vec<f64> = basis.matches.iter().map(|m| m.proceeds).collect();
+        assert_eq!(proceeds, vec![4000.0, 2000.0]);
+        assert_eq!(basis.matches[0].gain(), 3000.0);
+        assert_eq!(basis.matches[1].gain(), 500.0);
+        assert!(basis.matches.iter().all(|m| m.disposal_id == "d"));
+    }
+
+    #[test]
+    fn fees_add_to_cost_and_reduce_proceeds() {
+        let mut buy = transaction("a", "ETHAUD", TransactionKind::Buy, 2.0, 1000.0, 1);
+        buy.fee = 10.0;
+        let mut sell = transaction("b", "ETHAUD", TransactionKind::Sell, 2.0, 1500.0, 2);
+        sell.fee = 20.0;
+
+        let basis = cost_basis(&[buy, sell], CostBasisMethod::Fifo);
+
+        assert_eq!(basis.matches[0].cost, 2010.0);
+        assert_eq!(basis.matches[0].proceeds, 2980.0);
+    }
+
+    #[test]
+    fn fee_in_another_coin_is_a_disposal() {
+        let mut bnb = transaction("a", "ETHAUD", TransactionKind::Buy, 1.0, 400.0, 1);
+        bnb.coin = "BNBAUD".to_string();
+        let mut buy = transaction("b", "ETHAUD", TransactionKind::Buy, 1.0, 3000.0, 2);
+        buy.fee = 0.01;
+        buy.fee_asset = "BNBAUD".to_string();
+        buy.fee_price = 500.0;
+
+        let basis = cost_basis(&[bnb, buy], CostBasisMethod::Fifo);
+
+        assert_eq!(basis.matches.len(), 1);
+        let fee = &basis.matches[0];
+        assert_eq!(fee.coin, "BNBAUD");
+        assert_eq!(fee.disposal_id, "b");
+        assert_eq!((fee.amount, fee.cost, fee.proceeds), (0.01, 4.0, 5.0));
+        assert_eq!(open(&basis, "BNBAUD"), vec![("a".to_string(), 0.99)]);
+        assert_eq!(basis.coins[1].lots[0].unit_cost, 3005.0);
+    }
+
+    #[test]
+    fn income_and_transfers() {
+        let log = vec![
+            transaction("a", "ETHAUD", TransactionKind::Deposit, 1.0, 800.0, 1),
+            transaction("b", "ETHAUD", TransactionKind::Staking, 0.5, 2000.0, 2),
+            transaction("c", "ETHAUD", TransactionKind::Withdrawal, 1.0, 0.0, 3),
+            transaction("d", "ETHAUD", TransactionKind::Gift, 0.25, 2400.0, 4),
+        ];
+
+        let basis = cost_basis(&log, CostBasisMethod::Fifo);
+
+        // The withdrawal takes the deposit without a match, the gift is a disposal
+        assert_eq!(matched(&basis), vec![("b", 0.25, 500.0)]);
+        assert_eq!(basis.matches[0].proceeds, 600.0);
+        assert_eq!(open(&basis, "ETHAUD"), vec![("b".to_string(), 0.25)]);
+    }
+
+    #[test]
+    fn disposing_more_than_held_has_no_cost() {
+        let log = vec![
+            transaction("a", "ETHAUD", TransactionKind::Buy, 1.0, 1000.0, 1),
+            transaction("b", "ETHAUD", TransactionKind::Sell, 3.0, 2000.0, 2),
+        ];
+
+        let basis = cost_basis(&log, CostBasisMethod::Fifo);
+
+        assert_eq!(matched(&basis), vec![("a", 1.0, 1000.0), ("", 2.0, 0.0)]);
+        assert!(open(&basis, "ETHAUD").is_empty());
+        assert_eq!(basis.coins[0].average_cost, 0.0);
+    }
+
+    #[test]
+    fn closes_lots_sold_in_parts() {
+        let log = vec![
+            transaction("a", "ETHAUD", TransactionKind::Buy, 0.3, 1000.0, 1),
+            transaction("b", "ETHAUD", TransactionKind::Sell, 0.1, 1000.0, 2),
+            transaction("c", "ETHAUD", TransactionKind::Sell, 0.1, 1000.0, 3),
+            transaction("d", "ETHAUD", TransactionKind::Sell, 0.1, 1000.0, 4),
+        ];
+
+        for method in CostBasisMethod::ALL {
+            let basis = cost_basis(&log, method);
+            assert!(open(&basis, "ETHAUD").is_empty());
+            assert!(basis.matches.iter().all(|m| !m.lot_id.is_empty()));
+        }
+    }
+
+    #[test]
+    fn matching_is_deterministic() {
+        let mut log = log();
+        let mut ada = transaction("e", "ETHAUD", TransactionKind::Buy, 100.0, 1.0, 5);
+        ada.coin = "ADAAUD".to_string();
+        log.push(ada);
+
+        for method in CostBasisMethod::ALL {
+            let basis = cost_basis(&log, method);
+            assert_eq!(basis, cost_basis(&log, method));
+            assert_eq!(basis.method, method);
+            let coins: Vec<&str> = basis.coins.iter().map(|c| c.coin.as_str()).collect();
+            assert_eq!(coins, vec!["ADAAUD", "ETHAUD"]);
+        }
+    }
+
+    const DAY: i64 = 24 * 60 * 60;
+
+    fn rules(basis: &CostBasis) -> Vec<(&str, f64, f64, Option<MatchRule>)> {
+        basis
+            .matches
+            .iter()
+            .map(|m| (m.lot_id.as_str(), m.amount, m.cost, m.rule))
+            .collect()
+    }
+
+    #[test]
+    fn section_104_matches_same_day_first() {
+        let log = vec![
+            transaction("a", "ETHAUD", TransactionKind::Buy, 1.0, 1000.0, DAY),
+            transaction("b", "ETHAUD", TransactionKind::Buy, 1.0, 2000.0, 10 * DAY),
+            transaction(
+                "c",
+                "ETHAUD",
+                TransactionKind::Sell,
+                1.5,
+                3000.0,
+                10 * DAY + 60,
+            ),
+            transaction(
+                "d",
+                "ETHAUD",
+                TransactionKind::Buy,
+                1.0,
+                4000.0,
+                10 * DAY + 120,
+            ),
+        ];
+
+        let basis = cost_basis(&log, CostBasisMethod::Section104);
+
+        // Buys on the same day are one acquisition at their average cost of 3000
+        assert_eq!(
+            rules(&basis),
+            vec![
+                ("b", 1.0, 3000.0, Some(MatchRule::SameDay)),
+                ("d", 0.5, 1500.0, Some(MatchRule::SameDay)),
+            ]
+        );
+        assert_eq!(basis.matches[0].proceeds, 3000.0);
+        assert_eq!(basis.coins[0].amount, 1.5);
+        assert_eq!(basis.coins[0].cost, 2500.0);
+    }
+
+    #[test]
+    fn section_104_matches_the_next_30_days() {
+        let log = vec![
+            transaction("a", "ETHAUD", TransactionKind::Buy, 2.0, 1000.0, DAY),
+            transaction("b", "ETHAUD", TransactionKind::Sell, 1.0, 3000.0, 10 * DAY),
+            transaction("c", "ETHAUD", TransactionKind::Buy, 0.5, 2000.0, 20 * DAY),
+            transaction("d", "ETHAUD", TransactionKind::Buy, 0.5, 2500.0, 40 * DAY),
+            transaction("e", "ETHAUD", TransactionKind::Buy, 1.0, 4000.0, 41 * DAY),
+        ];
+
+        let basis = cost_basis(&log, CostBasisMethod::Section104);
+
+        // The buy 31 days later goes to the pool
+        assert_eq!(
+            rules(&basis),
+            vec![
+                ("c", 0.5, 1000.0, Some(MatchRule::BedAndBreakfast)),
+                ("d", 0.5, 1250.0, Some(MatchRule::BedAndBreakfast)),
+            ]
+        );
+        assert_eq!(basis.coins[0].amount, 3.0);
+        assert_eq!(basis.coins[0].cost, 6000.0);
+    }
+
+    #[test]
+    fn section_104_pools_at_average_cost() {
+        let log = vec![
+            transaction("a", "ETHAUD", TransactionKind::Buy, 1.0, 1000.0, DAY),
+            transaction("b", "ETHAUD", TransactionKind::Buy, 1.0, 3000.0, 2 * DAY),
+            transaction("c", "ETHAUD", TransactionKind::Sell, 0.5, 4000.0, 3 * DAY),
+            transaction(
+                "d",
+                "ETHAUD",
+                TransactionKind::Withdrawal,
+                0.5,
+                0.0,
+                4 * DAY,
+            ),
+        ];
+
+        let basis = cost_basis(&log, CostBasisMethod::Section104);
+
+        assert_eq!(
+            rules(&basis),
+            vec![(SECTION_104_LOT, 0.5, 1000.0, Some(MatchRule::Section104))]
+        );
+        let pool: Vec<(&str, f64, f64, f64)> = basis
+            .pool
+            .iter()
+            .map(|e| (e.id.as_str(), e.amount, e.pool_amount, e.pool_cost))
+            .collect();
+        assert_eq!(
+            pool,
+            vec![
+                ("a", 1.0, 1.0, 1000.0),
+                ("b", 1.0, 2.0, 4000.0),
+                ("c", -0.5, 1.5, 3000.0),
+                ("d", -0.5, 1.0, 2000.0),
+            ]
+        );
+        assert_eq!(basis.coins[0].lots[0].id, SECTION_104_LOT);
+        assert_eq!(basis.coins[0].lots[0].unit_cost, 2000.0);
+    }
+
+    #[test]
+    fn section_104_disposing_more_than_held_has_no_cost() {
+        let log = vec![
+            transaction("a", "ETHAUD", TransactionKind::Buy, 1.0, 1000.0, DAY),
+            transaction("b", "ETHAUD", TransactionKind::Sell, 3.0, 2000.0, 2 * DAY),
+        ];
+
+        let basis = cost_basis(&log, CostBasisMethod::Section104);
+
+        assert_eq!(
+            rules(&basis),
+            vec![
+                (SECTION_104_LOT, 1.0, 1000.0, Some(MatchRule::Section104)),
+                ("", 2.0, 0.0, None),
+            ]
+        );
+        assert!(basis.coins[0].lots.is_empty());
+    }
+}
@@ -0,0 +1,107 @@
+//! Deletes a coin and its price history. A coin that any user has transactions for can't be
+//! deleted, as their holdings would disappear, it should be disabled through `coins_put` instead
+
+use aws_sdk_dynamodb::Client;
+use lambda_http::{service_fn, IntoResponse, Request, RequestExt};
+use tracing::Level;
+use tracing_subscriber::FmtSubscriber;
+
+use holdcrypt::{store, Error, Res};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let subscriber = FmtSubscriber::builder()
+        .with_max_level(Level::INFO)
+        .finish();
+
+    tracing::subscriber::set_global_default(subscriber)?;
+
+    lambda_http::run(service_fn(lambda)).await?;
+    Ok(())
+}
+
+async fn lambda(event: Request) -> Result<impl IntoResponse, Error> {
+    let params = event.path_parameters();
+    let symbol = match params.first("symbol") {
+        Some(v) if !v.is_empty() => v,
+        _ => return Ok(Res::bad_request("must include a symbol in the path")),
+    };
+
+    let config = aws_config::load_from_env().await;
+    let client = Client::new(&config);
+
+    match store::get_coin(&client, symbol).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return Ok(Res::not_found(&format!("coin {} doesn't exist", symbol))),
+        Err(err) => {
+            return Ok(Res::internal_server_error(
+                "failed to get coin from dynamodb",
+                err,
+            ))
+        }
+    }
+
+    let holders = match store::users_holding(&client, symbol).await {
+        Ok(v) => v,
+        Err(err) => {
+            return Ok(Res::internal_server_error(
+                "failed to scan users for transactions",
+                err,
+            ))
+        }
+    };
+    if !holders.is_empty() {
+        return Ok(Res::conflict(&format!(
+            "{} users have transactions for {}, disable it instead",
+            holders.len(),
+            symbol
+        )));
+    }
+
+    if let Err(err) = store::delete_price_history(&client, symbol).await {
+        return Ok(Res::internal_server_error(
+            "failed to delete price history from dynamodb",
+            err,
+        ));
+    }
+
+    if let Err(err) = store::delete_coin(&client, symbol).await {
+        return Ok(Res::internal_server_error(
+            "failed to delete coin from dynamodb",
+            err,
+        ));
+    }
+
+    Ok(Res::ok("successfully deleted coin"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn fail_to_delete_missing_coin() {
+        let request = Request::default().with_path_parameters(HashMap::from([(
+            "symbol".to_string(),
+            vec!["NOTACOINAUD".to_string()],
+        )]));
+
+        let response = lambda(request)
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        assert_eq!(response.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn fail_to_delete_without_symbol() {
+        let response = lambda(Request::default())
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        assert_eq!(response.status(), 400);
+    }
+}
@@ -1,12 +1,13 @@
-//! Gets all available coins and prices from dynamodb
+//! Gets all available coins and prices from dynamodb, along with their 24 hour stats
+//! and display metadata
 
 use aws_sdk_dynamodb::{output::ScanOutput, Client};
 use lambda_http::{service_fn, IntoResponse, Request};
-use std::{collections::HashMap, io::ErrorKind};
+use std::collections::HashMap;
 use tracing::Level;
 use tracing_subscriber::FmtSubscriber;
 
-use holdcrypt::{CoinPrice, Error, Res};
+use holdcrypt::{store, CoinPrice, Error, Res};
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
@@ -62,23 +63,28 @@ fn get_items(items: ScanOutput) -> Result<HashMap<String, CoinPrice>, Error> {
             .get("name")
             .ok_or("name key doesn't exist")?
             .as_s()
-            .map_err(|_| std::io::Error::new(ErrorKind::Other, "name type incorrect"))?;
+            .map_err(|_| std::io::Error::other("name type incorrect"))?;
         let symbol = map
             .get("symbol")
             .ok_or("symbol key doesn't exist")?
             .as_s()
-            .map_err(|_| std::io::Error::new(ErrorKind::Other, "symbol type incorrect"))?;
-        let price = map
-            .get("price")
-            .ok_or("price key doesn't exist")?
+            .map_err(|_| std::io::Error::other("symbol type incorrect"))?;
+        // Coins that were disabled before they were ever priced have nothing to show
+        let price = match map.get("price") {
+            Some(v) => v,
+            None => continue,
+        };
+        let price = price
             .as_n()
-            .map_err(|_| std::io::Error::new(ErrorKind::Other, "price type incorrect"))?
+            .map_err(|_| std::io::Error::other("price type incorrect"))?
             .parse::<f64>()?;
         price_map.insert(
             symbol.clone(),
             CoinPrice {
                 name: name.clone(),
                 price,
+                stats: store::coin_stats(map)?,
+                metadata: store::coin_metadata(map)?,
             },
         );
     }
@@ -107,6 +113,7 @@ mod tests {
             assert!(!key.is_empty());
             assert!(!value.name.is_empty());
             assert!(value.price > 0.0);
+            assert!(value.stats.high_price >= value.stats.low_price);
         }
 
         assert_eq!(response.status(), 200);
@@ -1,17 +1,27 @@
 //! Queries the market for each coin passed into the body and retrieves
 //! the most recent 50 asks, and takes the average of those to determine
-//! the price
+//! the price. The rolling 24 hour ticker statistics are also retrieved and
+//! stored alongside the price.
+//!
+//! Before a price is written it's checked by the `PriceGuard`, suspicious prices are
+//! quarantined on the coin and reported in the response instead of being written.
+//! Display metadata is replaced if it's included in the body, otherwise it's left as is.
+//! Disabled coins are skipped so delisted pairs don't fail the refresh, only their metadata
+//! is saved. A coin that has never been priced is saved with just its name and metadata
 
-use aws_sdk_dynamodb::model::AttributeValue;
 use aws_sdk_dynamodb::Client;
 use lambda_http::{service_fn, Body, IntoResponse, Request};
-use std::collections::HashMap;
-use tracing::Level;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{info, warn, Level};
 use tracing_subscriber::FmtSubscriber;
 
-use holdcrypt::{BinancePrices, CoinPrice, CoinsPutRequest, Error, Res};
+use holdcrypt::{
+    store, BinancePrices, BinanceTicker24h, CoinMetadata, CoinPrice, CoinPutRequest,
+    CoinsPutRequest, CoinsPutResponse, Error, PriceGuard, QuarantinedPrice, Res, Verdict,
+};
 
 const BINANCE_PRICES_URL: &str = "https://api.binance.com/api/v3/depth";
+const BINANCE_TICKER_URL: &str = "https://api.binance.com/api/v3/ticker/24hr";
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
@@ -26,7 +36,8 @@ async fn main() -> Result<(), Error> {
 }
 
 async fn lambda(event: Request) -> Result<impl IntoResponse, Error> {
-    let mut price_map: HashMap<String, CoinPrice> = HashMap::new();
+    let mut response = CoinsPutResponse::default();
+    let guard = PriceGuard::from_env();
 
     let body: CoinsPutRequest = match event.body() {
         Body::Text(text) => match serde_json::from_str(text) {
@@ -42,6 +53,33 @@ async fn lambda(event: Request) -> Result<impl IntoResponse, Error> {
         let client = reqwest::Client::new();
         let config = aws_config::load_from_env().await;
         let dynamo_client = Client::new(&config);
+
+        let stored = match stored_coin(&dynamo_client, &coin.symbol).await {
+            Ok(v) => v,
+            Err(err) => {
+                return Ok(Res::internal_server_error(
+                    "failed to get coin from dynamodb",
+                    err,
+                ))
+            }
+        };
+
+        let active = coin
+            .metadata
+            .as_ref()
+            .map_or(stored.metadata.active, |metadata| metadata.active);
+        if !active {
+            if let Err(err) = save_metadata(&dynamo_client, &coin, stored.price.is_some()).await {
+                return Ok(Res::internal_server_error(
+                    "failed to save coin metadata to dynamodb",
+                    err,
+                ));
+            }
+            info!("skipping refresh of disabled coin {}", coin.symbol);
+            response.disabled.push(coin.symbol);
+            continue;
+        }
+
         let mut res = match client.get(BINANCE_PRICES_URL).query(&params).send() {
             Ok(res) => res,
             Err(err) => {
@@ -70,29 +108,132 @@ async fn lambda(event: Request) -> Result<impl IntoResponse, Error> {
                 .expect("failed to parse price as f64");
         }
         let average = sum / total;
-        price_map.insert(
-            coin.symbol.clone(),
-            CoinPrice {
-                price: average,
-                name: coin.name.clone(),
-            },
-        );
-        let request = dynamo_client
-            .put_item()
-            .table_name("coin")
-            .item("name", AttributeValue::S(coin.name))
-            .item("symbol", AttributeValue::S(coin.symbol))
-            .item("price", AttributeValue::N(average.to_string()));
-
-        if let Err(err) = request.send().await {
+
+        let mut res = match client
+            .get(BINANCE_TICKER_URL)
+            .query(&[("symbol", &coin.symbol)])
+            .send()
+        {
+            Ok(res) => res,
+            Err(err) => {
+                return Ok(Res::internal_server_error(
+                    "failed to get 24 hour ticker from Binance",
+                    Box::new(err),
+                ))
+            }
+        };
+
+        let ticker: BinanceTicker24h = match res.json() {
+            Ok(res) => res,
+            Err(err) => {
+                return Ok(Res::parse_response_error(
+                    "failed to parse 24 hour ticker from Binance",
+                    err,
+                ))
+            }
+        };
+
+        let stats = match ticker.stats() {
+            Ok(v) => v,
+            Err(err) => {
+                return Ok(Res::internal_server_error(
+                    "failed to parse 24 hour ticker numbers from Binance",
+                    Box::new(err),
+                ))
+            }
+        };
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let history = match store::recent_prices(&dynamo_client, &coin.symbol, guard.window).await {
+            Ok(v) => v,
+            Err(err) => {
+                return Ok(Res::internal_server_error(
+                    "failed to get price history from dynamodb",
+                    err,
+                ))
+            }
+        };
+
+        match guard.check(average, stored.price, &history, stored.quarantined) {
+            Verdict::Quarantine(reason) => {
+                warn!("quarantined {} price {}: {}", coin.symbol, average, reason);
+                // A brand new coin has nothing to hold the quarantined price on
+                if stored.price.is_some() {
+                    if let Err(err) =
+                        store::quarantine_price(&dynamo_client, &coin.symbol, average, timestamp)
+                            .await
+                    {
+                        return Ok(Res::internal_server_error(
+                            "failed to quarantine price in dynamodb",
+                            err,
+                        ));
+                    }
+                }
+                if let Err(err) = save_metadata(&dynamo_client, &coin, stored.price.is_some()).await
+                {
+                    return Ok(Res::internal_server_error(
+                        "failed to save coin metadata to dynamodb",
+                        err,
+                    ));
+                }
+                response.quarantined.push(QuarantinedPrice {
+                    name: coin.name,
+                    symbol: coin.symbol,
+                    price: average,
+                    previous_price: stored.price,
+                    reason,
+                });
+                continue;
+            }
+            Verdict::Confirmed => info!("{} quarantined price confirmed", coin.symbol),
+            Verdict::Accept => {}
+        }
+
+        if let Err(err) =
+            store::save_coin_price(&dynamo_client, &coin.name, &coin.symbol, average, &stats).await
+        {
             return Ok(Res::internal_server_error(
                 "failed to add coin to dynamodb",
-                Box::new(err),
+                err,
             ));
         }
+
+        if let Err(err) =
+            store::record_price(&dynamo_client, &coin.symbol, timestamp, average).await
+        {
+            return Ok(Res::internal_server_error(
+                "failed to add price to history",
+                err,
+            ));
+        }
+
+        let metadata = match coin.metadata {
+            Some(metadata) => {
+                if let Err(err) =
+                    store::save_coin_metadata(&dynamo_client, &coin.symbol, &metadata).await
+                {
+                    return Ok(Res::internal_server_error(
+                        "failed to save coin metadata to dynamodb",
+                        err,
+                    ));
+                }
+                metadata
+            }
+            None => stored.metadata,
+        };
+
+        response.coins.insert(
+            coin.symbol,
+            CoinPrice {
+                price: average,
+                name: coin.name,
+                stats,
+                metadata,
+            },
+        );
     }
 
-    let resp = match serde_json::to_string(&price_map) {
+    let resp = match serde_json::to_string(&response) {
         Ok(v) => v,
         Err(error) => {
             return Ok(Res::internal_server_error(
@@ -104,10 +245,44 @@ async fn lambda(event: Request) -> Result<impl IntoResponse, Error> {
     Ok(Res::ok_body(&resp))
 }
 
+/// Saves the metadata in the body if there is any, a coin that was never priced is created
+/// without a price so its metadata isn't lost
+async fn save_metadata(client: &Client, coin: &CoinPutRequest, priced: bool) -> Result<(), Error> {
+    match &coin.metadata {
+        Some(metadata) if priced => store::save_coin_metadata(client, &coin.symbol, metadata).await,
+        Some(metadata) => {
+            store::save_unpriced_coin(client, &coin.name, &coin.symbol, metadata).await
+        }
+        None => Ok(()),
+    }
+}
+
+/// What is currently stored for a coin, a new coin has no price and default metadata
+struct Stored {
+    price: Option<f64>,
+    quarantined: Option<f64>,
+    metadata: CoinMetadata,
+}
+
+async fn stored_coin(client: &Client, symbol: &str) -> Result<Stored, Error> {
+    Ok(match store::get_coin(client, symbol).await? {
+        Some(item) => Stored {
+            price: store::get_n_opt(&item, "price")?,
+            quarantined: store::get_n_opt(&item, "quarantined_price")?,
+            metadata: store::coin_metadata(&item)?,
+        },
+        None => Stored {
+            price: None,
+            quarantined: None,
+            metadata: CoinMetadata::default(),
+        },
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use holdcrypt::CoinPutRequest;
+    use holdcrypt::CoinCategory;
     use lambda_http::Body;
 
     #[tokio::test]
@@ -116,6 +291,11 @@ mod tests {
             coins: vec![CoinPutRequest {
                 name: "Ethereum".to_string(),
                 symbol: "ETHAUD".to_string(),
+                metadata: Some(CoinMetadata {
+                    decimals: 18,
+                    category: CoinCategory::Layer1,
+                    ..Default::default()
+                }),
             }],
         };
 
@@ -128,17 +308,19 @@ mod tests {
             .expect("failed to run lambda")
             .into_response();
 
-        let coins: HashMap<String, CoinPrice> = match response.body() {
+        let body: CoinsPutResponse = match response.body() {
             Body::Text(v) => serde_json::from_str(v).expect("failed to parse response body"),
             _ => panic!("response body not text"),
         };
 
-        dbg!(&coins);
+        dbg!(&body);
 
-        for (key, value) in coins.iter() {
+        for (key, value) in body.coins.iter() {
             assert!(!key.is_empty());
             assert!(!value.name.is_empty());
             assert!(value.price > 0.0);
+            assert!(value.stats.high_price >= value.stats.low_price);
+            assert_eq!(value.metadata.decimals, 18);
         }
 
         assert_eq!(response.status(), 200);
@@ -150,6 +332,7 @@ mod tests {
             coins: vec![CoinPutRequest {
                 name: "".to_string(),
                 symbol: "".to_string(),
+                metadata: None,
             }],
         };
 
@@ -164,4 +347,48 @@ mod tests {
 
         assert_eq!(response.status(), 500);
     }
+
+    #[tokio::test]
+    async fn skips_disabled_coin() {
+        let body = CoinsPutRequest {
+            coins: vec![CoinPutRequest {
+                name: "Delisted".to_string(),
+                symbol: "DELISTEDAUD".to_string(),
+                metadata: Some(CoinMetadata {
+                    active: false,
+                    ..Default::default()
+                }),
+            }],
+        };
+
+        let body = serde_json::to_string(&body).expect("failed to serialize to json string");
+
+        let request = Request::new(Body::Text(body));
+
+        let response = lambda(request)
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        let body: CoinsPutResponse = match response.body() {
+            Body::Text(v) => serde_json::from_str(v).expect("failed to parse response body"),
+            _ => panic!("response body not text"),
+        };
+
+        assert!(body.coins.is_empty());
+        assert_eq!(body.disabled, vec!["DELISTEDAUD".to_string()]);
+        assert_eq!(response.status(), 200);
+
+        // The coin was never priced but its metadata is still saved
+        let config = aws_config::load_from_env().await;
+        let item = store::get_coin(&Client::new(&config), "DELISTEDAUD")
+            .await
+            .expect("failed to get coin")
+            .expect("coin wasn't saved");
+        assert!(
+            !store::coin_metadata(&item)
+                .expect("failed to parse metadata")
+                .active
+        );
+    }
 }
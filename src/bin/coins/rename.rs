@@ -0,0 +1,172 @@
+//! Moves a coin to a new symbol e.g. when a pair moves from `ETHAUD` to `ETHUSDT`. The coin is
+//! copied to the new symbol first, then the price history and every user's transactions are
+//! pointed at it, and the old symbol is only removed once everything has moved. The copy
+//! remembers the symbol it was renamed from, so if the rename fails part way it can be run
+//! again and resumes, and running it again after it finished changes nothing
+
+use aws_sdk_dynamodb::Client;
+use lambda_http::{service_fn, Body, IntoResponse, Request, RequestExt};
+use tracing::Level;
+use tracing_subscriber::FmtSubscriber;
+
+use holdcrypt::{store, CoinRenameRequest, Error, Res};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let subscriber = FmtSubscriber::builder()
+        .with_max_level(Level::INFO)
+        .finish();
+
+    tracing::subscriber::set_global_default(subscriber)?;
+
+    lambda_http::run(service_fn(lambda)).await?;
+    Ok(())
+}
+
+async fn lambda(event: Request) -> Result<impl IntoResponse, Error> {
+    let params = event.path_parameters();
+    let from = match params.first("symbol") {
+        Some(v) if !v.is_empty() => v,
+        _ => return Ok(Res::bad_request("must include a symbol in the path")),
+    };
+
+    let body: CoinRenameRequest = match event.body() {
+        Body::Text(text) => match serde_json::from_str(text) {
+            Ok(js) => js,
+            Err(err) => return Ok(Res::parse_body_error(err)),
+        },
+        Body::Empty => return Ok(Res::bad_request("no body provided")),
+        Body::Binary(_) => return Ok(Res::bad_request("binary body not supported")),
+    };
+
+    if body.symbol.is_empty() || body.symbol == from {
+        return Ok(Res::bad_request(
+            "symbol must be provided and different to the current symbol",
+        ));
+    }
+
+    let config = aws_config::load_from_env().await;
+    let client = Client::new(&config);
+
+    let existing = match store::get_coin(&client, &body.symbol).await {
+        Ok(v) => v,
+        Err(err) => {
+            return Ok(Res::internal_server_error(
+                "failed to get coin from dynamodb",
+                err,
+            ))
+        }
+    };
+
+    // A coin at the new symbol that was copied from this one means this rename already ran
+    let resuming = match &existing {
+        Some(item) => match store::get_s_or_default(item, "renamed_from") {
+            Ok(v) => v == from,
+            Err(err) => {
+                return Ok(Res::internal_server_error(
+                    "failed to parse coin from dynamodb",
+                    err,
+                ))
+            }
+        },
+        None => false,
+    };
+
+    match store::get_coin(&client, from).await {
+        Ok(Some(_)) if resuming => {}
+        Ok(Some(item)) => {
+            if existing.is_some() {
+                return Ok(Res::conflict(&format!(
+                    "coin {} already exists",
+                    body.symbol
+                )));
+            }
+            if let Err(err) =
+                store::copy_coin(&client, &item, &body.symbol, body.name.as_deref()).await
+            {
+                return Ok(Res::internal_server_error(
+                    "failed to copy coin to new symbol",
+                    err,
+                ));
+            }
+        }
+        Ok(None) if resuming => {}
+        Ok(None) => return Ok(Res::not_found(&format!("coin {} doesn't exist", from))),
+        Err(err) => {
+            return Ok(Res::internal_server_error(
+                "failed to get coin from dynamodb",
+                err,
+            ))
+        }
+    }
+
+    let prices = match store::move_price_history(&client, from, &body.symbol).await {
+        Ok(v) => v,
+        Err(err) => {
+            return Ok(Res::internal_server_error(
+                "failed to move price history to new symbol",
+                err,
+            ))
+        }
+    };
+
+    let users = match store::rename_transaction_coin(&client, from, &body.symbol).await {
+        Ok(v) => v,
+        Err(err) => {
+            return Ok(Res::internal_server_error(
+                "failed to move transactions to new symbol",
+                err,
+            ))
+        }
+    };
+
+    if let Err(err) = store::delete_coin(&client, from).await {
+        return Ok(Res::internal_server_error(
+            "failed to delete old coin from dynamodb",
+            err,
+        ));
+    }
+
+    Ok(Res::ok(&format!(
+        "renamed {} to {}, moved {} prices and transactions for {} users",
+        from, body.symbol, prices, users
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn request(from: &str, to: &str) -> Request {
+        let body = CoinRenameRequest {
+            symbol: to.to_string(),
+            name: None,
+        };
+        let body = serde_json::to_string(&body).expect("failed to serialize to json string");
+        Request::new(Body::Text(body)).with_path_parameters(HashMap::from([(
+            "symbol".to_string(),
+            vec![from.to_string()],
+        )]))
+    }
+
+    #[tokio::test]
+    async fn fail_to_rename_missing_coin() {
+        let response = lambda(request("NOTACOINAUD", "NOTACOINUSDT"))
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        assert_eq!(response.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn fail_to_rename_to_same_symbol() {
+        let response = lambda(request("ETHAUD", "ETHAUD"))
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        assert_eq!(response.status(), 400);
+    }
+}
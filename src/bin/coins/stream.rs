@@ -0,0 +1,438 @@
+//! Long running ingestion that subscribes to the Binance combined streams for every active coin
+//! in the `coin` table, and writes throttled price updates through the same store as `coins_put`.
+//! The best ask from `bookTicker` is used as the price and `miniTicker` provides the 24 hour
+//! stats. Binance drops connections after 24 hours so the connection is recycled before then,
+//! any other disconnect is retried with an exponential backoff. This isn't a lambda so it's not
+//! part of `template.yaml`, it's optional and needs to run somewhere long lived.
+//!
+//! Every update is checked by the `PriceGuard` and recorded in the price history exactly like a
+//! refresh from `coins_put`. The active coins are reloaded periodically, the stream reconnects
+//! with the new subscriptions if they changed.
+//!
+//! Configured with environment variables, as well as the `PriceGuard` ones:
+//! - `BINANCE_STREAM_URL` base url of the stream endpoint
+//! - `INGEST_THROTTLE_SECS` minimum seconds between writes for a coin
+//! - `INGEST_RELOAD_SECS` seconds between reloads of the active coins
+
+use aws_sdk_dynamodb::Client;
+use futures_util::{SinkExt, StreamExt};
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{sync::mpsc, time};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tracing::{error, info, warn, Level};
+use tracing_subscriber::FmtSubscriber;
+
+use holdcrypt::{
+    store, BinanceStreamData, BinanceStreamEvent, CoinStats, Error, PriceGuard, Verdict,
+};
+
+const BINANCE_STREAM_URL: &str = "wss://stream.binance.com:9443";
+const DEFAULT_THROTTLE_SECS: u64 = 10;
+const DEFAULT_RELOAD_SECS: u64 = 300;
+// Binance disconnects after 24 hours, so reconnect before that happens
+const MAX_CONNECTION_AGE: Duration = Duration::from_secs(23 * 60 * 60);
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// The latest price and stats for a coin, sent to be written once per throttle interval
+#[derive(Debug, Clone, PartialEq)]
+struct PriceUpdate {
+    symbol: String,
+    price: f64,
+    stats: CoinStats,
+}
+
+/// Timings for the connection, configurable so tests don't have to wait hours
+struct Settings {
+    url: String,
+    throttle: Duration,
+    max_connection_age: Duration,
+    min_backoff: Duration,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let subscriber = FmtSubscriber::builder()
+        .with_max_level(Level::INFO)
+        .finish();
+
+    tracing::subscriber::set_global_default(subscriber)?;
+
+    let config = aws_config::load_from_env().await;
+    let client = Client::new(&config);
+    let guard = PriceGuard::from_env();
+    let mut coins = store::active_coin_names(&client).await?;
+    if coins.is_empty() {
+        return Err("no coins in table to subscribe to".into());
+    }
+
+    let base_url = env::var("BINANCE_STREAM_URL").unwrap_or_else(|_| BINANCE_STREAM_URL.into());
+    let throttle = Duration::from_secs(env_or("INGEST_THROTTLE_SECS", DEFAULT_THROTTLE_SECS));
+    let settings = |coins: &HashMap<String, String>| Settings {
+        url: stream_url(&base_url, coins.keys()),
+        throttle,
+        max_connection_age: MAX_CONNECTION_AGE,
+        min_backoff: MIN_BACKOFF,
+    };
+
+    let (tx, mut rx) = mpsc::channel(100);
+    let mut stream = tokio::spawn(ingest(settings(&coins), tx.clone()));
+    let mut reload = time::interval(Duration::from_secs(env_or(
+        "INGEST_RELOAD_SECS",
+        DEFAULT_RELOAD_SECS,
+    )));
+    reload.tick().await;
+
+    loop {
+        tokio::select! {
+            Some(update) = rx.recv() => {
+                // Coins disabled since the last reload are still streamed but aren't saved
+                let name = match coins.get(&update.symbol) {
+                    Some(v) => v,
+                    None => continue,
+                };
+                if let Err(err) = save(&client, &guard, name, &update).await {
+                    error!("failed to save {} price: {}", update.symbol, err);
+                }
+            }
+            _ = reload.tick() => match store::active_coin_names(&client).await {
+                Ok(latest) if latest.is_empty() => warn!("no active coins, keeping subscriptions"),
+                Ok(latest) => {
+                    if !same_symbols(&coins, &latest) {
+                        info!("active coins changed, resubscribing to {} coins", latest.len());
+                        stream.abort();
+                        stream = tokio::spawn(ingest(settings(&latest), tx.clone()));
+                    }
+                    coins = latest;
+                }
+                Err(err) => error!("failed to reload active coins: {}", err),
+            },
+        }
+    }
+}
+
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    env::var(key)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+fn same_symbols(coins: &HashMap<String, String>, latest: &HashMap<String, String>) -> bool {
+    coins.len() == latest.len() && coins.keys().all(|symbol| latest.contains_key(symbol))
+}
+
+/// Saves an update the same way `coins_put` saves a refresh, the price is checked by the guard
+/// and either quarantined on the coin or written and recorded in the price history
+async fn save(
+    client: &Client,
+    guard: &PriceGuard,
+    name: &str,
+    update: &PriceUpdate,
+) -> Result<(), Error> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    let (previous, quarantined) = match store::get_coin(client, &update.symbol).await? {
+        Some(item) => (
+            store::get_n_opt(&item, "price")?,
+            store::get_n_opt(&item, "quarantined_price")?,
+        ),
+        None => (None, None),
+    };
+    let history = store::recent_prices(client, &update.symbol, guard.window).await?;
+
+    match guard.check(update.price, previous, &history, quarantined) {
+        Verdict::Quarantine(reason) => {
+            warn!(
+                "quarantined {} price {}: {}",
+                update.symbol, update.price, reason
+            );
+            // A brand new coin has nothing to hold the quarantined price on
+            if previous.is_some() {
+                store::quarantine_price(client, &update.symbol, update.price, timestamp).await?;
+            }
+            return Ok(());
+        }
+        Verdict::Confirmed => info!("{} quarantined price confirmed", update.symbol),
+        Verdict::Accept => {}
+    }
+
+    store::save_coin_price(client, name, &update.symbol, update.price, &update.stats).await?;
+    store::record_price(client, &update.symbol, timestamp, update.price).await?;
+    info!("{} price updated to {}", update.symbol, update.price);
+    Ok(())
+}
+
+/// Combined stream url subscribing to the book and mini tickers for every symbol
+fn stream_url<'a>(base_url: &str, symbols: impl Iterator<Item = &'a String>) -> String {
+    let mut symbols: Vec<String> = symbols.map(|s| s.to_lowercase()).collect();
+    symbols.sort();
+    let streams: Vec<String> = symbols
+        .iter()
+        .map(|s| format!("{s}@bookTicker/{s}@miniTicker"))
+        .collect();
+    format!("{}/stream?streams={}", base_url, streams.join("/"))
+}
+
+/// Keeps a connection open forever, reconnecting when it drops or gets too old.
+/// Only returns once the receiving side of the channel has been closed
+async fn ingest(settings: Settings, tx: mpsc::Sender<PriceUpdate>) {
+    let mut backoff = settings.min_backoff;
+    loop {
+        match connect_async(settings.url.as_str()).await {
+            Ok((socket, _)) => {
+                info!("connected to {}", settings.url);
+                backoff = settings.min_backoff;
+                if let Err(err) = read(socket, &settings, &tx).await {
+                    warn!("stream disconnected: {}", err);
+                }
+            }
+            Err(err) => warn!("failed to connect to stream: {}", err),
+        }
+
+        if tx.is_closed() {
+            return;
+        }
+        time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// Reads messages until the connection closes or reaches the max age, keeping only the latest
+/// values for each coin and sending any that changed once per throttle interval
+async fn read<S>(
+    socket: tokio_tungstenite::WebSocketStream<S>,
+    settings: &Settings,
+    tx: &mpsc::Sender<PriceUpdate>,
+) -> Result<(), Error>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let (mut write, mut read) = socket.split();
+    let mut latest: HashMap<String, PriceUpdate> = HashMap::new();
+    let mut changed: HashSet<String> = HashSet::new();
+    let mut flush = time::interval(settings.throttle);
+    let expired = time::sleep(settings.max_connection_age);
+    tokio::pin!(expired);
+
+    let result = loop {
+        tokio::select! {
+            message = read.next() => match message {
+                Some(Ok(Message::Text(text))) => match apply(&text, &mut latest) {
+                    Ok(symbol) => {
+                        changed.insert(symbol);
+                    }
+                    Err(err) => warn!("skipping message {}: {}", text, err),
+                },
+                Some(Ok(Message::Close(_))) | None => break Ok(()),
+                Some(Ok(_)) => {}
+                Some(Err(err)) => break Err(err.into()),
+            },
+            _ = flush.tick() => {
+                if !send_changed(&mut changed, &latest, tx).await {
+                    break Ok(());
+                }
+            }
+            _ = &mut expired => {
+                info!("recycling connection before Binance drops it");
+                let _ = write.send(Message::Close(None)).await;
+                break Ok(());
+            }
+        }
+    };
+
+    send_changed(&mut changed, &latest, tx).await;
+    result
+}
+
+/// Applies a stream message to the latest values, returning the symbol that changed
+fn apply(text: &str, latest: &mut HashMap<String, PriceUpdate>) -> Result<String, Error> {
+    let event: BinanceStreamEvent = serde_json::from_str(text)?;
+    match event.data {
+        BinanceStreamData::MiniTicker(ticker) => {
+            let stats = ticker.stats()?;
+            let close = ticker.close_price.parse::<f64>()?;
+            let update = latest
+                .entry(ticker.symbol.clone())
+                .or_insert_with(|| PriceUpdate {
+                    symbol: ticker.symbol.clone(),
+                    price: close,
+                    stats: CoinStats::default(),
+                });
+            update.stats = stats;
+            Ok(ticker.symbol)
+        }
+        BinanceStreamData::BookTicker(ticker) => {
+            let ask = ticker.ask_price.parse::<f64>()?;
+            let update = latest
+                .entry(ticker.symbol.clone())
+                .or_insert_with(|| PriceUpdate {
+                    symbol: ticker.symbol.clone(),
+                    price: ask,
+                    stats: CoinStats::default(),
+                });
+            update.price = ask;
+            Ok(ticker.symbol)
+        }
+    }
+}
+
+/// Sends the latest values of every changed coin, returns false if the receiver has closed
+async fn send_changed(
+    changed: &mut HashSet<String>,
+    latest: &HashMap<String, PriceUpdate>,
+    tx: &mpsc::Sender<PriceUpdate>,
+) -> bool {
+    for symbol in changed.drain() {
+        if let Some(update) = latest.get(&symbol) {
+            if tx.send(update.clone()).await.is_err() {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::accept_async;
+
+    fn mini_ticker(symbol: &str, close: &str) -> Message {
+        let event = json!({
+            "stream": format!("{}@miniTicker", symbol.to_lowercase()),
+            "data": {
+                "e": "24hrMiniTicker", "E": 1, "s": symbol, "c": close, "o": "100.0",
+                "h": "120.0", "l": "90.0", "v": "1000.0", "q": "100000.0"
+            }
+        });
+        Message::Text(event.to_string())
+    }
+
+    fn book_ticker(symbol: &str, ask: &str) -> Message {
+        let event = json!({
+            "stream": format!("{}@bookTicker", symbol.to_lowercase()),
+            "data": { "u": 1, "s": symbol, "b": "99.0", "B": "1.0", "a": ask, "A": "1.0" }
+        });
+        Message::Text(event.to_string())
+    }
+
+    /// Starts a stub server that sends the messages to each new connection, then closes
+    /// the connection if `close` is set otherwise holds it open. Returns the url and
+    /// a count of connections
+    async fn stub_server(messages: Vec<Message>, close: bool) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("failed to bind stub server");
+        let url = format!("ws://{}", listener.local_addr().expect("no local address"));
+        let connections = Arc::new(AtomicUsize::new(0));
+        let count = connections.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                count.fetch_add(1, Ordering::SeqCst);
+                let messages = messages.clone();
+                tokio::spawn(async move {
+                    let mut socket = accept_async(stream).await.expect("failed handshake");
+                    for message in messages {
+                        socket.send(message).await.expect("failed to send message");
+                    }
+                    if close {
+                        let _ = socket.close(None).await;
+                    } else {
+                        while socket.next().await.is_some() {}
+                    }
+                });
+            }
+        });
+        (url, connections)
+    }
+
+    fn settings(url: String) -> Settings {
+        Settings {
+            url,
+            throttle: Duration::from_millis(200),
+            max_connection_age: MAX_CONNECTION_AGE,
+            min_backoff: Duration::from_millis(10),
+        }
+    }
+
+    #[test]
+    fn stream_url_subscribes_to_both_tickers() {
+        let symbols = ["ETHAUD".to_string(), "BTCAUD".to_string()];
+        assert_eq!(
+            stream_url("wss://example.com", symbols.iter()),
+            "wss://example.com/stream?streams=btcaud@bookTicker/btcaud@miniTicker/ethaud@bookTicker/ethaud@miniTicker"
+        );
+    }
+
+    #[tokio::test]
+    async fn throttles_to_latest_values() {
+        let messages = vec![
+            mini_ticker("ETHAUD", "110.0"),
+            book_ticker("ETHAUD", "111.0"),
+            book_ticker("ETHAUD", "112.0"),
+            book_ticker("ETHAUD", "113.0"),
+        ];
+        let (url, _) = stub_server(messages, false).await;
+        let (tx, mut rx) = mpsc::channel(100);
+        tokio::spawn(ingest(settings(url), tx));
+
+        let update = time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .expect("timed out waiting for update")
+            .expect("channel closed");
+        assert_eq!(update.symbol, "ETHAUD");
+        assert_eq!(update.price, 113.0);
+        assert_eq!(update.stats.price_change_percent, 10.0);
+        assert_eq!(update.stats.high_price, 120.0);
+
+        // Nothing changed since the last flush so there shouldn't be another write
+        assert!(time::timeout(Duration::from_millis(500), rx.recv())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn reconnects_after_disconnect() {
+        let (url, connections) = stub_server(vec![book_ticker("BTCAUD", "50000.0")], true).await;
+        let (tx, mut rx) = mpsc::channel(100);
+        tokio::spawn(ingest(settings(url), tx));
+
+        for _ in 0..2 {
+            let update = time::timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .expect("timed out waiting for update")
+                .expect("channel closed");
+            assert_eq!(update.price, 50000.0);
+        }
+        assert!(connections.load(Ordering::SeqCst) >= 2);
+    }
+
+    #[tokio::test]
+    async fn recycles_connection_at_max_age() {
+        let (url, connections) = stub_server(vec![], false).await;
+        let (tx, _rx) = mpsc::channel(100);
+        let mut settings = settings(url);
+        settings.max_connection_age = Duration::from_millis(100);
+        tokio::spawn(ingest(settings, tx));
+
+        time::sleep(Duration::from_millis(500)).await;
+        assert!(connections.load(Ordering::SeqCst) >= 2);
+    }
+
+    #[test]
+    fn skips_unknown_messages() {
+        let mut latest = HashMap::new();
+        assert!(apply(r#"{"result": null, "id": 1}"#, &mut latest).is_err());
+        assert!(latest.is_empty());
+    }
+}
@@ -0,0 +1,99 @@
+//! Lists the import mappings a user has saved, ordered by name
+
+use aws_sdk_dynamodb::Client;
+use lambda_http::{service_fn, IntoResponse, Request, RequestExt};
+use tracing::Level;
+use tracing_subscriber::FmtSubscriber;
+
+use holdcrypt::{store, Error, Res};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let subscriber = FmtSubscriber::builder()
+        .with_max_level(Level::INFO)
+        .finish();
+
+    tracing::subscriber::set_global_default(subscriber)?;
+
+    lambda_http::run(service_fn(lambda)).await?;
+    Ok(())
+}
+
+async fn lambda(event: Request) -> Result<impl IntoResponse, Error> {
+    let username = match event.path_parameters().first("username") {
+        Some(v) if !v.is_empty() => v.to_string(),
+        _ => return Ok(Res::bad_request("must include a username in the path")),
+    };
+
+    let config = aws_config::load_from_env().await;
+    let client = Client::new(&config);
+
+    match store::user_exists(&client, &username).await {
+        Ok(true) => {}
+        Ok(false) => return Ok(Res::not_found(&format!("user {} doesn't exist", username))),
+        Err(err) => {
+            return Ok(Res::internal_server_error(
+                "failed to get user from dynamodb",
+                err,
+            ))
+        }
+    }
+    let mappings = match store::import_mappings(&client, &username).await {
+        Ok(v) => v,
+        Err(err) => {
+            return Ok(Res::internal_server_error(
+                "failed to get import mappings from dynamodb",
+                err,
+            ))
+        }
+    };
+
+    match serde_json::to_string(&mappings) {
+        Ok(v) => Ok(Res::ok_body(&v)),
+        Err(error) => Ok(Res::internal_server_error(
+            "failed to convert struct to json string",
+            Box::new(error),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use holdcrypt::ImportMapping;
+    use lambda_http::Body;
+    use std::collections::HashMap;
+
+    fn request(username: &str) -> Request {
+        Request::default().with_path_parameters(HashMap::from([(
+            "username".to_string(),
+            vec![username.to_string()],
+        )]))
+    }
+
+    #[tokio::test]
+    async fn get_import_mappings() {
+        let response = lambda(request("testuser"))
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        let mappings: Vec<ImportMapping> = match response.body() {
+            Body::Text(v) => serde_json::from_str(v).expect("failed to parse response body"),
+            _ => panic!("response body not text"),
+        };
+
+        assert!(mappings.windows(2).all(|pair| pair[0].name < pair[1].name));
+        assert_eq!(response.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn fail_to_get_mappings_of_unknown_user() {
+        let response = lambda(request("notauser"))
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        assert_eq!(response.status(), 404);
+    }
+}
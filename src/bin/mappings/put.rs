@@ -0,0 +1,121 @@
+//! Saves how to read a user's own spreadsheet of trades under a name, replacing a mapping
+//! with the same name. The spreadsheet is then imported through `transactions_import` with
+//! the `custom` exchange and the mapping's name
+
+use aws_sdk_dynamodb::Client;
+use lambda_http::{service_fn, Body, IntoResponse, Request, RequestExt};
+use tracing::Level;
+use tracing_subscriber::FmtSubscriber;
+
+use holdcrypt::{store, Error, ImportMapping, Res};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let subscriber = FmtSubscriber::builder()
+        .with_max_level(Level::INFO)
+        .finish();
+
+    tracing::subscriber::set_global_default(subscriber)?;
+
+    lambda_http::run(service_fn(lambda)).await?;
+    Ok(())
+}
+
+async fn lambda(event: Request) -> Result<impl IntoResponse, Error> {
+    let path = event.path_parameters();
+    let username = match path.first("username") {
+        Some(v) if !v.is_empty() => v.to_string(),
+        _ => return Ok(Res::bad_request("must include a username in the path")),
+    };
+
+    let mut mapping: ImportMapping = match event.body() {
+        Body::Text(text) => match serde_json::from_str(text) {
+            Ok(js) => js,
+            Err(err) => return Ok(Res::parse_body_error(err)),
+        },
+        Body::Empty => return Ok(Res::bad_request("no body provided")),
+        Body::Binary(_) => return Ok(Res::bad_request("binary body not supported")),
+    };
+    mapping.name = path.first("name").unwrap_or_default().to_string();
+    if let Err(message) = mapping.validate() {
+        return Ok(Res::bad_request(&message));
+    }
+
+    let config = aws_config::load_from_env().await;
+    let client = Client::new(&config);
+
+    match store::user_exists(&client, &username).await {
+        Ok(true) => {}
+        Ok(false) => return Ok(Res::not_found(&format!("user {} doesn't exist", username))),
+        Err(err) => {
+            return Ok(Res::internal_server_error(
+                "failed to get user from dynamodb",
+                err,
+            ))
+        }
+    }
+    if let Err(err) = store::put_import_mapping(&client, &username, &mapping).await {
+        return Ok(Res::internal_server_error(
+            "failed to add import mapping to dynamodb",
+            err,
+        ));
+    }
+
+    Ok(Res::ok("successfully saved import mapping"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use holdcrypt::{ColumnMapping, DecimalSeparator, TransactionKind};
+    use std::collections::HashMap;
+
+    fn mapping() -> ImportMapping {
+        ImportMapping {
+            columns: ColumnMapping {
+                date: "Datum".to_string(),
+                kind: "Typ".to_string(),
+                asset: "Coin".to_string(),
+                amount: "Menge".to_string(),
+                price: "Kurs".to_string(),
+                ..Default::default()
+            },
+            date_format: "%d.%m.%Y %H:%M".to_string(),
+            delimiter: ";".to_string(),
+            decimal: DecimalSeparator::Comma,
+            kinds: HashMap::from([("Kauf".to_string(), TransactionKind::Buy)]),
+            ..Default::default()
+        }
+    }
+
+    fn request(name: &str, mapping: &ImportMapping) -> Request {
+        let body = serde_json::to_string(mapping).expect("failed to serialize to json string");
+        Request::new(Body::Text(body)).with_path_parameters(HashMap::from([
+            ("username".to_string(), vec!["testuser".to_string()]),
+            ("name".to_string(), vec![name.to_string()]),
+        ]))
+    }
+
+    #[tokio::test]
+    async fn put_import_mapping() {
+        let response = lambda(request("depot", &mapping()))
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        assert_eq!(response.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn fail_to_put_mapping_without_columns() {
+        let mut mapping = mapping();
+        mapping.columns.amount = String::new();
+
+        let response = lambda(request("depot", &mapping))
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        assert_eq!(response.status(), 400);
+    }
+}
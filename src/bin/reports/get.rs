@@ -0,0 +1,442 @@
+//! Builds a report from a single user's transactions, the report is chosen with the
+//! `report` path param:
+//! - `fees` total fees paid in each asset
+//! - `income` staking, interest and airdrops received in each coin at market value, between
+//!   the `from` and `to` query params in epoch seconds, `to` is exclusive and both are optional
+//! - `au_cgt` Australian capital gains for the financial year ending 30 June of the `year`
+//!   query param, disposals are matched with the user's cost basis method
+//! - `uk_cgt` UK capital gains for the tax year ending 5 April of the `year` query param,
+//!   disposals are always matched with Section 104 pooling as HMRC requires
+//! - `us_8949` US capital gains on Form 8949 for the calendar `year`, disposals are matched
+//!   with specific lots if that's the user's method, otherwise first in first out
+//! - `us_schedule_d` the Schedule D summary of `us_8949`
+//! - `koinly` and `cointracking` the whole log as csv in the import format of those tools, the
+//!   legs of a swap are one trade
+//!
+//! The `format` query param is either `json` (default) or `csv`, csv only has the rows of a
+//! report and isn't available for `fees`. `koinly` and `cointracking` are always csv
+
+use aws_sdk_dynamodb::Client;
+use lambda_http::{service_fn, IntoResponse, Request, RequestExt};
+use serde::Serialize;
+use std::collections::HashMap;
+use tracing::Level;
+use tracing_subscriber::FmtSubscriber;
+
+use holdcrypt::{basis, export, ledger, store, tax, CostBasisMethod, Error, Res};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let subscriber = FmtSubscriber::builder()
+        .with_max_level(Level::INFO)
+        .finish();
+
+    tracing::subscriber::set_global_default(subscriber)?;
+
+    lambda_http::run(service_fn(lambda)).await?;
+    Ok(())
+}
+
+fn json_report<T: Serialize>(report: &T) -> Res {
+    match serde_json::to_string(report) {
+        Ok(v) => Res::ok_body(&v),
+        Err(error) => {
+            Res::internal_server_error("failed to convert struct to json string", Box::new(error))
+        }
+    }
+}
+
+fn csv_report(body: Result<String, Error>) -> Res {
+    match body {
+        Ok(v) => Res::ok_csv(&v),
+        Err(err) => Res::internal_server_error("failed to write csv", err),
+    }
+}
+
+async fn lambda(event: Request) -> Result<impl IntoResponse, Error> {
+    let params = event.path_parameters();
+    let (username, report) = match (params.first("username"), params.first("report")) {
+        (Some(username), Some(report)) if !username.is_empty() => (username, report),
+        _ => {
+            return Ok(Res::bad_request(
+                "must include a username and report in the path",
+            ))
+        }
+    };
+
+    let query = event.query_string_parameters();
+    let csv = match query.first("format") {
+        None | Some("json") => false,
+        Some("csv") => true,
+        Some(format) => return Ok(Res::bad_request(&format!("unknown format {}", format))),
+    };
+    let mut numbers = HashMap::new();
+    for param in ["year", "from", "to"] {
+        if let Some(v) = query.first(param) {
+            match v.parse::<i64>() {
+                Ok(number) => numbers.insert(param, number),
+                Err(_) => return Ok(Res::bad_request(&format!("invalid {} {}", param, v))),
+            };
+        }
+    }
+    let year = numbers.get("year").copied();
+
+    let config = aws_config::load_from_env().await;
+    let client = Client::new(&config);
+
+    let user = match store::get_user(&client, username).await {
+        Ok(Some(v)) => v,
+        Ok(None) => return Ok(Res::not_found(&format!("user {} doesn't exist", username))),
+        Err(err) => {
+            return Ok(Res::internal_server_error(
+                "failed to get user from dynamodb",
+                err,
+            ))
+        }
+    };
+    let transactions = match store::user_transactions(&client, username).await {
+        Ok(v) => ledger::effective(&v),
+        Err(err) => {
+            return Ok(Res::internal_server_error(
+                "failed to get user transactions from dynamodb",
+                err,
+            ))
+        }
+    };
+    let method = match store::cost_basis_method(&user) {
+        Ok(v) => v,
+        Err(err) => {
+            return Ok(Res::internal_server_error(
+                "failed to parse user cost basis method",
+                err,
+            ))
+        }
+    };
+
+    Ok(match report {
+        "fees" if csv => Res::bad_request("fees report isn't available as csv"),
+        "fees" => json_report(&ledger::fee_summary(&transactions)),
+        "income" => {
+            let from = numbers.get("from").copied().unwrap_or(i64::MIN);
+            let to = numbers.get("to").copied().unwrap_or(i64::MAX);
+            let report = ledger::income_report(&transactions, from, to);
+            if csv {
+                csv_report(export::income_csv(&report))
+            } else {
+                json_report(&report)
+            }
+        }
+        "au_cgt" => {
+            let year = match year {
+                Some(v) => v,
+                None => {
+                    return Ok(Res::bad_request(
+                        "au_cgt must include a year e.g. 2023 for the year ending 30 June 2023",
+                    ))
+                }
+            };
+            let report = tax::au::cgt_report(
+                &basis::cost_basis(&transactions, tax::au::method(method)),
+                year,
+            );
+            if csv {
+                csv_report(export::au_cgt_csv(&report))
+            } else {
+                json_report(&report)
+            }
+        }
+        "uk_cgt" => {
+            let year = match year {
+                Some(v) => v,
+                None => {
+                    return Ok(Res::bad_request(
+                        "uk_cgt must include a year e.g. 2023 for the year ending 5 April 2023",
+                    ))
+                }
+            };
+            let basis = basis::cost_basis(&transactions, CostBasisMethod::Section104);
+            let report = tax::uk::gains_report(&basis, year);
+            if csv {
+                csv_report(export::uk_cgt_csv(&report))
+            } else {
+                json_report(&report)
+            }
+        }
+        "us_8949" | "us_schedule_d" => {
+            let year = match year {
+                Some(v) => v,
+                None => {
+                    return Ok(Res::bad_request(&format!(
+                        "{} must include a year e.g. 2023 for the calendar year",
+                        report
+                    )))
+                }
+            };
+            let basis = basis::cost_basis(&transactions, tax::us::method(method));
+            let form = tax::us::form_8949(&basis, year);
+            match (report, csv) {
+                ("us_8949", true) => csv_report(export::form_8949_csv(&form)),
+                ("us_8949", false) => json_report(&form),
+                (_, true) => csv_report(export::schedule_d_csv(&form)),
+                (_, false) => json_report(&form.schedule_d),
+            }
+        }
+        "koinly" => csv_report(export::koinly_csv(&transactions)),
+        "cointracking" => csv_report(export::cointracking_csv(&transactions)),
+        _ => Res::bad_request(&format!("unknown report {}", report)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use holdcrypt::{
+        AuCgtReport, FeeSummary, Form8949Report, IncomeReport, ScheduleD, UkGainsReport,
+    };
+    use lambda_http::Body;
+
+    fn request(username: &str, report: &str) -> Request {
+        Request::default().with_path_parameters(HashMap::from([
+            ("username".to_string(), vec![username.to_string()]),
+            ("report".to_string(), vec![report.to_string()]),
+        ]))
+    }
+
+    #[tokio::test]
+    async fn get_fee_report() {
+        let response = lambda(request("testuser", "fees"))
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        let fees: Vec<FeeSummary> = match response.body() {
+            Body::Text(v) => serde_json::from_str(v).expect("failed to parse response body"),
+            _ => panic!("response body not text"),
+        };
+
+        for fee in fees {
+            assert!(fee.amount > 0.0);
+            assert!(fee.transactions > 0);
+        }
+
+        assert_eq!(response.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn get_income_report() {
+        let request = request("testuser", "income").with_query_string_parameters(HashMap::from([
+            ("from".to_string(), vec!["1640995200".to_string()]),
+            ("to".to_string(), vec!["1672531200".to_string()]),
+        ]));
+        let response = lambda(request)
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        let report: IncomeReport = match response.body() {
+            Body::Text(v) => serde_json::from_str(v).expect("failed to parse response body"),
+            _ => panic!("response body not text"),
+        };
+
+        assert_eq!(report.from, 1640995200);
+        let total: f64 = report.income.iter().map(|s| s.value).sum();
+        assert_eq!(report.total, total);
+        assert_eq!(response.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn fail_to_get_income_report_with_invalid_from() {
+        let request =
+            request("testuser", "income").with_query_string_parameters(HashMap::from([(
+                "from".to_string(),
+                vec!["yesterday".to_string()],
+            )]));
+        let response = lambda(request)
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        assert_eq!(response.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn get_au_cgt_report() {
+        let request =
+            request("testuser", "au_cgt").with_query_string_parameters(HashMap::from([(
+                "year".to_string(),
+                vec!["2023".to_string()],
+            )]));
+        let response = lambda(request)
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        let report: AuCgtReport = match response.body() {
+            Body::Text(v) => serde_json::from_str(v).expect("failed to parse response body"),
+            _ => panic!("response body not text"),
+        };
+
+        assert_eq!(report.financial_year, "2022-23");
+        assert!(report.net_capital_gain >= 0.0);
+        assert!(report.discount <= report.discountable_gains);
+        assert_eq!(response.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn get_au_cgt_report_as_csv() {
+        let request = request("testuser", "au_cgt").with_query_string_parameters(HashMap::from([
+            ("year".to_string(), vec!["2023".to_string()]),
+            ("format".to_string(), vec!["csv".to_string()]),
+        ]));
+        let response = lambda(request)
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        match response.body() {
+            Body::Text(v) => assert!(v.starts_with(&export::AU_CGT_HEADER.join(","))),
+            _ => panic!("response body not text"),
+        };
+        assert_eq!(response.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn fail_to_get_au_cgt_report_without_year() {
+        let response = lambda(request("testuser", "au_cgt"))
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        assert_eq!(response.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn get_uk_cgt_report() {
+        let request =
+            request("testuser", "uk_cgt").with_query_string_parameters(HashMap::from([(
+                "year".to_string(),
+                vec!["2023".to_string()],
+            )]));
+        let response = lambda(request)
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        let report: UkGainsReport = match response.body() {
+            Body::Text(v) => serde_json::from_str(v).expect("failed to parse response body"),
+            _ => panic!("response body not text"),
+        };
+
+        assert_eq!(report.tax_year, "2022-23");
+        assert_eq!(report.net_gain, report.gains - report.losses);
+        assert!(report.pool.iter().all(|event| event.pool_amount >= 0.0));
+        assert_eq!(response.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn get_us_8949_report() {
+        let request =
+            request("testuser", "us_8949").with_query_string_parameters(HashMap::from([(
+                "year".to_string(),
+                vec!["2022".to_string()],
+            )]));
+        let response = lambda(request)
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        let report: Form8949Report = match response.body() {
+            Body::Text(v) => serde_json::from_str(v).expect("failed to parse response body"),
+            _ => panic!("response body not text"),
+        };
+
+        assert_eq!(report.tax_year, 2022);
+        assert!(report
+            .rows
+            .iter()
+            .all(|row| row.date_sold.ends_with("/2022")));
+        assert_eq!(response.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn get_us_schedule_d_as_csv() {
+        let request =
+            request("testuser", "us_schedule_d").with_query_string_parameters(HashMap::from([
+                ("year".to_string(), vec!["2022".to_string()]),
+                ("format".to_string(), vec!["csv".to_string()]),
+            ]));
+        let response = lambda(request)
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        match response.body() {
+            Body::Text(v) => {
+                assert!(v.starts_with(&export::SCHEDULE_D_HEADER.join(",")));
+                assert_eq!(v.lines().count(), 4);
+            }
+            _ => panic!("response body not text"),
+        };
+        assert_eq!(response.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn get_us_schedule_d() {
+        let request = request("testuser", "us_schedule_d").with_query_string_parameters(
+            HashMap::from([("year".to_string(), vec!["2022".to_string()])]),
+        );
+        let response = lambda(request)
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        let summary: ScheduleD = match response.body() {
+            Body::Text(v) => serde_json::from_str(v).expect("failed to parse response body"),
+            _ => panic!("response body not text"),
+        };
+
+        assert_eq!(
+            summary.net_gain,
+            summary.short_term.gain + summary.long_term.gain
+        );
+        assert_eq!(response.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn get_koinly_export() {
+        let response = lambda(request("testuser", "koinly"))
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        match response.body() {
+            Body::Text(v) => assert!(v.starts_with(&export::KOINLY_HEADER.join(","))),
+            _ => panic!("response body not text"),
+        };
+        assert_eq!(response.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn get_cointracking_export() {
+        let response = lambda(request("testuser", "cointracking"))
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        match response.body() {
+            Body::Text(v) => assert!(v.starts_with(&export::COINTRACKING_HEADER.join(","))),
+            _ => panic!("response body not text"),
+        };
+        assert_eq!(response.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn fail_to_get_unknown_report() {
+        let response = lambda(request("testuser", "unknown"))
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        assert_eq!(response.status(), 400);
+    }
+}
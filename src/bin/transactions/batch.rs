@@ -0,0 +1,341 @@
+//! Add many transactions for a user in one request, e.g. a year of trades. Every transaction
+//! is validated before anything is written, including that its coin and fee asset exist, then
+//! the valid ones are stored in chunks that each fit in a single transactional write.
+//! Responds with the result of each transaction in the order they were sent. Income without
+//! a price is valued from the price history at `executed_at`, like a single transaction.
+//!
+//! Transactions that remove more of a coin than was held at the time are invalid unless
+//! `allow_short` is set on them, earlier transactions in the batch count towards what was held.
+//! So are backdated transactions that leave a later transaction removing more than was held.
+//!
+//! If a chunk fails to be written the chunks after it are skipped, since they were only valid
+//! with the failed transactions stored before them.
+//!
+//! With `atomic` set nothing is stored if any transaction is invalid, the batch must also fit
+//! in a single transactional write so it's stored all at once
+
+use aws_sdk_dynamodb::Client;
+use lambda_http::{service_fn, Body, IntoResponse, Request};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::Level;
+use tracing_subscriber::FmtSubscriber;
+
+use holdcrypt::{
+    ledger, record_transactions, store, BatchResult, BatchStatus, Error, Res, Transaction,
+    TransactionBatchRequest, TransactionBatchResponse, MAX_CLOCK_SKEW,
+};
+
+const MAX_BATCH: usize = 1000;
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let subscriber = FmtSubscriber::builder()
+        .with_max_level(Level::INFO)
+        .finish();
+
+    tracing::subscriber::set_global_default(subscriber)?;
+
+    lambda_http::run(service_fn(lambda)).await?;
+    Ok(())
+}
+
+/// Checks a transaction from the batch and fills in its fee price, `prices` has the current
+/// price of every coin referenced in the batch or `None` if the coin doesn't exist.
+/// `market_value` is the price at `executed_at` for income without a price, `None` if the
+/// coin has no history then
+fn prepare(
+    transaction: &mut Transaction,
+    prices: &HashMap<String, Option<f64>>,
+    market_value: Option<f64>,
+    now: i64,
+) -> Result<(), String> {
+    transaction.validate()?;
+    if transaction.correction.is_some() {
+        return Err("corrections must be made through the void and amend endpoints".to_string());
+    }
+    if transaction.executed_at > now + MAX_CLOCK_SKEW {
+        return Err("executed_at must not be in the future".to_string());
+    }
+    if prices.get(&transaction.coin).copied().flatten().is_none() {
+        return Err(format!("coin {} doesn't exist", transaction.coin));
+    }
+    if transaction.needs_market_value() {
+        transaction.price = market_value.ok_or(format!(
+            "no price history for {} at executed_at, include a price",
+            transaction.coin
+        ))?;
+    }
+    if transaction.fee > 0.0 && !transaction.fee_asset.is_empty() && transaction.fee_price == 0.0 {
+        transaction.fee_price = if transaction.fee_asset == transaction.coin {
+            transaction.price
+        } else {
+            match prices.get(&transaction.fee_asset).copied().flatten() {
+                Some(price) => price,
+                None => {
+                    return Err(format!(
+                        "fee asset {} isn't a known coin, include a fee_price",
+                        transaction.fee_asset
+                    ))
+                }
+            }
+        };
+    }
+    Ok(())
+}
+
+fn result(index: usize, status: BatchStatus, error: Option<String>) -> BatchResult {
+    BatchResult {
+        index,
+        status,
+        id: None,
+        error,
+    }
+}
+
+async fn lambda(event: Request) -> Result<impl IntoResponse, Error> {
+    let batch: TransactionBatchRequest = match event.body() {
+        Body::Text(text) => match serde_json::from_str(text) {
+            Ok(js) => js,
+            Err(err) => return Ok(Res::parse_body_error(err)),
+        },
+        Body::Empty => return Ok(Res::bad_request("no body provided")),
+        Body::Binary(_) => return Ok(Res::bad_request("binary body not supported")),
+    };
+    if batch.username.is_empty() {
+        return Ok(Res::bad_request("username must not be empty"));
+    }
+    if batch.transactions.is_empty() || batch.transactions.len() > MAX_BATCH {
+        return Ok(Res::bad_request(&format!(
+            "must include between 1 and {} transactions",
+            MAX_BATCH
+        )));
+    }
+
+    let config = aws_config::load_from_env().await;
+    let client = Client::new(&config);
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+
+    match store::user_exists(&client, &batch.username).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return Ok(Res::not_found(&format!(
+                "user {} doesn't exist",
+                batch.username
+            )))
+        }
+        Err(err) => {
+            return Ok(Res::internal_server_error(
+                "failed to get user from dynamodb",
+                err,
+            ))
+        }
+    }
+
+    let mut prices = HashMap::new();
+    for transaction in &batch.transactions {
+        for symbol in [&transaction.coin, &transaction.fee_asset] {
+            if symbol.is_empty() || prices.contains_key(symbol) {
+                continue;
+            }
+            let price = match store::get_coin(&client, symbol).await {
+                Ok(Some(item)) => match store::get_n_opt(&item, "price") {
+                    Ok(v) => v,
+                    Err(err) => {
+                        return Ok(Res::internal_server_error(
+                            "failed to parse coin price from dynamodb",
+                            err,
+                        ))
+                    }
+                },
+                Ok(None) => None,
+                Err(err) => {
+                    return Ok(Res::internal_server_error(
+                        "failed to get coin from dynamodb",
+                        err,
+                    ))
+                }
+            };
+            prices.insert(symbol.clone(), price);
+        }
+    }
+
+    let mut market_values = HashMap::new();
+    for transaction in batch.transactions.iter().filter(|t| t.needs_market_value()) {
+        let key = (transaction.coin.clone(), transaction.executed_at);
+        if market_values.contains_key(&key) {
+            continue;
+        }
+        match store::price_at(&client, &transaction.coin, transaction.executed_at).await {
+            Ok(price) => market_values.insert(key, price),
+            Err(err) => {
+                return Ok(Res::internal_server_error(
+                    "failed to get price history from dynamodb",
+                    err,
+                ))
+            }
+        };
+    }
+
+    let mut results = vec![];
+    let mut valid = vec![];
+    let mut valid_index = vec![];
+    for (index, mut transaction) in batch.transactions.into_iter().enumerate() {
+        transaction.username = batch.username.clone();
+        let market_value = market_values
+            .get(&(transaction.coin.clone(), transaction.executed_at))
+            .copied()
+            .flatten();
+        match prepare(&mut transaction, &prices, market_value, now) {
+            Ok(()) => {
+                results.push(result(index, BatchStatus::Skipped, None));
+                valid.push(transaction);
+                valid_index.push(index);
+            }
+            Err(message) => results.push(result(index, BatchStatus::Invalid, Some(message))),
+        }
+    }
+
+    if let Err(message) = record_transactions(&mut valid, now) {
+        return Ok(Res::bad_request(&message));
+    }
+
+    let existing = match store::user_transactions(&client, &batch.username).await {
+        Ok(v) => v,
+        Err(err) => {
+            return Ok(Res::internal_server_error(
+                "failed to get user transactions from dynamodb",
+                err,
+            ))
+        }
+    };
+    for overdraft in ledger::overdrafts(&existing, &valid) {
+        if let Some(position) = valid.iter().position(|t| t.id == overdraft.id) {
+            valid.remove(position);
+            let index = valid_index.remove(position);
+            results[index] = result(index, BatchStatus::Invalid, Some(overdraft.to_string()));
+        }
+    }
+
+    let mut response = TransactionBatchResponse { stored: 0, results };
+    let invalid = valid.len() < response.results.len();
+    if batch.atomic && invalid {
+        return Ok(Res::ok_body(&serde_json::to_string(&response)?).with_status(422));
+    }
+    let chunks = store::transaction_chunks(&valid);
+    if batch.atomic && chunks.len() > 1 {
+        return Ok(Res::bad_request(&format!(
+            "atomic batch is too large to store in one write, split it into {} batches",
+            chunks.len()
+        )));
+    }
+
+    let mut offset = 0;
+    let mut failed = false;
+    for chunk in chunks {
+        let indexes = &valid_index[offset..offset + chunk.len()];
+        offset += chunk.len();
+        // Later chunks were only checked against balances that included the failed one
+        if failed {
+            for index in indexes {
+                response.results[*index] = result(
+                    *index,
+                    BatchStatus::Skipped,
+                    Some("not stored because an earlier write failed".to_string()),
+                );
+            }
+            continue;
+        }
+
+        let stored = store::append_transactions(&client, &batch.username, chunk).await;
+        for (transaction, index) in chunk.iter().zip(indexes) {
+            response.results[*index] = match &stored {
+                Ok(()) => BatchResult {
+                    index: *index,
+                    status: BatchStatus::Stored,
+                    id: Some(transaction.id.clone()),
+                    error: None,
+                },
+                Err(err) => result(*index, BatchStatus::Failed, Some(err.to_string())),
+            };
+        }
+        match stored {
+            Ok(()) => response.stored += chunk.len(),
+            Err(_) => failed = true,
+        }
+    }
+
+    let status = if invalid || response.stored < valid.len() {
+        207
+    } else {
+        200
+    };
+    match serde_json::to_string(&response) {
+        Ok(v) => Ok(Res::ok_body(&v).with_status(status)),
+        Err(error) => Ok(Res::internal_server_error(
+            "failed to convert struct to json string",
+            Box::new(error),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use holdcrypt::TransactionKind;
+
+    fn transaction(coin: &str, amount: f64) -> Transaction {
+        Transaction {
+            coin: coin.to_string(),
+            kind: TransactionKind::Buy,
+            amount,
+            price: 12.5,
+            executed_at: 1650000000,
+            ..Default::default()
+        }
+    }
+
+    async fn post(batch: TransactionBatchRequest) -> (u16, TransactionBatchResponse) {
+        let body = serde_json::to_string(&batch).expect("failed to serialize to json string");
+        let response = lambda(Request::new(Body::Text(body)))
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+        let results = match response.body() {
+            Body::Text(v) => serde_json::from_str(v).expect("failed to parse response body"),
+            _ => panic!("response body not text"),
+        };
+        (response.status().as_u16(), results)
+    }
+
+    #[tokio::test]
+    async fn reports_result_per_transaction() {
+        let (status, response) = post(TransactionBatchRequest {
+            username: "testuser".to_string(),
+            transactions: vec![transaction("ETHAUD", 1.0), transaction("ETHAUD", -1.0)],
+            atomic: false,
+        })
+        .await;
+
+        assert_eq!(status, 207);
+        assert_eq!(response.stored, 1);
+        assert_eq!(response.results[0].status, BatchStatus::Stored);
+        assert!(response.results[0].id.is_some());
+        assert_eq!(response.results[1].status, BatchStatus::Invalid);
+    }
+
+    #[tokio::test]
+    async fn atomic_batch_stores_nothing_when_invalid() {
+        let (status, response) = post(TransactionBatchRequest {
+            username: "testuser".to_string(),
+            transactions: vec![transaction("ETHAUD", 1.0), transaction("NOTACOINAUD", 1.0)],
+            atomic: true,
+        })
+        .await;
+
+        assert_eq!(status, 422);
+        assert_eq!(response.stored, 0);
+        assert_eq!(response.results[0].status, BatchStatus::Skipped);
+        assert_eq!(response.results[1].status, BatchStatus::Invalid);
+    }
+}
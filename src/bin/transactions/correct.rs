@@ -0,0 +1,191 @@
+//! Voids or amends a transaction by appending a correction that refers to it, the original is
+//! never changed. The `correction` path param is either:
+//! - `void` removes the transaction from balances and calculations, voiding one leg of a swap
+//!   voids every leg so the trade isn't left one sided
+//! - `amend` replaces the transaction with the one in the body, which is priced the same way as
+//!   `transactions_post`. The legs of a swap can't be amended, void the swap and record it again
+//!
+//! A correction that would leave any transaction removing more of a coin than was held when it
+//! was executed returns a 422, e.g. voiding a buy that a later sale depends on
+//!
+//! Responds with the audit chain from the original transaction to the new correction
+
+use aws_sdk_dynamodb::Client;
+use lambda_http::{service_fn, Body, IntoResponse, Request, RequestExt};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::Level;
+use tracing_subscriber::FmtSubscriber;
+
+use holdcrypt::{ledger, record_transactions, store, Correction, Error, Res, Transaction};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let subscriber = FmtSubscriber::builder()
+        .with_max_level(Level::INFO)
+        .finish();
+
+    tracing::subscriber::set_global_default(subscriber)?;
+
+    lambda_http::run(service_fn(lambda)).await?;
+    Ok(())
+}
+
+async fn lambda(event: Request) -> Result<impl IntoResponse, Error> {
+    let params = event.path_parameters();
+    let (username, id, correction) = match (
+        params.first("username"),
+        params.first("id"),
+        params.first("correction"),
+    ) {
+        (Some(username), Some(id), Some(correction)) if !username.is_empty() && !id.is_empty() => {
+            (username, id, correction)
+        }
+        _ => {
+            return Ok(Res::bad_request(
+                "must include a username, transaction id and correction in the path",
+            ))
+        }
+    };
+
+    let amendment = match correction.parse::<Correction>() {
+        Ok(Correction::Void) => None,
+        Ok(Correction::Amend) => match event.body() {
+            Body::Text(text) => match serde_json::from_str::<Transaction>(text) {
+                Ok(js) => Some(Transaction {
+                    username: username.to_string(),
+                    ..js
+                }),
+                Err(err) => return Ok(Res::parse_body_error(err)),
+            },
+            Body::Empty => return Ok(Res::bad_request("an amend must include a body")),
+            Body::Binary(_) => return Ok(Res::bad_request("binary body not supported")),
+        },
+        Err(message) => return Ok(Res::bad_request(&message)),
+    };
+
+    let config = aws_config::load_from_env().await;
+    let client = Client::new(&config);
+
+    let transactions = match store::get_user_transactions(&client, username).await {
+        Ok(Some(v)) => v,
+        Ok(None) => return Ok(Res::not_found(&format!("user {} doesn't exist", username))),
+        Err(err) => {
+            return Ok(Res::internal_server_error(
+                "failed to get user transactions from dynamodb",
+                err,
+            ))
+        }
+    };
+
+    if ledger::audit_chain(&transactions, id).is_empty() {
+        return Ok(Res::not_found(&format!("transaction {} doesn't exist", id)));
+    }
+
+    let legs = ledger::swap_legs(&transactions, id);
+    if amendment.is_some() && !legs.is_empty() {
+        return Ok(Res::bad_request(&format!(
+            "transaction {} is part of a swap, void the swap and record it again",
+            id
+        )));
+    }
+    if amendment.as_ref().is_some_and(|t| !t.swap_id.is_empty()) {
+        return Ok(Res::bad_request(
+            "an amend can't add a transaction to a swap",
+        ));
+    }
+
+    let mut correction = match ledger::correct(&transactions, id, amendment) {
+        Ok(v) => vec![v],
+        Err(message) => return Ok(Res::conflict(&message)),
+    };
+    if let Err(message) = correction[0].validate() {
+        return Ok(Res::bad_request(&message));
+    }
+    if correction[0].correction == Some(Correction::Amend) {
+        match store::fill_prices(&client, &mut correction[0]).await {
+            Ok(Ok(())) => {}
+            Ok(Err(message)) => return Ok(Res::bad_request(&message)),
+            Err(err) => {
+                return Ok(Res::internal_server_error(
+                    "failed to get prices from dynamodb",
+                    err,
+                ))
+            }
+        }
+    }
+    // The other legs of a swap are voided with it, any that were already voided are left
+    let corrected = correction[0].corrects.clone();
+    for leg in legs.iter().filter(|leg| **leg != corrected) {
+        if let Ok(void) = ledger::correct(&transactions, leg, None) {
+            correction.push(void);
+        }
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    if let Err(message) = record_transactions(&mut correction, now) {
+        return Ok(Res::bad_request(&message));
+    }
+    if let Some(overdraft) = ledger::correction_overdraft(&transactions, &correction) {
+        return Ok(Res::unprocessable_entity(&overdraft.to_string()));
+    }
+
+    if let Err(err) = store::append_transactions(&client, username, &correction).await {
+        return Ok(Res::internal_server_error(
+            "failed to add correction to dynamodb",
+            err,
+        ));
+    }
+
+    let chain = ledger::audit_chain(&[transactions, correction.clone()].concat(), id);
+    match serde_json::to_string(&chain) {
+        Ok(v) => Ok(Res::ok_body(&v)),
+        Err(error) => Ok(Res::internal_server_error(
+            "failed to convert struct to json string",
+            Box::new(error),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn request(username: &str, id: &str, correction: &str) -> Request {
+        Request::default().with_path_parameters(HashMap::from([
+            ("username".to_string(), vec![username.to_string()]),
+            ("id".to_string(), vec![id.to_string()]),
+            ("correction".to_string(), vec![correction.to_string()]),
+        ]))
+    }
+
+    #[tokio::test]
+    async fn fail_to_void_missing_transaction() {
+        let response = lambda(request("testuser", "01G0000000000000000000000", "void"))
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        assert_eq!(response.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn fail_to_amend_without_body() {
+        let response = lambda(request("testuser", "01G0000000000000000000000", "amend"))
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        assert_eq!(response.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn fail_on_unknown_correction() {
+        let response = lambda(request("testuser", "01G0000000000000000000000", "delete"))
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        assert_eq!(response.status(), 400);
+    }
+}
@@ -0,0 +1,204 @@
+//! Gets a page of a user's transaction history ordered by when they were executed, including
+//! voids and amends so the full log can be audited. Query params:
+//! - `coin`, `kind` and `tag` only include transactions that match
+//! - `from` and `to` inclusive range of `executed_at` in epoch seconds
+//! - `order` either `asc` (default) or `desc`
+//! - `limit` how many transactions to read, defaults to 50
+//! - `cursor` from the previous page to get the next one
+//! - `chain` a transaction id, returns the original and every correction of it instead
+//! - `format` either `json` (default) or `csv`, the csv cursor is in the `Next-Cursor` header
+
+use aws_sdk_dynamodb::Client;
+use lambda_http::{service_fn, IntoResponse, Request, RequestExt};
+use std::str::FromStr;
+use tracing::Level;
+use tracing_subscriber::FmtSubscriber;
+
+use holdcrypt::{export, ledger, store, Error, Res, TransactionPage, TransactionQuery};
+
+const DEFAULT_LIMIT: i32 = 50;
+const MAX_LIMIT: i32 = 500;
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let subscriber = FmtSubscriber::builder()
+        .with_max_level(Level::INFO)
+        .finish();
+
+    tracing::subscriber::set_global_default(subscriber)?;
+
+    lambda_http::run(service_fn(lambda)).await?;
+    Ok(())
+}
+
+/// Parses an optional query param, returns a message naming the param if it's invalid
+fn parse<T: FromStr>(name: &str, value: Option<&str>) -> Result<Option<T>, String> {
+    match value {
+        Some(v) => v
+            .parse::<T>()
+            .map(Some)
+            .map_err(|_| format!("invalid {} {}", name, v)),
+        None => Ok(None),
+    }
+}
+
+async fn lambda(event: Request) -> Result<impl IntoResponse, Error> {
+    let username = match event.path_parameters().first("username") {
+        Some(v) if !v.is_empty() => v.to_string(),
+        _ => return Ok(Res::bad_request("must include a username in the path")),
+    };
+
+    let params = event.query_string_parameters();
+    let csv = match params.first("format") {
+        None | Some("json") => false,
+        Some("csv") => true,
+        Some(format) => return Ok(Res::bad_request(&format!("unknown format {}", format))),
+    };
+    let descending = match params.first("order") {
+        None | Some("asc") => false,
+        Some("desc") => true,
+        Some(order) => return Ok(Res::bad_request(&format!("unknown order {}", order))),
+    };
+    let query = (|| -> Result<TransactionQuery, String> {
+        Ok(TransactionQuery {
+            coin: params.first("coin").map(str::to_string),
+            kind: parse("kind", params.first("kind"))?,
+            from: parse("from", params.first("from"))?,
+            to: parse("to", params.first("to"))?,
+            tag: params.first("tag").map(str::to_string),
+            descending,
+            limit: parse("limit", params.first("limit"))?.unwrap_or(DEFAULT_LIMIT),
+            cursor: params.first("cursor").map(str::to_string),
+        })
+    })();
+    let query = match query {
+        Ok(v) => v,
+        Err(message) => return Ok(Res::bad_request(&message)),
+    };
+    if query.limit < 1 || query.limit > MAX_LIMIT {
+        return Ok(Res::bad_request(&format!(
+            "limit must be between 1 and {}",
+            MAX_LIMIT
+        )));
+    }
+
+    let config = aws_config::load_from_env().await;
+    let client = Client::new(&config);
+
+    let page = match params.first("chain") {
+        Some(id) => match store::get_user_transactions(&client, &username).await {
+            Ok(Some(transactions)) => TransactionPage {
+                transactions: ledger::audit_chain(&transactions, id),
+                cursor: None,
+            },
+            Ok(None) => return Ok(Res::not_found(&format!("user {} doesn't exist", username))),
+            Err(err) => {
+                return Ok(Res::internal_server_error(
+                    "failed to get user transactions from dynamodb",
+                    err,
+                ))
+            }
+        },
+        None => match store::transaction_page(&client, &username, &query).await {
+            Ok(v) => v,
+            Err(err) => {
+                return Ok(Res::internal_server_error(
+                    "failed to get transactions from dynamodb",
+                    err,
+                ))
+            }
+        },
+    };
+
+    if csv {
+        let body = match export::transactions_csv(&page.transactions) {
+            Ok(v) => v,
+            Err(err) => return Ok(Res::internal_server_error("failed to write csv", err)),
+        };
+        return Ok(match page.cursor {
+            Some(cursor) => Res::ok_csv(&body).with_header("Next-Cursor", &cursor),
+            None => Res::ok_csv(&body),
+        });
+    }
+
+    match serde_json::to_string(&page) {
+        Ok(v) => Ok(Res::ok_body(&v)),
+        Err(error) => Ok(Res::internal_server_error(
+            "failed to convert struct to json string",
+            Box::new(error),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lambda_http::Body;
+    use std::collections::HashMap;
+
+    fn request(query: &[(&str, &str)]) -> Request {
+        Request::default()
+            .with_path_parameters(HashMap::from([(
+                "username".to_string(),
+                vec!["testuser".to_string()],
+            )]))
+            .with_query_string_parameters(
+                query
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), vec![v.to_string()]))
+                    .collect::<HashMap<String, Vec<String>>>(),
+            )
+    }
+
+    #[tokio::test]
+    async fn get_transaction_page() {
+        let response = lambda(request(&[("coin", "ETHAUD"), ("limit", "2")]))
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        let page: TransactionPage = match response.body() {
+            Body::Text(v) => serde_json::from_str(v).expect("failed to parse response body"),
+            _ => panic!("response body not text"),
+        };
+
+        assert!(page.transactions.len() <= 2);
+        assert!(page.transactions.iter().all(|t| t.coin == "ETHAUD"));
+        assert!(page
+            .transactions
+            .windows(2)
+            .all(|w| w[0].executed_at <= w[1].executed_at));
+        assert_eq!(response.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn get_transactions_as_csv() {
+        let response = lambda(request(&[("format", "csv")]))
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        match response.body() {
+            Body::Text(v) => assert!(v.starts_with(&export::TRANSACTION_HEADER.join(","))),
+            _ => panic!("response body not text"),
+        };
+        assert_eq!(response.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn fail_on_invalid_filters() {
+        for query in [
+            [("kind", "trade")],
+            [("from", "yesterday")],
+            [("limit", "0")],
+            [("format", "xml")],
+        ] {
+            let response = lambda(request(&query))
+                .await
+                .expect("failed to run lambda")
+                .into_response();
+
+            assert_eq!(response.status(), 400);
+        }
+    }
+}
@@ -0,0 +1,480 @@
+//! Imports a trade history exported from an exchange, the body is the csv as downloaded and
+//! the exchange is in the path. Trades against the quote currency are buys and sells, trades
+//! between two coins are swaps priced from the price history, and income is valued at its
+//! market price unless the file has its value. Query params:
+//! - `quote` the fiat currency coins are tracked in, `AUD` by default
+//! - `dry_run` set to `true` to preview the transactions without storing anything
+//! - `mapping` name of a mapping saved with `mappings_put`, to import a user's own spreadsheet
+//!   with `custom` as the exchange
+//!
+//! Each row keeps the exchange's id for the trade, rows already imported are reported as
+//! duplicates so the same file, or one that overlaps it, can be imported again. Rows are
+//! checked like a batch, a row that removes more of a coin than was held is invalid. If a write
+//! fails the rows after it are skipped. Responds with the result of each row in the order they
+//! appear in the file
+
+use aws_sdk_dynamodb::Client;
+use lambda_http::{service_fn, Body, IntoResponse, Request, RequestExt};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::Level;
+use tracing_subscriber::FmtSubscriber;
+
+use holdcrypt::{
+    import, ledger, record_transactions, store, Error, Exchange, ImportResponse, ImportResult,
+    ImportStatus, Res, Transaction,
+};
+
+const MAX_ROWS: usize = 5000;
+
+/// What the file was exported from
+enum Source {
+    Exchange(Exchange),
+    // Name of the user's mapping for their own spreadsheet
+    Mapping(String),
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let subscriber = FmtSubscriber::builder()
+        .with_max_level(Level::INFO)
+        .finish();
+
+    tracing::subscriber::set_global_default(subscriber)?;
+
+    lambda_http::run(service_fn(lambda)).await?;
+    Ok(())
+}
+
+fn result(
+    index: usize,
+    status: ImportStatus,
+    external_id: &str,
+    error: Option<String>,
+) -> ImportResult {
+    ImportResult {
+        index,
+        status,
+        external_id: external_id.to_string(),
+        transactions: vec![],
+        error,
+    }
+}
+
+/// Splits transactions into ranges that can each be stored with one `append_transactions`,
+/// `rows` has the row of each transaction. A row is never split so both legs of a swap are
+/// stored together
+fn row_chunks(transactions: &[Transaction], rows: &[usize]) -> Vec<Range<usize>> {
+    let mut chunks = vec![];
+    let mut start = 0;
+    let mut end = 0;
+    while end < rows.len() {
+        let mut next = end + 1;
+        while next < rows.len() && rows[next] == rows[end] {
+            next += 1;
+        }
+        if end > start && store::transaction_chunks(&transactions[start..next]).len() > 1 {
+            chunks.push(start..end);
+            start = end;
+        }
+        end = next;
+    }
+    if start < end {
+        chunks.push(start..end);
+    }
+    chunks
+}
+
+async fn lambda(event: Request) -> Result<impl IntoResponse, Error> {
+    let path = event.path_parameters();
+    let username = match path.first("username") {
+        Some(v) if !v.is_empty() => v.to_string(),
+        _ => return Ok(Res::bad_request("must include a username in the path")),
+    };
+
+    let params = event.query_string_parameters();
+    let source = match (path.first("exchange"), params.first("mapping")) {
+        (Some(import::mapping::SOURCE), Some(name)) => Source::Mapping(name.to_string()),
+        (Some(import::mapping::SOURCE), None) => {
+            return Ok(Res::bad_request(
+                "must include a mapping to import a custom spreadsheet",
+            ))
+        }
+        (Some(exchange), _) => match exchange.parse() {
+            Ok(v) => Source::Exchange(v),
+            Err(message) => return Ok(Res::bad_request(&message)),
+        },
+        (None, _) => return Ok(Res::bad_request("must include an exchange in the path")),
+    };
+    let dry_run = match params.first("dry_run") {
+        None | Some("false") => false,
+        Some("true") => true,
+        Some(v) => return Ok(Res::bad_request(&format!("invalid dry_run {}", v))),
+    };
+    let quote = params.first("quote").unwrap_or("AUD").to_ascii_uppercase();
+    if !import::FIAT.contains(&quote.as_str()) {
+        return Ok(Res::bad_request(&format!(
+            "quote must be one of {}",
+            import::FIAT.join(", ")
+        )));
+    }
+
+    let text = match event.body() {
+        Body::Text(text) => text.as_str(),
+        Body::Binary(bytes) => match std::str::from_utf8(bytes) {
+            Ok(v) => v,
+            Err(_) => return Ok(Res::bad_request("body must be utf-8 text")),
+        },
+        Body::Empty => return Ok(Res::bad_request("no body provided")),
+    };
+
+    let config = aws_config::load_from_env().await;
+    let client = Client::new(&config);
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+
+    match store::user_exists(&client, &username).await {
+        Ok(true) => {}
+        Ok(false) => return Ok(Res::not_found(&format!("user {} doesn't exist", username))),
+        Err(err) => {
+            return Ok(Res::internal_server_error(
+                "failed to get user from dynamodb",
+                err,
+            ))
+        }
+    }
+
+    let fills = match source {
+        Source::Exchange(exchange) => import::parse(exchange, text)
+            .map_err(|err| format!("invalid {} export, {}", exchange, err)),
+        Source::Mapping(name) => {
+            let mapping = match store::get_import_mapping(&client, &username, &name).await {
+                Ok(Some(v)) => v,
+                Ok(None) => {
+                    return Ok(Res::not_found(&format!(
+                        "import mapping {} doesn't exist",
+                        name
+                    )))
+                }
+                Err(err) => {
+                    return Ok(Res::internal_server_error(
+                        "failed to get import mapping from dynamodb",
+                        err,
+                    ))
+                }
+            };
+            import::mapping::fills(&mapping, text, &quote)
+                .map_err(|err| format!("spreadsheet doesn't match mapping {}, {}", name, err))
+        }
+    };
+    let fills = match fills {
+        Ok(v) => v,
+        Err(message) => return Ok(Res::bad_request(&message)),
+    };
+    if fills.is_empty() || fills.len() > MAX_ROWS {
+        return Ok(Res::bad_request(&format!(
+            "must include between 1 and {} rows",
+            MAX_ROWS
+        )));
+    }
+    let existing = match store::user_transactions(&client, &username).await {
+        Ok(v) => v,
+        Err(err) => {
+            return Ok(Res::internal_server_error(
+                "failed to get user transactions from dynamodb",
+                err,
+            ))
+        }
+    };
+    let mut seen: HashSet<String> = existing
+        .iter()
+        .filter(|t| !t.external_id.is_empty())
+        .map(|t| t.external_id.clone())
+        .collect();
+
+    let mut market_prices = HashMap::new();
+    let mut coins = HashMap::new();
+    let mut results = vec![];
+    let mut valid = vec![];
+    let mut rows = vec![];
+    for (index, fill) in fills.into_iter().enumerate() {
+        let fill = match fill {
+            Ok(v) => v,
+            Err(message) => {
+                results.push(result(index, ImportStatus::Invalid, "", Some(message)));
+                continue;
+            }
+        };
+        if !seen.insert(fill.external_id.clone()) {
+            results.push(result(
+                index,
+                ImportStatus::Duplicate,
+                &fill.external_id,
+                None,
+            ));
+            continue;
+        }
+
+        let mut prices = HashMap::new();
+        for symbol in import::prices_needed(&fill, &quote) {
+            let key = (symbol.clone(), fill.executed_at);
+            let price = match market_prices.get(&key) {
+                Some(price) => *price,
+                None => match store::price_at(&client, &symbol, fill.executed_at).await {
+                    Ok(price) => *market_prices.entry(key).or_insert(price),
+                    Err(err) => {
+                        return Ok(Res::internal_server_error(
+                            "failed to get price history from dynamodb",
+                            err,
+                        ))
+                    }
+                },
+            };
+            if let Some(price) = price {
+                prices.insert(symbol, price);
+            }
+        }
+
+        let transactions = match import::transactions(&fill, &username, &quote, &prices) {
+            Ok(v) if v.is_empty() => {
+                results.push(result(
+                    index,
+                    ImportStatus::Ignored,
+                    &fill.external_id,
+                    None,
+                ));
+                continue;
+            }
+            Ok(v) => v,
+            Err(message) => {
+                results.push(result(
+                    index,
+                    ImportStatus::Invalid,
+                    &fill.external_id,
+                    Some(message),
+                ));
+                continue;
+            }
+        };
+
+        let mut missing = None;
+        for symbol in transactions.iter().flat_map(|t| [&t.coin, &t.fee_asset]) {
+            if symbol.is_empty() {
+                continue;
+            }
+            if !coins.contains_key(symbol) {
+                match store::get_coin(&client, symbol).await {
+                    Ok(item) => coins.insert(symbol.clone(), item.is_some()),
+                    Err(err) => {
+                        return Ok(Res::internal_server_error(
+                            "failed to get coin from dynamodb",
+                            err,
+                        ))
+                    }
+                };
+            }
+            if !coins[symbol] {
+                missing = Some(format!("coin {} doesn't exist", symbol));
+                break;
+            }
+        }
+        if missing.is_some() {
+            results.push(result(
+                index,
+                ImportStatus::Invalid,
+                &fill.external_id,
+                missing,
+            ));
+            continue;
+        }
+
+        results.push(result(index, ImportStatus::Ready, &fill.external_id, None));
+        for transaction in transactions {
+            valid.push(transaction);
+            rows.push(index);
+        }
+    }
+
+    if let Err(message) = record_transactions(&mut valid, now) {
+        return Ok(Res::bad_request(&message));
+    }
+
+    // An overdraft makes its whole row invalid, the ledger overdraws every leg of a swap
+    // together so a swap is never stored with only one leg
+    for overdraft in ledger::overdrafts(&existing, &valid) {
+        if let Some(position) = valid.iter().position(|t| t.id == overdraft.id) {
+            let row = &mut results[rows[position]];
+            if row.status == ImportStatus::Ready {
+                row.status = ImportStatus::Invalid;
+                row.error = Some(overdraft.to_string());
+            }
+        }
+    }
+    let (valid, rows): (Vec<Transaction>, Vec<usize>) = valid
+        .into_iter()
+        .zip(rows)
+        .filter(|(_, row)| results[*row].status == ImportStatus::Ready)
+        .unzip();
+    for (transaction, row) in valid.iter().zip(&rows) {
+        results[*row].transactions.push(transaction.clone());
+    }
+
+    if !dry_run {
+        // Later rows were only valid with the earlier ones stored, so stop at the first failure
+        let mut failed = false;
+        for chunk in row_chunks(&valid, &rows) {
+            if failed {
+                for row in &rows[chunk] {
+                    let outcome = &mut results[*row];
+                    outcome.status = ImportStatus::Skipped;
+                    outcome.error = Some("not stored because an earlier write failed".to_string());
+                }
+                continue;
+            }
+            let stored =
+                store::append_transactions(&client, &username, &valid[chunk.clone()]).await;
+            for row in &rows[chunk] {
+                let outcome = &mut results[*row];
+                match &stored {
+                    Ok(()) => outcome.status = ImportStatus::Imported,
+                    Err(err) => {
+                        outcome.status = ImportStatus::Failed;
+                        outcome.error = Some(err.to_string());
+                    }
+                }
+            }
+            failed = stored.is_err();
+        }
+    }
+
+    let count = |status: ImportStatus| results.iter().filter(|r| r.status == status).count();
+    let status = if count(ImportStatus::Invalid)
+        + count(ImportStatus::Failed)
+        + count(ImportStatus::Skipped)
+        > 0
+    {
+        207
+    } else {
+        200
+    };
+    let response = ImportResponse {
+        dry_run,
+        imported: count(ImportStatus::Ready) + count(ImportStatus::Imported),
+        duplicates: count(ImportStatus::Duplicate),
+        results,
+    };
+    match serde_json::to_string(&response) {
+        Ok(v) => Ok(Res::ok_body(&v).with_status(status)),
+        Err(error) => Ok(Res::internal_server_error(
+            "failed to convert struct to json string",
+            Box::new(error),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use holdcrypt::TransactionKind;
+
+    const TRADES: &str = "\
+Date(UTC),Pair,Side,Price,Executed,Amount,Fee
+2022-04-15 05:20:00,ETHAUD,BUY,5000,0.01ETH,50AUD,0.1AUD
+2022-04-15 05:20:00,AUDUSDT,BUY,0.7,10AUD,7USDT,
+";
+
+    fn request(exchange: &str, body: &str, query: &[(&str, &str)]) -> Request {
+        Request::new(Body::Text(body.to_string()))
+            .with_path_parameters(HashMap::from([
+                ("username".to_string(), vec!["testuser".to_string()]),
+                ("exchange".to_string(), vec![exchange.to_string()]),
+            ]))
+            .with_query_string_parameters(
+                query
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), vec![v.to_string()]))
+                    .collect::<HashMap<String, Vec<String>>>(),
+            )
+    }
+
+    async fn post(request: Request) -> (u16, ImportResponse) {
+        let response = lambda(request)
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+        let body = match response.body() {
+            Body::Text(v) => serde_json::from_str(v).expect("failed to parse response body"),
+            _ => panic!("response body not text"),
+        };
+        (response.status().as_u16(), body)
+    }
+
+    #[tokio::test]
+    async fn previews_import_without_storing() {
+        let (status, response) = post(request("binance", TRADES, &[("dry_run", "true")])).await;
+
+        assert_eq!(status, 200);
+        assert!(response.dry_run);
+        let buy = &response.results[0];
+        assert!(matches!(
+            buy.status,
+            ImportStatus::Ready | ImportStatus::Duplicate
+        ));
+        if buy.status == ImportStatus::Ready {
+            assert_eq!(buy.transactions[0].coin, "ETHAUD");
+            assert_eq!(buy.transactions[0].kind, TransactionKind::Buy);
+            assert_eq!(buy.transactions[0].price, 5000.0);
+            assert_eq!(buy.transactions[0].external_id, buy.external_id);
+        }
+        // Buying the quote currency isn't tracked
+        assert_eq!(response.results[1].status, ImportStatus::Ignored);
+    }
+
+    #[tokio::test]
+    async fn reimport_is_a_duplicate() {
+        let (status, _) = post(request("binance", TRADES, &[])).await;
+        assert_eq!(status, 200);
+
+        let (status, response) = post(request("binance", TRADES, &[])).await;
+
+        assert_eq!(status, 200);
+        assert_eq!(response.imported, 0);
+        assert_eq!(response.duplicates, 1);
+        assert_eq!(response.results[0].status, ImportStatus::Duplicate);
+    }
+
+    #[tokio::test]
+    async fn fail_to_import_from_unknown_exchange() {
+        let response = lambda(request("notanexchange", TRADES, &[]))
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        assert_eq!(response.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn fail_to_import_spreadsheet_without_mapping() {
+        let response = lambda(request("custom", TRADES, &[]))
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+        assert_eq!(response.status(), 400);
+
+        let response = lambda(request("custom", TRADES, &[("mapping", "notamapping")]))
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+        assert_eq!(response.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn fail_to_import_without_columns() {
+        let response = lambda(request("kraken", TRADES, &[]))
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        assert_eq!(response.status(), 400);
+    }
+}
@@ -0,0 +1,53 @@
+//! One off move of every user's `transactions` list from the `user` table to the
+//! `transaction` table, keyed by username and id. Transactions stored before ids existed are
+//! given ids in the order they were added, the same ids are generated each run so it's safe
+//! to run again if it fails part way. A list is only removed from the user once all of its
+//! transactions are stored. This isn't a lambda so it's not part of `template.yaml`.
+//!
+//! Everything that reads transactions, including the paginated history and coin renames and
+//! deletes, also reads lists that haven't been moved yet, so this can run any time after
+//! deploying
+
+use aws_sdk_dynamodb::Client;
+use tracing::{info, Level};
+use tracing_subscriber::FmtSubscriber;
+
+use holdcrypt::{store, Error};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let subscriber = FmtSubscriber::builder()
+        .with_max_level(Level::INFO)
+        .finish();
+
+    tracing::subscriber::set_global_default(subscriber)?;
+
+    let config = aws_config::load_from_env().await;
+    let client = Client::new(&config);
+
+    let mut start_key = None;
+    loop {
+        let users = client
+            .scan()
+            .table_name("user")
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await?;
+        for item in users.items().unwrap_or_default() {
+            let username = store::get_s(item, "username")?;
+            let transactions = store::legacy_transactions(item)?;
+            if transactions.is_empty() {
+                continue;
+            }
+
+            store::put_transactions(&client, &transactions).await?;
+            store::remove_legacy_transactions(&client, username, transactions.len()).await?;
+            info!("moved {} transactions for {}", transactions.len(), username);
+        }
+        match users.last_evaluated_key() {
+            Some(key) => start_key = Some(key.clone()),
+            None => break,
+        }
+    }
+    Ok(())
+}
@@ -1,13 +1,28 @@
-//! Add a transaction, which is stored in the user table under transactions
+//! Add a transaction, which is stored in the transaction table under the user.
+//! Each kind of transaction is validated with its own rules before it's stored.
+//! Staking, interest and airdrops without a price are valued from the price history at
+//! `executed_at`, so the income is reported at market value and becomes the coins' cost.
+//! A fee paid in another coin is valued at that coin's current price if `fee_price`
+//! isn't provided. The stored transaction is returned with its generated id.
+//!
+//! Removing more of a coin than the user held when the transaction was executed returns a 422
+//! with the shortfall, unless `allow_short` is set to open a short position. So does a
+//! backdated transaction that leaves a later one removing more than was held. The check reads
+//! the user's transactions before the write and isn't part of it, so two sales posted at the
+//! same moment can both pass and leave the balance overdrawn.
+//!
+//! An `Idempotency-Key` header makes retries safe, the first response for a key is stored for
+//! 24 hours and returned to any retry with the same body. Reusing a key with a different body
+//! returns a 422. The `idempotency` table needs `expires_at` set as its ttl attribute
 
-use aws_sdk_dynamodb::model::AttributeValue;
 use aws_sdk_dynamodb::Client;
 use lambda_http::{service_fn, Body, IntoResponse, Request};
-use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
 use tracing::Level;
 use tracing_subscriber::FmtSubscriber;
 
-use holdcrypt::{Error, Res, Transaction};
+use holdcrypt::{ledger, record_transactions, store, Error, Res, Transaction};
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
@@ -21,68 +36,167 @@ async fn main() -> Result<(), Error> {
     Ok(())
 }
 
+const IDEMPOTENCY_TTL_SECS: i64 = 24 * 60 * 60;
+
 async fn lambda(event: Request) -> Result<impl IntoResponse, Error> {
-    let body = event.body();
+    let txt = match event.body() {
+        Body::Text(txt) => txt,
+        _ => return Ok(Res::bad_request("must include a body")),
+    };
+    let key = match event.headers().get("Idempotency-Key") {
+        Some(value) => match value.to_str() {
+            Ok(v) if !v.is_empty() => Some(v),
+            _ => {
+                return Ok(Res::bad_request(
+                    "Idempotency-Key must be a non empty string",
+                ))
+            }
+        },
+        None => None,
+    };
 
-    if let Body::Text(txt) = body {
-        let trans: Transaction = match serde_json::from_str(txt) {
-            Ok(val) => val,
-            Err(err) => return Ok(Res::parse_body_error(err)),
-        };
+    let config = aws_config::load_from_env().await;
+    let client = Client::new(&config);
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
 
-        let config = aws_config::load_from_env().await;
-        let client = Client::new(&config);
-
-        let map = HashMap::from([
-            ("coin".to_string(), AttributeValue::S(trans.coin)),
-            (
-                "amount".to_string(),
-                AttributeValue::N(trans.amount.to_string()),
-            ),
-            (
-                "price".to_string(),
-                AttributeValue::N(trans.price.to_string()),
-            ),
-        ]);
-
-        let request = client
-            .update_item()
-            .table_name("user")
-            .key("username", AttributeValue::S(trans.username.clone()))
-            .condition_expression("username = :username")
-            .update_expression(
-                "set transactions = list_append(if_not_exists(transactions, :trans), :trans)",
-            )
-            .expression_attribute_values(":trans", AttributeValue::L(vec![AttributeValue::M(map)]))
-            .expression_attribute_values(":username", AttributeValue::S(trans.username));
-
-        if let Err(err) = request.send().await {
+    let key = match key {
+        Some(v) => v,
+        None => return add_transaction(&client, txt, now).await,
+    };
+
+    let hash = store::request_hash(txt);
+    let expires_at = now + IDEMPOTENCY_TTL_SECS;
+    let reserved = match store::reserve_idempotency_key(&client, key, &hash, now, expires_at).await
+    {
+        Ok(v) => v,
+        Err(err) => {
             return Ok(Res::internal_server_error(
-                "failed to add transaction to dynamodb",
-                Box::new(err),
+                "failed to reserve Idempotency-Key in dynamodb",
+                err,
+            ))
+        }
+    };
+    if !reserved {
+        let item = match store::get_idempotency_key(&client, key).await {
+            Ok(Some(v)) => v,
+            Ok(None) => {
+                return Ok(Res::conflict(
+                    "request with this Idempotency-Key is in progress",
+                ))
+            }
+            Err(err) => {
+                return Ok(Res::internal_server_error(
+                    "failed to get Idempotency-Key from dynamodb",
+                    err,
+                ))
+            }
+        };
+        if *store::get_s(&item, "request_hash")? != hash {
+            return Ok(Res::unprocessable_entity(
+                "Idempotency-Key was already used with a different body",
             ));
         }
+        return match store::get_n_opt(&item, "status")? {
+            Some(status) => Ok(Res::replay(status as u16, store::get_s(&item, "body")?)),
+            None => Ok(Res::conflict(
+                "request with this Idempotency-Key is in progress",
+            )),
+        };
+    }
+
+    let res = add_transaction(&client, txt, now).await?;
+    // Server errors can be retried, anything else is the final response for the key
+    let saved = if res.status() >= 500 {
+        store::release_idempotency_key(&client, key).await
+    } else {
+        store::save_idempotent_response(&client, key, res.status(), &res.body()).await
+    };
+    if let Err(err) = saved {
+        warn!(
+            "failed to store response for Idempotency-Key {}: {}",
+            key, err
+        );
+    }
+    Ok(res)
+}
+
+async fn add_transaction(client: &Client, txt: &str, now: i64) -> Result<Res, Error> {
+    let mut trans: Transaction = match serde_json::from_str(txt) {
+        Ok(val) => val,
+        Err(err) => return Ok(Res::parse_body_error(err)),
+    };
+
+    if let Err(message) = trans.validate() {
+        return Ok(Res::bad_request(&message));
+    }
+    if trans.correction.is_some() {
+        return Ok(Res::bad_request(
+            "corrections must be made through the void and amend endpoints",
+        ));
+    }
+
+    if let Err(message) = record_transactions(std::slice::from_mut(&mut trans), now) {
+        return Ok(Res::bad_request(&message));
+    }
+
+    match store::fill_prices(client, &mut trans).await {
+        Ok(Ok(())) => {}
+        Ok(Err(message)) => return Ok(Res::bad_request(&message)),
+        Err(err) => {
+            return Ok(Res::internal_server_error(
+                "failed to get prices from dynamodb",
+                err,
+            ))
+        }
+    }
+
+    let existing = match store::user_transactions(client, &trans.username).await {
+        Ok(v) => v,
+        Err(err) => {
+            return Ok(Res::internal_server_error(
+                "failed to get user transactions from dynamodb",
+                err,
+            ))
+        }
+    };
+    if let Some(overdraft) = ledger::overdrafts(&existing, std::slice::from_ref(&trans)).first() {
+        return Ok(Res::unprocessable_entity(&overdraft.to_string()));
+    }
 
-        return Ok(Res::ok("successfully added transaction"));
-        // return Ok(json!({"message": "record written"}));
+    if let Err(err) =
+        store::append_transactions(client, &trans.username, std::slice::from_ref(&trans)).await
+    {
+        return Ok(Res::internal_server_error(
+            "failed to add transaction to dynamodb",
+            err,
+        ));
     }
 
-    Ok(Res::bad_request("must include a body"))
+    match serde_json::to_string(&trans) {
+        Ok(v) => Ok(Res::ok_body(&v)),
+        Err(error) => Ok(Res::internal_server_error(
+            "failed to convert struct to json string",
+            Box::new(error),
+        )),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use holdcrypt::TransactionKind;
     use lambda_http::Body;
-    use serde_json::json;
 
     #[tokio::test]
     async fn post_transaction() {
         let body = Transaction {
             username: "testuser".to_string(),
-            coin: "Ethereum".to_string(),
+            coin: "ETHAUD".to_string(),
+            kind: TransactionKind::Buy,
             amount: 10.10,
             price: 12.5,
+            executed_at: 1650000000,
+            ..Default::default()
         };
 
         let body = serde_json::to_string(&body).expect("failed to serialize to json string");
@@ -94,13 +208,133 @@ mod tests {
             .expect("failed to run lambda")
             .into_response();
 
-        assert_eq!(
-            response.body(),
-            json!({"message": "successfully added transaction"})
-                .into_response()
-                .body()
-        );
+        let stored: Transaction = match response.body() {
+            Body::Text(v) => serde_json::from_str(v).expect("failed to parse response body"),
+            _ => panic!("response body not text"),
+        };
+
+        assert!(!stored.id.is_empty());
+        assert_eq!(stored.executed_at, 1650000000);
+        assert!(stored.recorded_at >= stored.executed_at);
 
         assert_eq!(response.status(), 200);
     }
+
+    #[tokio::test]
+    async fn post_staking_valued_from_price_history() {
+        let body = Transaction {
+            username: "testuser".to_string(),
+            coin: "ETHAUD".to_string(),
+            kind: TransactionKind::Staking,
+            amount: 0.01,
+            executed_at: 1650000000,
+            ..Default::default()
+        };
+
+        let body = serde_json::to_string(&body).expect("failed to serialize to json string");
+
+        let response = lambda(Request::new(Body::Text(body)))
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        let stored: Transaction = match response.body() {
+            Body::Text(v) => serde_json::from_str(v).expect("failed to parse response body"),
+            _ => panic!("response body not text"),
+        };
+
+        assert!(stored.price > 0.0);
+        assert_eq!(response.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn fail_to_post_negative_amount() {
+        let body = Transaction {
+            username: "testuser".to_string(),
+            coin: "ETHAUD".to_string(),
+            kind: TransactionKind::Sell,
+            amount: -1.0,
+            price: 12.5,
+            executed_at: 1650000000,
+            ..Default::default()
+        };
+
+        let body = serde_json::to_string(&body).expect("failed to serialize to json string");
+
+        let request = Request::new(Body::Text(body));
+
+        let response = lambda(request)
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        assert_eq!(response.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn fail_to_sell_more_than_held() {
+        let body = Transaction {
+            username: "testuser".to_string(),
+            coin: "ETHAUD".to_string(),
+            kind: TransactionKind::Sell,
+            amount: 1e12,
+            price: 12.5,
+            executed_at: 1650000000,
+            ..Default::default()
+        };
+
+        let request = Request::new(Body::Text(
+            serde_json::to_string(&body).expect("failed to serialize to json string"),
+        ));
+        let response = lambda(request)
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        assert_eq!(response.status(), 422);
+    }
+
+    fn idempotent_request(key: &str, amount: f64) -> Request {
+        let body = Transaction {
+            username: "testuser".to_string(),
+            coin: "ETHAUD".to_string(),
+            kind: TransactionKind::Buy,
+            amount,
+            price: 12.5,
+            executed_at: 1650000000,
+            ..Default::default()
+        };
+        let body = serde_json::to_string(&body).expect("failed to serialize to json string");
+        let mut request = Request::new(Body::Text(body));
+        request.headers_mut().insert(
+            "Idempotency-Key",
+            key.parse().expect("failed to parse header value"),
+        );
+        request
+    }
+
+    #[tokio::test]
+    async fn retry_with_idempotency_key() {
+        let key = format!("test-{}", ulid::Ulid::new());
+
+        let first = lambda(idempotent_request(&key, 1.0))
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+        let retry = lambda(idempotent_request(&key, 1.0))
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        assert_eq!(first.status(), 200);
+        assert_eq!(retry.status(), 200);
+        assert_eq!(first.body(), retry.body());
+
+        let different = lambda(idempotent_request(&key, 2.0))
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        assert_eq!(different.status(), 422);
+    }
 }
@@ -0,0 +1,176 @@
+//! Add a coin to coin swap, which is stored as a sell leg and a buy leg under the user's
+//! transactions. Both legs are written in a single dynamodb transaction so a failure can't
+//! leave only one of them stored. The fiat price of each leg, and of the fee asset, comes
+//! from the price history at `executed_at`, so a swap can be backdated. Swapping more than
+//! was held at the time, or leaving a later transaction short, returns a 422 unless
+//! `allow_short` is set
+
+use aws_sdk_dynamodb::Client;
+use lambda_http::{service_fn, Body, IntoResponse, Request};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::Level;
+use tracing_subscriber::FmtSubscriber;
+use ulid::Ulid;
+
+use holdcrypt::{ledger, record_transactions, store, Error, Res, SwapRequest};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let subscriber = FmtSubscriber::builder()
+        .with_max_level(Level::TRACE)
+        .finish();
+
+    tracing::subscriber::set_global_default(subscriber)?;
+
+    lambda_http::run(service_fn(lambda)).await?;
+    Ok(())
+}
+
+async fn lambda(event: Request) -> Result<impl IntoResponse, Error> {
+    let swap: SwapRequest = match event.body() {
+        Body::Text(text) => match serde_json::from_str(text) {
+            Ok(js) => js,
+            Err(err) => return Ok(Res::parse_body_error(err)),
+        },
+        Body::Empty => return Ok(Res::bad_request("no body provided")),
+        Body::Binary(_) => return Ok(Res::bad_request("binary body not supported")),
+    };
+
+    if let Err(message) = swap.validate() {
+        return Ok(Res::bad_request(&message));
+    }
+
+    let config = aws_config::load_from_env().await;
+    let client = Client::new(&config);
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+
+    let mut symbols = vec![&swap.sell_coin, &swap.buy_coin];
+    if !swap.fee_asset.is_empty() {
+        symbols.push(&swap.fee_asset);
+    }
+    let mut prices = vec![];
+    for symbol in symbols {
+        match store::price_at(&client, symbol, swap.executed_at).await {
+            Ok(Some(price)) => prices.push(price),
+            Ok(None) => {
+                return Ok(Res::bad_request(&format!(
+                    "no price history for {} at the time of the swap",
+                    symbol
+                )))
+            }
+            Err(err) => {
+                return Ok(Res::internal_server_error(
+                    "failed to get price history from dynamodb",
+                    err,
+                ))
+            }
+        }
+    }
+    let fee_price = prices.get(2).copied().unwrap_or_default();
+
+    let mut legs = swap.legs(&Ulid::new().to_string(), prices[0], prices[1], fee_price);
+    for leg in &legs {
+        if let Err(message) = leg.validate() {
+            return Ok(Res::bad_request(&message));
+        }
+    }
+    if let Err(message) = record_transactions(&mut legs, now) {
+        return Ok(Res::bad_request(&message));
+    }
+
+    let existing = match store::user_transactions(&client, &swap.username).await {
+        Ok(v) => v,
+        Err(err) => {
+            return Ok(Res::internal_server_error(
+                "failed to get user transactions from dynamodb",
+                err,
+            ))
+        }
+    };
+    if let Some(overdraft) = ledger::overdrafts(&existing, &legs).first() {
+        return Ok(Res::unprocessable_entity(&overdraft.to_string()));
+    }
+
+    if let Err(err) = store::append_transactions(&client, &swap.username, &legs).await {
+        return Ok(Res::internal_server_error(
+            "failed to add swap to dynamodb",
+            err,
+        ));
+    }
+
+    let res_body = match serde_json::to_string(&legs) {
+        Ok(v) => v,
+        Err(error) => {
+            return Ok(Res::internal_server_error(
+                "failed to convert struct to json string",
+                Box::new(error),
+            ))
+        }
+    };
+    Ok(Res::ok_body(&res_body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use holdcrypt::{Transaction, TransactionKind};
+
+    #[tokio::test]
+    async fn post_swap() {
+        let body = SwapRequest {
+            username: "testuser".to_string(),
+            sell_coin: "ETHAUD".to_string(),
+            sell_amount: 0.1,
+            buy_coin: "ADAAUD".to_string(),
+            buy_amount: 200.0,
+            executed_at: 1650000000,
+            ..Default::default()
+        };
+
+        let body = serde_json::to_string(&body).expect("failed to serialize to json string");
+
+        let request = Request::new(Body::Text(body));
+
+        let response = lambda(request)
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        let legs: Vec<Transaction> = match response.body() {
+            Body::Text(v) => serde_json::from_str(v).expect("failed to parse response body"),
+            _ => panic!("response body not text"),
+        };
+
+        assert_eq!(legs.len(), 2);
+        assert_eq!(legs[0].kind, TransactionKind::Sell);
+        assert_eq!(legs[1].kind, TransactionKind::Buy);
+        assert_eq!(legs[0].swap_id, legs[1].swap_id);
+        assert!(legs[0].id < legs[1].id);
+        assert!(legs[0].price > 0.0);
+        assert_eq!(response.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn fail_to_swap_same_coin() {
+        let body = SwapRequest {
+            username: "testuser".to_string(),
+            sell_coin: "ETHAUD".to_string(),
+            sell_amount: 0.1,
+            buy_coin: "ETHAUD".to_string(),
+            buy_amount: 0.1,
+            executed_at: 1650000000,
+            ..Default::default()
+        };
+
+        let body = serde_json::to_string(&body).expect("failed to serialize to json string");
+
+        let request = Request::new(Body::Text(body));
+
+        let response = lambda(request)
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        assert_eq!(response.status(), 400);
+    }
+}
@@ -1,17 +1,24 @@
 //! Get an array of users with the sum of all the coins, conveniently structured
-//! for minimal effort on the frontend
-
-#![feature(map_try_insert)]
-
-use std::collections::HashMap;
+//! for minimal effort on the frontend. Balances are folded from the transaction log,
+//! where the kind of each transaction decides if coins are added or removed. Each coin
+//! includes its 24 hour stats so the daily profit or loss of the portfolio can be shown.
+//! Coins are ordered by the `sort_order` in their metadata.
+//!
+//! Each coin has its profit and loss from the user's cost basis method, unrealized against the
+//! coin's price and realized from every sale so far. The user has the totals across all coins.
+//!
+//! The `as_of` query param, in epoch seconds, folds only the transactions executed up to then
+//! and values each coin at the last historical price recorded by that time. Coins that had no
+//! recorded price yet are left out rather than valued at 0. The daily profit or loss is then
+//! the change over the 24 hours before `as_of`
 
 use aws_sdk_dynamodb::{output::ScanOutput, Client};
-use lambda_http::{service_fn, IntoResponse, Request};
-use std::io::ErrorKind;
+use lambda_http::{service_fn, IntoResponse, Request, RequestExt};
+use std::collections::HashMap;
 use tracing::Level;
 use tracing_subscriber::FmtSubscriber;
 
-use holdcrypt::{Coin, Error, Res, UserGetResponse};
+use holdcrypt::{basis, ledger, store, Coin, CoinStats, Error, Pnl, Res, UserGetResponse};
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
@@ -25,13 +32,23 @@ async fn main() -> Result<(), Error> {
     Ok(())
 }
 
-async fn lambda(_: Request) -> Result<impl IntoResponse, Error> {
+const DAY_SECS: i64 = 24 * 60 * 60;
+
+async fn lambda(event: Request) -> Result<impl IntoResponse, Error> {
+    let as_of = match event.query_string_parameters().first("as_of") {
+        Some(v) => match v.parse::<i64>() {
+            Ok(timestamp) => Some(timestamp),
+            Err(_) => return Ok(Res::bad_request("as_of must be epoch seconds")),
+        },
+        None => None,
+    };
+
     let config = aws_config::load_from_env().await;
     let client = Client::new(&config);
     let user_table = client.scan().table_name("user").send().await?;
     let coin_table = client.scan().table_name("coin").send().await?;
 
-    let users = match get_users(user_table, coin_table) {
+    let users = match get_users(&client, user_table, coin_table, as_of).await {
         Ok(v) => v,
         Err(error) => {
             return Ok(Res::internal_server_error(
@@ -54,54 +71,42 @@ async fn lambda(_: Request) -> Result<impl IntoResponse, Error> {
 
 // Returns a generic error, this allows use of the `?` operator so any errors related to dynamodb
 // we can return to the caller with the correct status code in the calling code
-fn get_users(
+async fn get_users(
+    client: &Client,
     user_table: ScanOutput,
     coin_table: ScanOutput,
+    as_of: Option<i64>,
 ) -> Result<Vec<UserGetResponse>, Error> {
+    let historical = match as_of {
+        Some(timestamp) => historical_prices(client, &coin_table, timestamp).await?,
+        None => HashMap::new(),
+    };
+
     let mut users = vec![];
     for map in user_table.items().ok_or("no users in table")? {
         let first_name = map
             .get("first_name")
             .ok_or("first_name key doesn't exist")?
             .as_s()
-            .map_err(|_| std::io::Error::new(ErrorKind::Other, "first_name not of type string"))?;
+            .map_err(|_| std::io::Error::other("first_name not of type string"))?;
         let last_name = map
             .get("last_name")
             .ok_or("last_name key doesn't exist")?
             .as_s()
-            .map_err(|_| std::io::Error::new(ErrorKind::Other, "last_name not of type string"))?;
+            .map_err(|_| std::io::Error::other("last_name not of type string"))?;
         let username = map
             .get("username")
             .ok_or("username key doesn't exist")?
             .as_s()
-            .map_err(|_| std::io::Error::new(ErrorKind::Other, "username not of type string"))?;
-
-        let transactions = map
-            .get("transactions")
-            .ok_or("transactions key doesn't exist")?
-            .as_l()
-            .map_err(|_| std::io::Error::new(ErrorKind::Other, "transactions type incorrect"))?;
-
-        let mut coins = HashMap::new();
-        for transaction in transactions {
-            let trans_map = transaction
-                .as_m()
-                .map_err(|_| std::io::Error::new(ErrorKind::Other, "trans_map type incorrect"))?;
-            let amount = trans_map
-                .get("amount")
-                .ok_or("amount key doesn't exist")?
-                .as_n()
-                .map_err(|_| std::io::Error::new(ErrorKind::Other, "amount not of type number"))?
-                .parse::<f64>()?;
-            let coin = trans_map
-                .get("coin")
-                .ok_or("coin key doesn't exist")?
-                .as_s()
-                .map_err(|_| std::io::Error::new(ErrorKind::Other, "coin not of type string"))?;
+            .map_err(|_| std::io::Error::other("username not of type string"))?;
 
-            coins.entry(coin.clone()).or_insert(0.0);
-            coins.insert(coin.clone(), amount + coins[coin]);
+        let mut transactions =
+            ledger::effective(&store::user_transactions(client, username).await?);
+        if let Some(timestamp) = as_of {
+            transactions = ledger::executed_by(&transactions, timestamp);
         }
+        let coins = ledger::balances(&transactions);
+        let basis = basis::cost_basis(&transactions, store::cost_basis_method(map)?);
 
         let mut coin_vec = Vec::new();
         for coin in coin_table.items().ok_or("no coins in table")? {
@@ -109,42 +114,99 @@ fn get_users(
                 .get("symbol")
                 .ok_or("symbol key doesn't exist")?
                 .as_s()
-                .map_err(|_| std::io::Error::new(ErrorKind::Other, "symbol not of type string"))?;
+                .map_err(|_| std::io::Error::other("symbol not of type string"))?;
             if let Some(amount) = coins.get(symbol) {
-                let price = coin
-                    .get("price")
-                    .ok_or("price key doesn't exist")?
-                    .as_n()
-                    .map_err(|_| std::io::Error::new(ErrorKind::Other, "price not of type number"))?
-                    .parse::<f64>()?;
+                // A coin that was never priced can't be valued
+                let price = match store::get_n_opt(coin, "price")? {
+                    Some(v) => v,
+                    None => continue,
+                };
                 let name = coin
                     .get("name")
                     .ok_or("name key doesn't exist")?
                     .as_s()
-                    .map_err(|_| {
-                        std::io::Error::new(ErrorKind::Other, "name not of type string")
-                    })?;
-                let coin_value = Coin {
+                    .map_err(|_| std::io::Error::other("name not of type string"))?;
+                let (price, stats) = match as_of {
+                    Some(_) => match historical.get(symbol) {
+                        Some(v) => v.clone(),
+                        // No price was recorded by then, so there's nothing to value it at
+                        None => continue,
+                    },
+                    None => (price, store::coin_stats(coin)?),
+                };
+                let mut coin_value = Coin {
                     name: name.clone(),
                     price,
                     symbol: symbol.clone(),
                     amount: *amount,
+                    stats,
+                    metadata: store::coin_metadata(coin)?,
+                    pnl: Default::default(),
+                };
+                let held = basis.coin(symbol).cloned().unwrap_or_default();
+                coin_value.pnl = Pnl {
+                    average_entry_price: held.average_cost,
+                    ..Pnl::new(held.cost, coin_value.value(), basis.realized(symbol))
                 };
                 coin_vec.push(coin_value)
             }
         }
+        coin_vec.sort_by(|a, b| {
+            a.metadata
+                .sort_order
+                .cmp(&b.metadata.sort_order)
+                .then_with(|| a.name.cmp(&b.name))
+        });
 
+        let daily_pnl = coin_vec.iter().map(Coin::daily_pnl).sum();
+        let value = coin_vec.iter().map(Coin::value).sum();
+        // Gains on coins that were sold off or can't be valued still count towards the total
+        let pnl = Pnl::new(
+            coin_vec.iter().map(|c| c.pnl.cost_basis).sum(),
+            value,
+            basis.total_realized(),
+        );
         let user = UserGetResponse {
             first_name: first_name.clone(),
             last_name: last_name.clone(),
             username: username.clone(),
             coins: coin_vec,
+            daily_pnl,
+            value,
+            pnl,
+            as_of,
         };
         users.push(user);
     }
     Ok(users)
 }
 
+/// The last price of every coin recorded at or before `timestamp` keyed by symbol, with the
+/// change over the day before as the only stat. Coins without a price by then are left out
+async fn historical_prices(
+    client: &Client,
+    coin_table: &ScanOutput,
+    timestamp: i64,
+) -> Result<HashMap<String, (f64, CoinStats)>, Error> {
+    let mut prices = HashMap::new();
+    for coin in coin_table.items().unwrap_or_default() {
+        let symbol = store::get_s(coin, "symbol")?;
+        let price = match store::price_at(client, symbol, timestamp).await? {
+            Some(v) => v,
+            None => continue,
+        };
+        let stats = match store::price_at(client, symbol, timestamp - DAY_SECS).await? {
+            Some(previous) if previous > 0.0 => CoinStats {
+                price_change_percent: (price / previous - 1.0) * 100.0,
+                ..Default::default()
+            },
+            _ => CoinStats::default(),
+        };
+        prices.insert(symbol.clone(), (price, stats));
+    }
+    Ok(prices)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -166,8 +228,48 @@ mod tests {
         for user in users {
             assert!(!user.first_name.is_empty());
             assert!(!user.last_name.is_empty());
+            let cost_basis: f64 = user.coins.iter().map(|c| c.pnl.cost_basis).sum();
+            assert!((user.pnl.cost_basis - cost_basis).abs() < 1e-6);
+            assert!((user.pnl.unrealized - (user.value - user.pnl.cost_basis)).abs() < 1e-6);
         }
 
         assert_eq!(response.status(), 200);
     }
+
+    #[tokio::test]
+    async fn get_users_as_of() {
+        let request = Request::default().with_query_string_parameters(HashMap::from([(
+            "as_of".to_string(),
+            vec!["1656597599".to_string()],
+        )]));
+        let response = lambda(request)
+            .await
+            .expect("failed to get users")
+            .into_response();
+
+        let users: Vec<UserGetResponse> = match response.body() {
+            Body::Text(v) => serde_json::from_str(v).expect("failed to parse response body"),
+            _ => panic!("response body not text"),
+        };
+
+        for user in users {
+            assert_eq!(user.as_of, Some(1656597599));
+        }
+
+        assert_eq!(response.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn fail_on_invalid_as_of() {
+        let request = Request::default().with_query_string_parameters(HashMap::from([(
+            "as_of".to_string(),
+            vec!["30 June".to_string()],
+        )]));
+        let response = lambda(request)
+            .await
+            .expect("failed to get users")
+            .into_response();
+
+        assert_eq!(response.status(), 400);
+    }
 }
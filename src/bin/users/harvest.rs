@@ -0,0 +1,156 @@
+//! Lists a user's positions that are worth less than they cost at the coin's current price,
+//! largest loss first, as candidates for tax-loss harvesting. Each has the lots selling would
+//! be matched against under the user's cost basis method, or the method their jurisdiction
+//! requires. With a jurisdiction set on the user each candidate also has the change to the
+//! current tax year's net gain if it was sold now.
+//!
+//! Nothing is sold or stored. In the UK buying the coin back within 30 days matches the sale
+//! against the new coins instead, which undoes the loss
+
+use aws_sdk_dynamodb::Client;
+use lambda_http::{service_fn, IntoResponse, Request, RequestExt};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::Level;
+use tracing_subscriber::FmtSubscriber;
+
+use holdcrypt::{harvest, ledger, store, Error, Res};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let subscriber = FmtSubscriber::builder()
+        .with_max_level(Level::INFO)
+        .finish();
+
+    tracing::subscriber::set_global_default(subscriber)?;
+
+    lambda_http::run(service_fn(lambda)).await?;
+    Ok(())
+}
+
+async fn lambda(event: Request) -> Result<impl IntoResponse, Error> {
+    let username = match event.path_parameters().first("username") {
+        Some(v) if !v.is_empty() => v.to_string(),
+        _ => return Ok(Res::bad_request("must include a username in the path")),
+    };
+
+    let config = aws_config::load_from_env().await;
+    let client = Client::new(&config);
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+
+    let user = match store::get_user(&client, &username).await {
+        Ok(Some(v)) => v,
+        Ok(None) => return Ok(Res::not_found(&format!("user {} doesn't exist", username))),
+        Err(err) => {
+            return Ok(Res::internal_server_error(
+                "failed to get user from dynamodb",
+                err,
+            ))
+        }
+    };
+    let transactions = match store::user_transactions(&client, &username).await {
+        Ok(v) => ledger::effective(&v),
+        Err(err) => {
+            return Ok(Res::internal_server_error(
+                "failed to get user transactions from dynamodb",
+                err,
+            ))
+        }
+    };
+    let (method, jurisdiction) = match (store::cost_basis_method(&user), store::jurisdiction(&user))
+    {
+        (Ok(method), Ok(jurisdiction)) => (method, jurisdiction),
+        (Err(err), _) | (_, Err(err)) => {
+            return Ok(Res::internal_server_error(
+                "failed to parse user tax settings",
+                err,
+            ))
+        }
+    };
+
+    let mut prices = HashMap::new();
+    for (symbol, amount) in ledger::balances(&transactions) {
+        if amount <= 0.0 {
+            continue;
+        }
+        match store::get_coin(&client, &symbol).await {
+            Ok(Some(item)) => match store::get_n_opt(&item, "price") {
+                Ok(Some(price)) => {
+                    prices.insert(symbol, price);
+                }
+                // Coins that were never priced are left out like deleted ones
+                Ok(None) => {}
+                Err(err) => {
+                    return Ok(Res::internal_server_error(
+                        "failed to parse coin price from dynamodb",
+                        err,
+                    ))
+                }
+            },
+            // Coins that were deleted have no price to compare against
+            Ok(None) => {}
+            Err(err) => {
+                return Ok(Res::internal_server_error(
+                    "failed to get coin from dynamodb",
+                    err,
+                ))
+            }
+        }
+    }
+
+    let report = harvest::harvest(&transactions, method, jurisdiction, &prices, now);
+    match serde_json::to_string(&report) {
+        Ok(v) => Ok(Res::ok_body(&v)),
+        Err(error) => Ok(Res::internal_server_error(
+            "failed to convert struct to json string",
+            Box::new(error),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use holdcrypt::HarvestReport;
+    use lambda_http::Body;
+
+    fn request(username: &str) -> Request {
+        Request::default().with_path_parameters(HashMap::from([(
+            "username".to_string(),
+            vec![username.to_string()],
+        )]))
+    }
+
+    #[tokio::test]
+    async fn get_harvest_candidates() {
+        let response = lambda(request("testuser"))
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        let report: HarvestReport = match response.body() {
+            Body::Text(v) => serde_json::from_str(v).expect("failed to parse response body"),
+            _ => panic!("response body not text"),
+        };
+
+        for candidate in &report.candidates {
+            assert!(candidate.loss > 0.0);
+            assert!(!candidate.lots.is_empty());
+        }
+        assert!(report
+            .candidates
+            .windows(2)
+            .all(|pair| pair[0].loss >= pair[1].loss));
+        assert_eq!(response.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn fail_to_harvest_unknown_user() {
+        let response = lambda(request("notauser"))
+            .await
+            .expect("failed to run lambda")
+            .into_response();
+
+        assert_eq!(response.status(), 404);
+    }
+}
@@ -1,4 +1,5 @@
-//! Add a user or update if the user already exists
+//! Add a user or update if the user already exists, along with the cost basis method used
+//! to match their sells against buys and the jurisdiction they pay tax in
 
 use aws_sdk_dynamodb::model::AttributeValue;
 use aws_sdk_dynamodb::Client;
@@ -31,15 +32,33 @@ async fn lambda(event: Request) -> Result<impl IntoResponse, Error> {
 
         let config = aws_config::load_from_env().await;
         let client = Client::new(&config);
-        let request = client
-            .put_item()
+        // An update leaves the rest of the item alone, a user that hasn't been migrated still
+        // has their transactions on it
+        let mut update = "set first_name = :first_name, last_name = :last_name, \
+                          cost_basis_method = :cost_basis_method"
+            .to_string();
+        let mut request = client
+            .update_item()
             .table_name("user")
-            .item("username", AttributeValue::S(user.username))
-            .item("first_name", AttributeValue::S(user.first_name))
-            .item("last_name", AttributeValue::S(user.last_name))
-            .item("transactions", AttributeValue::L([].to_vec()));
+            .key("username", AttributeValue::S(user.username))
+            .expression_attribute_values(":first_name", AttributeValue::S(user.first_name))
+            .expression_attribute_values(":last_name", AttributeValue::S(user.last_name))
+            .expression_attribute_values(
+                ":cost_basis_method",
+                AttributeValue::S(user.cost_basis_method.to_string()),
+            );
+        match user.jurisdiction {
+            Some(jurisdiction) => {
+                update.push_str(", jurisdiction = :jurisdiction");
+                request = request.expression_attribute_values(
+                    ":jurisdiction",
+                    AttributeValue::S(jurisdiction.to_string()),
+                );
+            }
+            None => update.push_str(" remove jurisdiction"),
+        }
 
-        if let Err(err) = request.send().await {
+        if let Err(err) = request.update_expression(update).send().await {
             return Ok(Res::internal_server_error(
                 "failed to add user to dynamodb",
                 Box::new(err),
@@ -55,6 +74,7 @@ async fn lambda(event: Request) -> Result<impl IntoResponse, Error> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use holdcrypt::{CostBasisMethod, Jurisdiction};
     use lambda_http::Body;
     use serde_json::json;
 
@@ -64,6 +84,8 @@ mod tests {
             first_name: "test".to_string(),
             last_name: "user".to_string(),
             username: "testuser".to_string(),
+            cost_basis_method: CostBasisMethod::Hifo,
+            jurisdiction: Some(Jurisdiction::Au),
         };
 
         let body = serde_json::to_string(&body).expect("failed to serialize to json string");
@@ -91,6 +113,7 @@ mod tests {
             first_name: "test".to_string(),
             last_name: "user".to_string(),
             username: "".to_string(),
+            ..Default::default()
         };
 
         let body = serde_json::to_string(&body).expect("failed to serialize to json string");
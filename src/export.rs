@@ -0,0 +1,840 @@
+//! Writes transactions out as csv so they can be opened in a spreadsheet or imported
+//! into other software
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    import::FIAT, tax::Date, AuCgtReport, Error, Form8949Report, HoldingPeriod, IncomeReport,
+    Transaction, TransactionKind, UkGainsReport,
+};
+
+/// Header of the csv written by `transactions_csv`, times are epoch seconds
+pub const TRANSACTION_HEADER: [&str; 14] = [
+    "id",
+    "executed_at",
+    "recorded_at",
+    "coin",
+    "kind",
+    "amount",
+    "price",
+    "fee",
+    "fee_asset",
+    "fee_price",
+    "swap_id",
+    "correction",
+    "corrects",
+    "tags",
+];
+
+/// Writes transactions as csv with a header row, tags are separated by `;`
+pub fn transactions_csv(transactions: &[Transaction]) -> Result<String, Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(TRANSACTION_HEADER)?;
+    for transaction in transactions {
+        writer.write_record([
+            transaction.id.clone(),
+            transaction.executed_at.to_string(),
+            transaction.recorded_at.to_string(),
+            transaction.coin.clone(),
+            transaction.kind.to_string(),
+            transaction.amount.to_string(),
+            transaction.price.to_string(),
+            transaction.fee.to_string(),
+            transaction.fee_asset.clone(),
+            transaction.fee_price.to_string(),
+            transaction.swap_id.clone(),
+            transaction
+                .correction
+                .map(|correction| correction.to_string())
+                .unwrap_or_default(),
+            transaction.corrects.clone(),
+            transaction.tags.join(";"),
+        ])?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+/// Header of the csv written by `income_csv`
+pub const INCOME_HEADER: [&str; 5] = ["coin", "kind", "amount", "value", "transactions"];
+
+/// Writes the totals of an income report as csv with a header row
+pub fn income_csv(report: &IncomeReport) -> Result<String, Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(INCOME_HEADER)?;
+    for summary in &report.income {
+        writer.write_record([
+            summary.coin.clone(),
+            summary.kind.to_string(),
+            summary.amount.to_string(),
+            summary.value.to_string(),
+            summary.transactions.to_string(),
+        ])?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+/// Header of the csv written by `au_cgt_csv`, dates are in Australian Eastern Standard Time
+pub const AU_CGT_HEADER: [&str; 9] = [
+    "disposal_id",
+    "coin",
+    "acquired",
+    "disposed",
+    "amount",
+    "cost_base",
+    "proceeds",
+    "gain",
+    "discountable",
+];
+
+/// Writes the disposals of an Australian capital gains report as csv with a header row, the
+/// totals are only in the json report
+pub fn au_cgt_csv(report: &AuCgtReport) -> Result<String, Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(AU_CGT_HEADER)?;
+    for disposal in &report.disposals {
+        writer.write_record([
+            disposal.disposal_id.clone(),
+            disposal.coin.clone(),
+            disposal.acquired.clone(),
+            disposal.disposed.clone(),
+            disposal.amount.to_string(),
+            disposal.cost_base.to_string(),
+            disposal.proceeds.to_string(),
+            disposal.gain.to_string(),
+            disposal.discountable.to_string(),
+        ])?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+/// Header of the csv written by `uk_cgt_csv`, `rule` is empty for coins disposed of without
+/// being held
+pub const UK_CGT_HEADER: [&str; 8] = [
+    "disposal_id",
+    "coin",
+    "disposed",
+    "amount",
+    "proceeds",
+    "allowable_cost",
+    "gain",
+    "rule",
+];
+
+/// Writes the disposals of a UK capital gains report as csv with a header row, the pool and
+/// totals are only in the json report
+pub fn uk_cgt_csv(report: &UkGainsReport) -> Result<String, Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(UK_CGT_HEADER)?;
+    for disposal in &report.disposals {
+        writer.write_record([
+            disposal.disposal_id.clone(),
+            disposal.coin.clone(),
+            disposal.disposed.clone(),
+            disposal.amount.to_string(),
+            disposal.proceeds.to_string(),
+            disposal.allowable_cost.to_string(),
+            disposal.gain.to_string(),
+            disposal
+                .rule
+                .map(|rule| rule.as_str().to_string())
+                .unwrap_or_default(),
+        ])?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+/// Header of the csv written by `form_8949_csv`, columns (a) to (e) and (h) of the form
+pub const FORM_8949_HEADER: [&str; 7] = [
+    "holding_period",
+    "description",
+    "date_acquired",
+    "date_sold",
+    "proceeds",
+    "cost_basis",
+    "gain",
+];
+
+/// Header of the csv written by `schedule_d_csv`
+pub const SCHEDULE_D_HEADER: [&str; 4] = ["line", "proceeds", "cost_basis", "gain"];
+
+fn holding_period(holding_period: HoldingPeriod) -> &'static str {
+    match holding_period {
+        HoldingPeriod::ShortTerm => "short_term",
+        HoldingPeriod::LongTerm => "long_term",
+    }
+}
+
+/// Writes the rows of a Form 8949 report as csv with a header row, short term rows first
+pub fn form_8949_csv(report: &Form8949Report) -> Result<String, Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(FORM_8949_HEADER)?;
+    for part in [HoldingPeriod::ShortTerm, HoldingPeriod::LongTerm] {
+        for row in report.rows.iter().filter(|r| r.holding_period == part) {
+            writer.write_record([
+                holding_period(part).to_string(),
+                row.description.clone(),
+                row.date_acquired.clone(),
+                row.date_sold.clone(),
+                row.proceeds.to_string(),
+                row.cost_basis.to_string(),
+                row.gain.to_string(),
+            ])?;
+        }
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+/// Writes the Schedule D summary of a Form 8949 report as csv, a line each for short and long
+/// term gains then the net gain
+pub fn schedule_d_csv(report: &Form8949Report) -> Result<String, Error> {
+    let summary = &report.schedule_d;
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(SCHEDULE_D_HEADER)?;
+    for (line, totals) in [
+        ("short_term", &summary.short_term),
+        ("long_term", &summary.long_term),
+    ] {
+        writer.write_record([
+            line.to_string(),
+            totals.proceeds.to_string(),
+            totals.cost_basis.to_string(),
+            totals.gain.to_string(),
+        ])?;
+    }
+    writer.write_record([
+        "net".to_string(),
+        (summary.short_term.proceeds + summary.long_term.proceeds).to_string(),
+        (summary.short_term.cost_basis + summary.long_term.cost_basis).to_string(),
+        summary.net_gain.to_string(),
+    ])?;
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+/// Quote currencies of symbols that aren't fiat, checked before fiat so `BUSD` isn't read as
+/// `USD`
+const COIN_QUOTES: [&str; 3] = ["USDT", "USDC", "BUSD"];
+
+/// Splits a symbol e.g. `ETHAUD` into its asset and quote currency, the quote is empty if it
+/// isn't a known one
+fn split_symbol(symbol: &str) -> (&str, &str) {
+    COIN_QUOTES
+        .iter()
+        .chain(&FIAT)
+        .find_map(|quote| {
+            symbol
+                .strip_suffix(quote)
+                .filter(|asset| !asset.is_empty())
+                .map(|asset| (asset, *quote))
+        })
+        .unwrap_or((symbol, ""))
+}
+
+/// `YYYY-MM-DD HH:MM:SS` in UTC
+fn utc_datetime(timestamp: i64) -> String {
+    let seconds = timestamp.rem_euclid(24 * 60 * 60);
+    format!(
+        "{} {:02}:{:02}:{:02}",
+        Date::at(timestamp, 0),
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// A transaction as tax software sees it, what was sent and what was received with the fee
+/// separate. Both legs of a swap are a single trade
+struct Movement<'a> {
+    // Swap id for a swap, otherwise the transaction id
+    id: &'a str,
+    executed_at: i64,
+    // Buy for a trade between two coins
+    kind: TransactionKind,
+    sent: Option<(f64, &'a str)>,
+    received: Option<(f64, &'a str)>,
+    fee: Option<(f64, &'a str)>,
+    // Values in `quote` of what was sent and received
+    sent_value: f64,
+    received_value: f64,
+    quote: &'a str,
+    tags: &'a [String],
+}
+
+impl Movement<'_> {
+    /// Value of what was received, or what was sent if nothing was
+    fn value(&self) -> f64 {
+        match self.received {
+            Some(_) => self.received_value,
+            None => self.sent_value,
+        }
+    }
+}
+
+fn movement(transaction: &Transaction) -> Movement<'_> {
+    let (asset, quote) = split_symbol(&transaction.coin);
+    let value = transaction.amount * transaction.price;
+    let coins = Some((transaction.amount, asset));
+    let (sent, received) = match transaction.kind {
+        TransactionKind::Buy => (Some((value, quote)), coins),
+        TransactionKind::Sell => (coins, Some((value, quote))),
+        kind if kind.direction() > 0.0 => (None, coins),
+        _ => (coins, None),
+    };
+    let fee_asset = match transaction.fee_asset.as_str() {
+        "" => quote,
+        symbol => split_symbol(symbol).0,
+    };
+    Movement {
+        id: &transaction.id,
+        executed_at: transaction.executed_at,
+        kind: transaction.kind,
+        sent,
+        received,
+        fee: Some((transaction.fee, fee_asset)).filter(|(fee, _)| *fee > 0.0),
+        sent_value: value,
+        received_value: value,
+        quote,
+        tags: &transaction.tags,
+    }
+}
+
+/// Each transaction as a movement in the order they were executed, the legs of a swap are
+/// joined at the first leg. A swap missing a leg is written as the leg that's left
+fn movements(transactions: &[Transaction]) -> Vec<Movement<'_>> {
+    let mut legs: HashMap<&str, Vec<&Transaction>> = HashMap::new();
+    for transaction in transactions.iter().filter(|t| !t.swap_id.is_empty()) {
+        legs.entry(&transaction.swap_id)
+            .or_default()
+            .push(transaction);
+    }
+
+    let mut joined = HashSet::new();
+    let mut movements = vec![];
+    for transaction in transactions {
+        let swap = match legs.get(transaction.swap_id.as_str()).map(Vec::as_slice) {
+            Some([a, b]) if a.kind == TransactionKind::Sell && b.kind == TransactionKind::Buy => {
+                Some((*a, *b))
+            }
+            Some([a, b]) if a.kind == TransactionKind::Buy && b.kind == TransactionKind::Sell => {
+                Some((*b, *a))
+            }
+            _ => None,
+        };
+        let (sell, buy) = match swap {
+            Some(legs) => legs,
+            None => {
+                movements.push(movement(transaction));
+                continue;
+            }
+        };
+        if !joined.insert(transaction.swap_id.as_str()) {
+            continue;
+        }
+        let (sell, buy) = (movement(sell), movement(buy));
+        movements.push(Movement {
+            id: &transaction.swap_id,
+            kind: TransactionKind::Buy,
+            sent: sell.sent,
+            received: buy.received,
+            fee: buy.fee.or(sell.fee),
+            sent_value: sell.sent_value,
+            received_value: buy.received_value,
+            ..buy
+        });
+    }
+    movements
+}
+
+/// Header of the Koinly universal csv written by `koinly_csv`
+pub const KOINLY_HEADER: [&str; 12] = [
+    "Date",
+    "Sent Amount",
+    "Sent Currency",
+    "Received Amount",
+    "Received Currency",
+    "Fee Amount",
+    "Fee Currency",
+    "Net Worth Amount",
+    "Net Worth Currency",
+    "Label",
+    "Description",
+    "TxHash",
+];
+
+/// Koinly's label for a kind, trades and transfers don't have one
+fn koinly_label(kind: TransactionKind) -> &'static str {
+    match kind {
+        TransactionKind::Staking => "staking",
+        TransactionKind::Interest => "loan interest",
+        TransactionKind::Airdrop => "airdrop",
+        TransactionKind::Gift => "gift",
+        TransactionKind::Fee => "cost",
+        _ => "",
+    }
+}
+
+/// Writes transactions in the Koinly universal csv format, the legs of a swap are one row
+/// with the value of the coins received as its net worth. Times are UTC and the transaction or
+/// swap id is the tx hash
+pub fn koinly_csv(transactions: &[Transaction]) -> Result<String, Error> {
+    let amount = |side: Option<(f64, &str)>| match side {
+        Some((amount, asset)) => [amount.to_string(), asset.to_string()],
+        None => [String::new(), String::new()],
+    };
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(KOINLY_HEADER)?;
+    for movement in movements(transactions) {
+        let [sent, sent_currency] = amount(movement.sent);
+        let [received, received_currency] = amount(movement.received);
+        let [fee, fee_currency] = amount(movement.fee);
+        writer.write_record([
+            format!("{} UTC", utc_datetime(movement.executed_at)),
+            sent,
+            sent_currency,
+            received,
+            received_currency,
+            fee,
+            fee_currency,
+            movement.value().to_string(),
+            movement.quote.to_string(),
+            koinly_label(movement.kind).to_string(),
+            movement.tags.join(";"),
+            movement.id.to_string(),
+        ])?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+/// Header of the CoinTracking csv import written by `cointracking_csv`
+pub const COINTRACKING_HEADER: [&str; 14] = [
+    "Type",
+    "Buy Amount",
+    "Buy Currency",
+    "Sell Amount",
+    "Sell Currency",
+    "Fee",
+    "Fee Currency",
+    "Exchange",
+    "Trade-Group",
+    "Comment",
+    "Date",
+    "Tx-ID",
+    "Buy Value in Account Currency",
+    "Sell Value in Account Currency",
+];
+
+/// CoinTracking's type for a kind
+fn cointracking_type(kind: TransactionKind) -> &'static str {
+    match kind {
+        TransactionKind::Buy | TransactionKind::Sell => "Trade",
+        TransactionKind::Deposit => "Deposit",
+        TransactionKind::Withdrawal => "Withdrawal",
+        TransactionKind::Staking => "Staking",
+        TransactionKind::Interest => "Interest Income",
+        TransactionKind::Airdrop => "Airdrop",
+        TransactionKind::Gift => "Gift",
+        TransactionKind::Fee => "Other Fee",
+    }
+}
+
+/// Writes transactions in the CoinTracking csv import format, the legs of a swap are one
+/// trade with the value of each leg. Times are UTC and the transaction or swap id is the tx id
+pub fn cointracking_csv(transactions: &[Transaction]) -> Result<String, Error> {
+    let amount = |side: Option<(f64, &str)>, value: f64| match side {
+        Some((amount, asset)) => [amount.to_string(), asset.to_string(), value.to_string()],
+        None => [String::new(), String::new(), String::new()],
+    };
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(COINTRACKING_HEADER)?;
+    for movement in movements(transactions) {
+        let [bought, buy_currency, buy_value] = amount(movement.received, movement.received_value);
+        let [sold, sell_currency, sell_value] = amount(movement.sent, movement.sent_value);
+        let [fee, fee_currency, _] = amount(movement.fee, 0.0);
+        writer.write_record([
+            cointracking_type(movement.kind).to_string(),
+            bought,
+            buy_currency,
+            sold,
+            sell_currency,
+            fee,
+            fee_currency,
+            String::new(),
+            String::new(),
+            movement.tags.join(";"),
+            utc_datetime(movement.executed_at),
+            movement.id.to_string(),
+            buy_value,
+            sell_value,
+        ])?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        import, AuDisposal, Correction, Fill, Form8949Row, IncomeSummary, MatchRule, ScheduleD,
+        ScheduleDLine, SwapRequest, UkDisposal,
+    };
+
+    #[test]
+    fn writes_transactions_with_header() {
+        let buy = Transaction {
+            id: "01G0000000000000000000000A".to_string(),
+            username: "testuser".to_string(),
+            coin: "ETHAUD".to_string(),
+            kind: TransactionKind::Buy,
+            amount: 1.5,
+            price: 4000.0,
+            executed_at: 1650000000,
+            recorded_at: 1650000100,
+            tags: vec!["ledger".to_string(), "long term".to_string()],
+            ..Default::default()
+        };
+        let void = Transaction {
+            id: "01G0000000000000000000000B".to_string(),
+            correction: Some(Correction::Void),
+            corrects: buy.id.clone(),
+            tags: vec![],
+            ..buy.clone()
+        };
+
+        let csv = transactions_csv(&[buy, void]).expect("failed to write csv");
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(lines[0], TRANSACTION_HEADER.join(","));
+        assert_eq!(
+            lines[1],
+            "01G0000000000000000000000A,1650000000,1650000100,ETHAUD,buy,1.5,4000,0,,0,,,,ledger;long term"
+        );
+        assert_eq!(
+            lines[2],
+            "01G0000000000000000000000B,1650000000,1650000100,ETHAUD,buy,1.5,4000,0,,0,,void,01G0000000000000000000000A,"
+        );
+    }
+
+    #[test]
+    fn writes_au_cgt_disposals() {
+        let report = AuCgtReport {
+            disposals: vec![AuDisposal {
+                disposal_id: "01G0000000000000000000000B".to_string(),
+                coin: "ETHAUD".to_string(),
+                acquired: "2021-01-10".to_string(),
+                disposed: "2022-07-01".to_string(),
+                amount: 1.0,
+                cost_base: 1000.0,
+                proceeds: 1500.0,
+                gain: 500.0,
+                discountable: true,
+            }],
+            ..Default::default()
+        };
+
+        let csv = au_cgt_csv(&report).expect("failed to write csv");
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(lines[0], AU_CGT_HEADER.join(","));
+        assert_eq!(
+            lines[1],
+            "01G0000000000000000000000B,ETHAUD,2021-01-10,2022-07-01,1,1000,1500,500,true"
+        );
+    }
+
+    #[test]
+    fn writes_uk_cgt_disposals() {
+        let disposal = UkDisposal {
+            disposal_id: "01G0000000000000000000000B".to_string(),
+            coin: "BTCGBP".to_string(),
+            disposed: "2022-05-01".to_string(),
+            amount: 0.5,
+            proceeds: 4000.0,
+            allowable_cost: 5000.0,
+            gain: -1000.0,
+            rule: Some(MatchRule::BedAndBreakfast),
+        };
+        let report = UkGainsReport {
+            disposals: vec![
+                disposal.clone(),
+                UkDisposal {
+                    rule: None,
+                    ..disposal
+                },
+            ],
+            ..Default::default()
+        };
+
+        let csv = uk_cgt_csv(&report).expect("failed to write csv");
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(lines[0], UK_CGT_HEADER.join(","));
+        assert_eq!(
+            lines[1],
+            "01G0000000000000000000000B,BTCGBP,2022-05-01,0.5,4000,5000,-1000,bed_and_breakfast"
+        );
+        assert!(lines[2].ends_with(",-1000,"));
+    }
+
+    #[test]
+    fn writes_form_8949_short_term_first() {
+        let row = Form8949Row {
+            disposal_id: "01G0000000000000000000000B".to_string(),
+            holding_period: HoldingPeriod::LongTerm,
+            description: "0.5 ETHUSD".to_string(),
+            date_acquired: "06/01/2021".to_string(),
+            date_sold: "08/01/2022".to_string(),
+            proceeds: 1000.0,
+            cost_basis: 500.0,
+            gain: 500.0,
+        };
+        let report = Form8949Report {
+            rows: vec![
+                row.clone(),
+                Form8949Row {
+                    holding_period: HoldingPeriod::ShortTerm,
+                    ..row
+                },
+            ],
+            ..Default::default()
+        };
+
+        let csv = form_8949_csv(&report).expect("failed to write csv");
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(lines[0], FORM_8949_HEADER.join(","));
+        assert!(lines[1].starts_with("short_term,"));
+        assert_eq!(
+            lines[2],
+            "long_term,0.5 ETHUSD,06/01/2021,08/01/2022,1000,500,500"
+        );
+    }
+
+    #[test]
+    fn writes_schedule_d_lines() {
+        let report = Form8949Report {
+            schedule_d: ScheduleD {
+                short_term: ScheduleDLine {
+                    proceeds: 1000.0,
+                    cost_basis: 1500.0,
+                    gain: -500.0,
+                },
+                long_term: ScheduleDLine {
+                    proceeds: 2000.0,
+                    cost_basis: 500.0,
+                    gain: 1500.0,
+                },
+                net_gain: 1000.0,
+            },
+            ..Default::default()
+        };
+
+        let csv = schedule_d_csv(&report).expect("failed to write csv");
+
+        assert_eq!(
+            csv.lines().collect::<Vec<&str>>(),
+            vec![
+                "line,proceeds,cost_basis,gain",
+                "short_term,1000,1500,-500",
+                "long_term,2000,500,1500",
+                "net,3000,2000,1000",
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_income_totals() {
+        let report = IncomeReport {
+            income: vec![IncomeSummary {
+                coin: "ETHAUD".to_string(),
+                kind: TransactionKind::Staking,
+                amount: 0.5,
+                value: 1500.0,
+                transactions: 12,
+            }],
+            ..Default::default()
+        };
+
+        let csv = income_csv(&report).expect("failed to write csv");
+
+        assert_eq!(
+            csv.lines().collect::<Vec<&str>>(),
+            vec![
+                "coin,kind,amount,value,transactions",
+                "ETHAUD,staking,0.5,1500,12"
+            ]
+        );
+    }
+
+    /// A log with each kind, fees in the quote currency and in another coin, a swap and a
+    /// tagged sell
+    fn log() -> Vec<Transaction> {
+        let transaction =
+            |coin: &str, kind: TransactionKind, amount: f64, price: f64| Transaction {
+                username: "testuser".to_string(),
+                coin: coin.to_string(),
+                kind,
+                amount,
+                price,
+                executed_at: 1650000000,
+                ..Default::default()
+            };
+        let buy = Transaction {
+            fee: 0.01,
+            fee_asset: "BNBAUD".to_string(),
+            fee_price: 500.0,
+            ..transaction("ETHAUD", TransactionKind::Buy, 2.0, 4000.0)
+        };
+        let sell = Transaction {
+            fee: 2.5,
+            tags: vec!["ledger".to_string(), "long term".to_string()],
+            ..transaction("ETHAUD", TransactionKind::Sell, 0.5, 5000.0)
+        };
+        let swap = SwapRequest {
+            username: "testuser".to_string(),
+            sell_coin: "ETHAUD".to_string(),
+            sell_amount: 1.0,
+            buy_coin: "BTCAUD".to_string(),
+            buy_amount: 0.125,
+            executed_at: 1650000000,
+            fee: 0.0001,
+            fee_asset: "BTCAUD".to_string(),
+            allow_short: false,
+        };
+
+        let mut log = vec![buy, sell];
+        log.extend(swap.legs("swap", 4000.0, 32000.0, 32000.0));
+        log.extend([
+            transaction("DOTAUD", TransactionKind::Staking, 2.0, 25.0),
+            transaction("DOTAUD", TransactionKind::Interest, 0.5, 25.0),
+            transaction("ADAAUD", TransactionKind::Airdrop, 10.0, 1.5),
+            transaction("BTCAUD", TransactionKind::Deposit, 0.25, 40000.0),
+            transaction("BTCAUD", TransactionKind::Withdrawal, 0.125, 40000.0),
+            transaction("ADAAUD", TransactionKind::Gift, 4.0, 1.5),
+            transaction("ETHAUD", TransactionKind::Fee, 0.25, 4000.0),
+        ]);
+        for (index, transaction) in log.iter_mut().enumerate() {
+            transaction.id = format!("T{}", index);
+        }
+        log
+    }
+
+    /// Transactions from reading an export back in with our own importer, `BNBAUD` is the only
+    /// price that comes from the price history
+    fn reimport(fills: Vec<Result<Fill, String>>) -> Vec<Transaction> {
+        let prices = HashMap::from([("BNBAUD".to_string(), 500.0)]);
+        fills
+            .into_iter()
+            .flat_map(|fill| {
+                let fill = fill.expect("failed to read row");
+                import::transactions(&fill, "testuser", "AUD", &prices).expect("failed to map fill")
+            })
+            .collect()
+    }
+
+    /// Everything that should survive a round trip, ids are new and the legs of a swap only
+    /// need to share one
+    fn assert_same(imported: &[Transaction], log: &[Transaction]) {
+        let summary = |transactions: &[Transaction]| -> Vec<_> {
+            transactions
+                .iter()
+                .map(|t| {
+                    (
+                        t.coin.clone(),
+                        t.kind,
+                        t.amount,
+                        t.price,
+                        t.fee,
+                        t.fee_asset.clone(),
+                        t.fee_price,
+                        t.swap_id.is_empty(),
+                        t.executed_at,
+                        t.tags.clone(),
+                    )
+                })
+                .collect()
+        };
+        assert_eq!(summary(imported), summary(log));
+        assert_eq!(imported[2].swap_id, imported[3].swap_id);
+    }
+
+    #[test]
+    fn splits_symbols() {
+        assert_eq!(split_symbol("ETHAUD"), ("ETH", "AUD"));
+        assert_eq!(split_symbol("ETHBUSD"), ("ETH", "BUSD"));
+        assert_eq!(split_symbol("USDTAUD"), ("USDT", "AUD"));
+        assert_eq!(split_symbol("ETHBTC"), ("ETHBTC", ""));
+    }
+
+    #[test]
+    fn writes_koinly_universal_csv() {
+        let csv = koinly_csv(&log()).expect("failed to write csv");
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(
+            lines[0],
+            "Date,Sent Amount,Sent Currency,Received Amount,Received Currency,Fee Amount,\
+             Fee Currency,Net Worth Amount,Net Worth Currency,Label,Description,TxHash"
+        );
+        // The legs of the swap are one row
+        assert_eq!(lines.len(), 11);
+        assert_eq!(
+            lines[1..4],
+            [
+                "2022-04-15 05:20:00 UTC,8000,AUD,2,ETH,0.01,BNB,8000,AUD,,,T0",
+                "2022-04-15 05:20:00 UTC,0.5,ETH,2500,AUD,2.5,AUD,2500,AUD,,ledger;long term,T1",
+                "2022-04-15 05:20:00 UTC,1,ETH,0.125,BTC,0.0001,BTC,4000,AUD,,,swap",
+            ]
+        );
+        assert_eq!(
+            lines[4],
+            "2022-04-15 05:20:00 UTC,,,2,DOT,,,50,AUD,staking,,T4"
+        );
+    }
+
+    #[test]
+    fn writes_cointracking_csv() {
+        let csv = cointracking_csv(&log()).expect("failed to write csv");
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(
+            lines[0],
+            "Type,Buy Amount,Buy Currency,Sell Amount,Sell Currency,Fee,Fee Currency,Exchange,\
+             Trade-Group,Comment,Date,Tx-ID,Buy Value in Account Currency,\
+             Sell Value in Account Currency"
+        );
+        assert_eq!(lines.len(), 11);
+        assert_eq!(
+            lines[2],
+            "Trade,2500,AUD,0.5,ETH,2.5,AUD,,,ledger;long term,2022-04-15 05:20:00,T1,2500,2500"
+        );
+        assert_eq!(
+            lines[3],
+            "Trade,0.125,BTC,1,ETH,0.0001,BTC,,,,2022-04-15 05:20:00,swap,4000,4000"
+        );
+        assert_eq!(
+            lines[8],
+            "Withdrawal,,,0.125,BTC,,,,,,2022-04-15 05:20:00,T8,,5000"
+        );
+    }
+
+    #[test]
+    fn koinly_export_imports_without_loss() {
+        let log = log();
+        let csv = koinly_csv(&log).expect("failed to write csv");
+
+        let imported = reimport(import::koinly::fills(&csv).expect("failed to read csv"));
+
+        assert_same(&imported, &log);
+        assert_eq!(imported[0].external_id, "koinly:T0");
+    }
+
+    #[test]
+    fn cointracking_export_imports_without_loss() {
+        let log = log();
+        let csv = cointracking_csv(&log).expect("failed to write csv");
+
+        let imported = reimport(import::cointracking::fills(&csv).expect("failed to read csv"));
+
+        assert_same(&imported, &log);
+        assert_eq!(imported[2].external_id, "cointracking:swap");
+    }
+}
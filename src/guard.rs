@@ -0,0 +1,234 @@
+//! Sanity checks a refreshed price before it's persisted, so a bad depth snapshot or a flash
+//! crash doesn't instantly change every user's valuation. A price is suspicious if it moved
+//! too far from the previous stored price, or if its z-score against the recent history is
+//! too high. Suspicious prices are quarantined and only written if the next fetch confirms them.
+//!
+//! Configured with environment variables, falling back to defaults if they're not set:
+//! - `PRICE_GUARD_MAX_MOVE_PERCENT` maximum percentage move between refreshes
+//! - `PRICE_GUARD_MAX_Z_SCORE` maximum z-score against the rolling window
+//! - `PRICE_GUARD_WINDOW` how many historical prices are in the rolling window
+
+use serde::{Deserialize, Serialize};
+use std::env;
+
+/// The z-score is skipped until there are enough prices for the deviation to mean something
+const MIN_Z_SCORE_SAMPLES: usize = 5;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PriceGuard {
+    pub max_move_percent: f64,
+    pub max_z_score: f64,
+    pub window: usize,
+}
+
+/// What to do with a freshly fetched price
+#[derive(Debug, PartialEq)]
+pub enum Verdict {
+    /// The price looks normal and can be written
+    Accept,
+    /// The price was suspicious last time, but the new fetch agrees with it so it can be written
+    Confirmed,
+    /// The price is suspicious and should be held until the next fetch, contains the reason
+    Quarantine(String),
+}
+
+impl Default for PriceGuard {
+    fn default() -> Self {
+        PriceGuard {
+            max_move_percent: 20.0,
+            max_z_score: 4.0,
+            window: 30,
+        }
+    }
+}
+
+impl PriceGuard {
+    /// Reads the limits from the environment, any that are missing or invalid use the default
+    pub fn from_env() -> PriceGuard {
+        let default = PriceGuard::default();
+        PriceGuard {
+            max_move_percent: env_or("PRICE_GUARD_MAX_MOVE_PERCENT", default.max_move_percent),
+            max_z_score: env_or("PRICE_GUARD_MAX_Z_SCORE", default.max_z_score),
+            window: env_or("PRICE_GUARD_WINDOW", default.window),
+        }
+    }
+
+    /// Checks a new price against the previous stored price, the recent history (most
+    /// recent first) and any price that was quarantined on the last fetch
+    pub fn check(
+        &self,
+        price: f64,
+        previous: Option<f64>,
+        history: &[f64],
+        quarantined: Option<f64>,
+    ) -> Verdict {
+        if !price.is_finite() || price <= 0.0 {
+            return Verdict::Quarantine(format!("price {} is not a positive number", price));
+        }
+
+        if let Some(quarantined) = quarantined {
+            if percent_move(quarantined, price) <= self.max_move_percent {
+                return Verdict::Confirmed;
+            }
+        }
+
+        if let Some(previous) = previous {
+            let moved = percent_move(previous, price);
+            if moved > self.max_move_percent {
+                return Verdict::Quarantine(format!(
+                    "price moved {:.2}% from {}, the maximum is {}%",
+                    moved, previous, self.max_move_percent
+                ));
+            }
+        }
+
+        let window = &history[..history.len().min(self.window)];
+        if let Some(z_score) = z_score(price, window) {
+            if z_score.abs() > self.max_z_score {
+                return Verdict::Quarantine(format!(
+                    "price has a z-score of {:.2} against the last {} prices, the maximum is {}",
+                    z_score,
+                    window.len(),
+                    self.max_z_score
+                ));
+            }
+        }
+
+        Verdict::Accept
+    }
+}
+
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    env::var(key)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Absolute percentage difference of `price` relative to `from`
+fn percent_move(from: f64, price: f64) -> f64 {
+    if from == 0.0 {
+        return 0.0;
+    }
+    ((price - from) / from * 100.0).abs()
+}
+
+/// How many standard deviations the price is from the mean of the window, `None` if there
+/// aren't enough samples or they're all identical
+fn z_score(price: f64, window: &[f64]) -> Option<f64> {
+    if window.len() < MIN_Z_SCORE_SAMPLES {
+        return None;
+    }
+    let count = window.len() as f64;
+    let mean = window.iter().sum::<f64>() / count;
+    let variance = window.iter().map(|p| (p - mean).powi(2)).sum::<f64>() / count;
+    let deviation = variance.sqrt();
+    if deviation == 0.0 {
+        return None;
+    }
+    Some((price - mean) / deviation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HISTORY: [f64; 6] = [100.0, 101.0, 99.0, 100.5, 99.5, 100.0];
+
+    #[test]
+    fn accepts_first_price() {
+        let guard = PriceGuard::default();
+        assert_eq!(guard.check(4500.0, None, &[], None), Verdict::Accept);
+    }
+
+    #[test]
+    fn accepts_normal_move() {
+        let guard = PriceGuard::default();
+        assert_eq!(
+            guard.check(100.8, Some(100.0), &HISTORY, None),
+            Verdict::Accept
+        );
+    }
+
+    #[test]
+    fn quarantines_large_move() {
+        let guard = PriceGuard::default();
+        match guard.check(10.0, Some(100.0), &[], None) {
+            Verdict::Quarantine(reason) => assert!(reason.contains("moved 90.00%")),
+            verdict => panic!("expected quarantine, got {:?}", verdict),
+        }
+    }
+
+    #[test]
+    fn quarantines_high_z_score() {
+        let guard = PriceGuard::default();
+        // Within the max move but far outside the usual range of the history
+        match guard.check(110.0, Some(100.0), &HISTORY, None) {
+            Verdict::Quarantine(reason) => assert!(reason.contains("z-score")),
+            verdict => panic!("expected quarantine, got {:?}", verdict),
+        }
+    }
+
+    #[test]
+    fn skips_z_score_with_small_window() {
+        let guard = PriceGuard::default();
+        assert_eq!(
+            guard.check(110.0, Some(100.0), &HISTORY[..4], None),
+            Verdict::Accept
+        );
+    }
+
+    #[test]
+    fn only_uses_prices_in_window() {
+        // The outliers are older than the window, they would hide the jump if they were used
+        let history = [100.0, 101.0, 99.0, 100.5, 99.5, 10.0, 1000.0];
+        let guard = PriceGuard {
+            window: MIN_Z_SCORE_SAMPLES,
+            ..Default::default()
+        };
+        assert!(matches!(
+            guard.check(110.0, Some(100.0), &history, None),
+            Verdict::Quarantine(_)
+        ));
+
+        let guard = PriceGuard {
+            window: history.len(),
+            ..Default::default()
+        };
+        assert_eq!(
+            guard.check(110.0, Some(100.0), &history, None),
+            Verdict::Accept
+        );
+    }
+
+    #[test]
+    fn confirms_quarantined_price() {
+        let guard = PriceGuard::default();
+        assert_eq!(
+            guard.check(10.5, Some(100.0), &HISTORY, Some(10.0)),
+            Verdict::Confirmed
+        );
+    }
+
+    #[test]
+    fn quarantines_again_if_not_confirmed() {
+        let guard = PriceGuard::default();
+        assert!(matches!(
+            guard.check(50.0, Some(100.0), &HISTORY, Some(10.0)),
+            Verdict::Quarantine(_)
+        ));
+    }
+
+    #[test]
+    fn quarantines_invalid_price() {
+        let guard = PriceGuard::default();
+        assert!(matches!(
+            guard.check(f64::NAN, Some(100.0), &[], None),
+            Verdict::Quarantine(_)
+        ));
+        assert!(matches!(
+            guard.check(0.0, None, &[], None),
+            Verdict::Quarantine(_)
+        ));
+    }
+}
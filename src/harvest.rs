@@ -0,0 +1,348 @@
+//! Tax-loss harvesting, finds positions worth less than they cost at the current price and
+//! the lots that selling would be matched against. It only reads the transaction log and
+//! prices, nothing is sold
+
+use std::collections::HashMap;
+
+use crate::{
+    basis::{self, AMOUNT_TOLERANCE},
+    tax, CoinBasis, CostBasisMethod, HarvestCandidate, HarvestReport, Jurisdiction, Lot,
+    LotSelection, TaxEffect, Transaction, TransactionKind,
+};
+
+/// Method disposals are matched with in a jurisdiction given the user's chosen method, the UK
+/// and US only accept some methods
+pub fn method(jurisdiction: Option<Jurisdiction>, user: CostBasisMethod) -> CostBasisMethod {
+    match jurisdiction {
+        Some(Jurisdiction::Uk) => CostBasisMethod::Section104,
+        Some(Jurisdiction::Us) => tax::us::method(user),
+        Some(Jurisdiction::Au) | None => user,
+    }
+}
+
+fn selection(lot: &Lot) -> LotSelection {
+    LotSelection {
+        id: lot.id.clone(),
+        amount: lot.amount,
+    }
+}
+
+/// The lots to sell to realize the largest loss at `price`, empty if there's no loss
+fn lots_to_sell(coin: &CoinBasis, method: CostBasisMethod, price: f64) -> Vec<LotSelection> {
+    let lots = &coin.lots;
+    match method {
+        // Any sale costs the average so it's all or nothing
+        CostBasisMethod::AverageCost | CostBasisMethod::Section104 => {
+            if coin.average_cost > price {
+                lots.iter().map(selection).collect()
+            } else {
+                vec![]
+            }
+        }
+        CostBasisMethod::SpecificLot => {
+            let mut losing: Vec<&Lot> = lots.iter().filter(|lot| lot.unit_cost > price).collect();
+            losing.sort_by(|a, b| b.unit_cost.total_cmp(&a.unit_cost));
+            losing.into_iter().map(selection).collect()
+        }
+        // Lots are used in order, so sell up to the lot where the total loss is largest
+        CostBasisMethod::Fifo | CostBasisMethod::Lifo | CostBasisMethod::Hifo => {
+            let order = basis::match_order(lots, method);
+            let (mut gain, mut lowest, mut count) = (0.0, 0.0, 0);
+            for (used, index) in order.iter().enumerate() {
+                gain += lots[*index].amount * (price - lots[*index].unit_cost);
+                if gain < lowest {
+                    (lowest, count) = (gain, used + 1);
+                }
+            }
+            order[..count]
+                .iter()
+                .map(|i| selection(&lots[*i]))
+                .collect()
+        }
+    }
+}
+
+/// Name and net gain of the tax year ending in `year` under the jurisdiction's rules
+fn net_gain(
+    transactions: &[Transaction],
+    method: CostBasisMethod,
+    jurisdiction: Jurisdiction,
+    year: i64,
+) -> (String, f64) {
+    let basis = basis::cost_basis(transactions, method);
+    match jurisdiction {
+        Jurisdiction::Au => {
+            let report = tax::au::cgt_report(&basis, year);
+            (
+                report.financial_year,
+                report.net_capital_gain - report.net_capital_loss,
+            )
+        }
+        Jurisdiction::Uk => {
+            let report = tax::uk::gains_report(&basis, year);
+            (report.tax_year, report.net_gain)
+        }
+        Jurisdiction::Us => {
+            let report = tax::us::form_8949(&basis, year);
+            (year.to_string(), report.schedule_d.net_gain)
+        }
+    }
+}
+
+/// Positions in `prices` with an unrealized loss at `now`, ranked by the loss from selling the
+/// lots the method allows. With a jurisdiction each candidate has the change to the current
+/// tax year's net gain if it was sold at `now`. `transactions` must have corrections applied
+pub fn harvest(
+    transactions: &[Transaction],
+    user_method: CostBasisMethod,
+    jurisdiction: Option<Jurisdiction>,
+    prices: &HashMap<String, f64>,
+    now: i64,
+) -> HarvestReport {
+    let method = method(jurisdiction, user_method);
+    let basis = basis::cost_basis(transactions, method);
+    let year = jurisdiction.map(|jurisdiction| match jurisdiction {
+        Jurisdiction::Au => tax::au::financial_year_of(now),
+        Jurisdiction::Uk => tax::uk::tax_year_of(now),
+        Jurisdiction::Us => tax::us::tax_year_of(now),
+    });
+    let before = match (jurisdiction, year) {
+        (Some(jurisdiction), Some(year)) => {
+            Some(net_gain(transactions, method, jurisdiction, year).1)
+        }
+        _ => None,
+    };
+
+    let mut candidates = vec![];
+    for coin in basis.coins.iter().filter(|c| c.amount > AMOUNT_TOLERANCE) {
+        let price = match prices.get(&coin.coin) {
+            Some(v) => *v,
+            None => continue,
+        };
+        let lots = lots_to_sell(coin, method, price);
+        let amount: f64 = lots.iter().map(|lot| lot.amount).sum();
+        let cost: f64 = if matches!(
+            method,
+            CostBasisMethod::AverageCost | CostBasisMethod::Section104
+        ) {
+            amount * coin.average_cost
+        } else {
+            lots.iter()
+                .map(|selected| {
+                    let lot = coin.lots.iter().find(|lot| lot.id == selected.id);
+                    selected.amount * lot.map(|lot| lot.unit_cost).unwrap_or_default()
+                })
+                .sum()
+        };
+        let value = amount * price;
+        if cost - value <= AMOUNT_TOLERANCE {
+            continue;
+        }
+
+        let tax_effect = match (jurisdiction, year, before) {
+            (Some(jurisdiction), Some(year), Some(before)) => {
+                let mut sold = transactions.to_vec();
+                sold.push(Transaction {
+                    id: "harvest".to_string(),
+                    coin: coin.coin.clone(),
+                    kind: TransactionKind::Sell,
+                    amount,
+                    price,
+                    executed_at: now,
+                    lots: if method == CostBasisMethod::SpecificLot {
+                        lots.clone()
+                    } else {
+                        vec![]
+                    },
+                    ..Default::default()
+                });
+                let (tax_year, net_gain_after) = net_gain(&sold, method, jurisdiction, year);
+                Some(TaxEffect {
+                    tax_year,
+                    net_gain: before,
+                    net_gain_after,
+                })
+            }
+            _ => None,
+        };
+
+        candidates.push(HarvestCandidate {
+            coin: coin.coin.clone(),
+            price,
+            amount,
+            cost,
+            value,
+            loss: cost - value,
+            lots,
+            tax_effect,
+        });
+    }
+    candidates.sort_by(|a, b| b.loss.total_cmp(&a.loss).then(a.coin.cmp(&b.coin)));
+
+    HarvestReport {
+        method,
+        jurisdiction,
+        total_loss: candidates.iter().map(|c| c.loss).sum(),
+        candidates,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tax::Date;
+    use crate::testing::transaction;
+
+    const DAY: i64 = 24 * 60 * 60;
+
+    /// ETH bought at 1000, 3000 and 2000, BTC bought once at 20000
+    fn log(start: i64) -> Vec<Transaction> {
+        vec![
+            transaction("a", "ETHAUD", TransactionKind::Buy, 1.0, 1000.0, start),
+            transaction(
+                "b",
+                "ETHAUD",
+                TransactionKind::Buy,
+                1.0,
+                3000.0,
+                start + DAY,
+            ),
+            transaction(
+                "c",
+                "ETHAUD",
+                TransactionKind::Buy,
+                1.0,
+                2000.0,
+                start + 2 * DAY,
+            ),
+            transaction(
+                "d",
+                "BTCAUD",
+                TransactionKind::Buy,
+                1.0,
+                20000.0,
+                start + 3 * DAY,
+            ),
+        ]
+    }
+
+    fn prices() -> HashMap<String, f64> {
+        HashMap::from([
+            ("ETHAUD".to_string(), 1500.0),
+            ("BTCAUD".to_string(), 19000.0),
+        ])
+    }
+
+    fn lot_ids(candidate: &HarvestCandidate) -> Vec<&str> {
+        candidate.lots.iter().map(|lot| lot.id.as_str()).collect()
+    }
+
+    #[test]
+    fn ranks_by_harvestable_loss() {
+        let report = harvest(&log(DAY), CostBasisMethod::Hifo, None, &prices(), 10 * DAY);
+
+        let coins: Vec<(&str, f64)> = report
+            .candidates
+            .iter()
+            .map(|c| (c.coin.as_str(), c.loss))
+            .collect();
+        assert_eq!(coins, vec![("ETHAUD", 2000.0), ("BTCAUD", 1000.0)]);
+        assert_eq!(lot_ids(&report.candidates[0]), vec!["b", "c"]);
+        assert_eq!(report.total_loss, 3000.0);
+        assert!(report.candidates[0].tax_effect.is_none());
+    }
+
+    #[test]
+    fn fifo_sells_up_to_the_largest_loss() {
+        let report = harvest(&log(DAY), CostBasisMethod::Fifo, None, &prices(), 10 * DAY);
+
+        // Selling the first lot is a gain of 500, adding the second makes it a loss of 1000
+        // and the third makes it 1500
+        let eth = &report.candidates[0];
+        assert_eq!(lot_ids(eth), vec!["a", "b", "c"]);
+        assert_eq!((eth.amount, eth.cost, eth.value), (3.0, 6000.0, 4500.0));
+        assert_eq!(eth.loss, 1500.0);
+    }
+
+    #[test]
+    fn specific_lots_sells_only_losing_lots() {
+        let report = harvest(
+            &log(DAY),
+            CostBasisMethod::SpecificLot,
+            None,
+            &prices(),
+            10 * DAY,
+        );
+
+        assert_eq!(lot_ids(&report.candidates[0]), vec!["b", "c"]);
+        assert_eq!(report.candidates[0].loss, 2000.0);
+    }
+
+    #[test]
+    fn average_cost_sells_everything() {
+        let mut prices = prices();
+        prices.insert("ETHAUD".to_string(), 2100.0);
+
+        let report = harvest(
+            &log(DAY),
+            CostBasisMethod::AverageCost,
+            None,
+            &prices,
+            10 * DAY,
+        );
+
+        // ETH averages 2000 so only BTC is at a loss
+        assert_eq!(report.candidates.len(), 1);
+        assert_eq!(report.candidates[0].coin, "BTCAUD");
+    }
+
+    #[test]
+    fn estimates_tax_effect() {
+        let start = Date::new(2022, 8, 1).start(0);
+        let mut log = log(start);
+        log.push(transaction(
+            "e",
+            "ETHAUD",
+            TransactionKind::Sell,
+            1.0,
+            2500.0,
+            start + 5 * DAY,
+        ));
+
+        let report = harvest(
+            &log,
+            CostBasisMethod::Fifo,
+            Some(Jurisdiction::Au),
+            &prices(),
+            start + 10 * DAY,
+        );
+
+        // The sale matched the lot bought at 1000 for a gain of 1500, selling the rest of ETH
+        // for a loss of 2000 leaves a net loss to carry forward
+        let effect = report.candidates[0]
+            .tax_effect
+            .clone()
+            .expect("no tax effect");
+        assert_eq!(effect.tax_year, "2022-23");
+        assert_eq!(effect.net_gain, 1500.0);
+        assert_eq!(report.candidates[0].coin, "ETHAUD");
+        assert_eq!(effect.net_gain_after, -500.0);
+    }
+
+    #[test]
+    fn uk_uses_the_pool() {
+        let report = harvest(
+            &log(DAY),
+            CostBasisMethod::Hifo,
+            Some(Jurisdiction::Uk),
+            &prices(),
+            40 * DAY,
+        );
+
+        assert_eq!(report.method, CostBasisMethod::Section104);
+        // ETH pools at 2000 each
+        assert_eq!(report.candidates[0].coin, "ETHAUD");
+        assert_eq!(report.candidates[0].loss, 1500.0);
+        assert_eq!(lot_ids(&report.candidates[0]), vec!["section_104"]);
+    }
+}
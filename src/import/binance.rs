@@ -0,0 +1,102 @@
+//! Binance spot trade history, with the columns `Date(UTC)`, `Pair`, `Side`, `Executed`,
+//! `Amount` and `Fee`. Amounts have their asset after them e.g. `0.5ETH`. The export doesn't
+//! have trade ids so they're built from the row
+
+use std::collections::HashMap;
+
+use super::{amount_of, columns, field, reader, row_id, timestamp};
+use crate::{Error, Exchange, Fill, TransactionKind};
+
+const COLUMNS: [&str; 6] = ["Date(UTC)", "Pair", "Side", "Executed", "Amount", "Fee"];
+
+pub fn fills(text: &str) -> Result<Vec<Result<Fill, String>>, Error> {
+    let mut reader = reader(text);
+    let indexes = columns(reader.headers()?, COLUMNS)?;
+
+    let mut seen = HashMap::new();
+    let mut fills = vec![];
+    for record in reader.records() {
+        let record = record?;
+        let fields = indexes.map(|index| field(&record, index));
+        let external_id = row_id(Exchange::Binance, &fields[..4].join(" "), &mut seen);
+        fills.push(fill(external_id, fields));
+    }
+    Ok(fills)
+}
+
+fn fill(external_id: String, fields: [&str; 6]) -> Result<Fill, String> {
+    let [date, _, side, executed, total, fee] = fields;
+    let kind = match side.to_ascii_uppercase().as_str() {
+        "BUY" => TransactionKind::Buy,
+        "SELL" => TransactionKind::Sell,
+        _ => return Err(format!("unknown side {}", side)),
+    };
+    let (amount, asset) = amount_of(executed)?;
+    let (total, quote) = amount_of(total)?;
+    let (fee, fee_asset) = match fee {
+        "" => (0.0, String::new()),
+        fee => amount_of(fee)?,
+    };
+    Ok(Fill {
+        external_id,
+        executed_at: timestamp(date)?,
+        kind,
+        asset,
+        amount,
+        quote,
+        total,
+        fee,
+        fee_asset,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_trade_history() {
+        let text = "\
+Date(UTC),Pair,Side,Price,Executed,Amount,Fee
+2022-04-15 05:20:00,ETHAUD,BUY,5000,0.5ETH,\"2,500AUD\",0.0005BNB
+2022-04-15 05:20:00,ETHAUD,BUY,5000,0.5ETH,\"2,500AUD\",0.0005BNB
+2022-04-16 00:00:00,ETHBTC,SELL,0.075,1ETH,0.075BTC,0.000075BTC
+2022-04-17 00:00:00,ETHAUD,HOLD,5000,1ETH,5000AUD,
+";
+
+        let fills = fills(text).expect("failed to read export");
+
+        assert_eq!(fills.len(), 4);
+        assert_eq!(
+            fills[0],
+            Ok(Fill {
+                external_id: "binance:2022-04-15 05:20:00 ETHAUD BUY 0.5ETH".to_string(),
+                executed_at: 1650000000,
+                kind: TransactionKind::Buy,
+                asset: "ETH".to_string(),
+                amount: 0.5,
+                quote: "AUD".to_string(),
+                total: 2500.0,
+                fee: 0.0005,
+                fee_asset: "BNB".to_string(),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            fills[1].as_ref().map(|f| f.external_id.as_str()),
+            Ok("binance:2022-04-15 05:20:00 ETHAUD BUY 0.5ETH#2")
+        );
+        let sell = fills[2].as_ref().expect("failed to read sell");
+        assert_eq!(
+            (sell.kind, sell.quote.as_str()),
+            (TransactionKind::Sell, "BTC")
+        );
+        assert!(fills[3].is_err());
+    }
+
+    #[test]
+    fn fails_without_binance_columns() {
+        assert!(fills("Date,Market,Type\n2022-04-15,ETHAUD,BUY\n").is_err());
+    }
+}
@@ -0,0 +1,119 @@
+//! CoinSpot order history, with the columns `Transaction Type`, `Order Date`, `Market`,
+//! `Amount`, `Rate ex. fee` and `Fee AUD (inc GST)`. Dates are `DD/MM/YYYY` in Sydney time and
+//! markets are written `ETH/AUD`. The export doesn't have order ids so they're built from the
+//! row
+
+use std::collections::HashMap;
+
+use super::{asset, columns, field, number, reader, row_id, timestamp};
+use crate::{
+    tax::au::{sydney_offset, AEST_OFFSET},
+    Error, Exchange, Fill, TransactionKind,
+};
+
+const COLUMNS: [&str; 6] = [
+    "Transaction Type",
+    "Order Date",
+    "Market",
+    "Amount",
+    "Rate ex. fee",
+    "Fee AUD (inc GST)",
+];
+
+pub fn fills(text: &str) -> Result<Vec<Result<Fill, String>>, Error> {
+    let mut reader = reader(text);
+    let indexes = columns(reader.headers()?, COLUMNS)?;
+
+    let mut seen = HashMap::new();
+    let mut fills = vec![];
+    for record in reader.records() {
+        let record = record?;
+        let fields = indexes.map(|index| field(&record, index));
+        let external_id = row_id(Exchange::Coinspot, &fields[..4].join(" "), &mut seen);
+        fills.push(fill(external_id, fields));
+    }
+    Ok(fills)
+}
+
+/// Epoch seconds of a time in Sydney, during the hour the clocks go back it's read as the
+/// first of the two
+fn sydney_timestamp(text: &str) -> Result<i64, String> {
+    let local = timestamp(text)?;
+    Ok(local - sydney_offset(local - AEST_OFFSET))
+}
+
+fn fill(external_id: String, fields: [&str; 6]) -> Result<Fill, String> {
+    let [kind, date, market, amount, rate, fee] = fields;
+    let kind = match kind.to_ascii_lowercase().as_str() {
+        "buy" => TransactionKind::Buy,
+        "sell" => TransactionKind::Sell,
+        _ => return Err(format!("unknown transaction type {}", kind)),
+    };
+    let (coin, quote) = market
+        .split_once('/')
+        .ok_or(format!("invalid market {}", market))?;
+    let amount = number(amount)?;
+    let fee = match fee {
+        "" => 0.0,
+        fee => number(fee)?,
+    };
+    Ok(Fill {
+        external_id,
+        executed_at: sydney_timestamp(date)?,
+        kind,
+        asset: asset(coin),
+        amount,
+        quote: asset(quote),
+        total: amount * number(rate)?,
+        fee,
+        fee_asset: if fee > 0.0 {
+            "AUD".to_string()
+        } else {
+            String::new()
+        },
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_order_history() {
+        let text = "\
+Transaction Type,Order Date,Referral ID,Market,Amount,Rate inc. fee,Rate ex. fee,Fee AUD (inc GST),GST AUD,Total AUD,Total (inc GST)
+Buy,15/04/2022 3:20 PM,,ETH/AUD,0.5,5020,5000,10,0.91,2510,2510 AUD
+Sell,15/07/2022 2:20 PM,,BTC/USDT,0.1,29000,30000,6,0.55,4344,2900 USDT
+";
+
+        let fills = fills(text).expect("failed to read export");
+
+        // 3:20pm AEST is 5:20am UTC
+        assert_eq!(
+            fills[0],
+            Ok(Fill {
+                external_id: "coinspot:Buy 15/04/2022 3:20 PM ETH/AUD 0.5".to_string(),
+                executed_at: 1650000000,
+                kind: TransactionKind::Buy,
+                asset: "ETH".to_string(),
+                amount: 0.5,
+                quote: "AUD".to_string(),
+                total: 2500.0,
+                fee: 10.0,
+                fee_asset: "AUD".to_string(),
+                ..Default::default()
+            })
+        );
+        let sell = fills[1].as_ref().expect("failed to read sell");
+        assert_eq!((sell.quote.as_str(), sell.total), ("USDT", 3000.0));
+    }
+
+    #[test]
+    fn reads_daylight_saving_times() {
+        // 11am AEDT on 15 January is midnight UTC
+        assert_eq!(sydney_timestamp("15/01/2022 11:00 AM"), Ok(1642204800));
+        // 10am AEST on 15 July is midnight UTC
+        assert_eq!(sydney_timestamp("15/07/2022 10:00 AM"), Ok(1657843200));
+    }
+}
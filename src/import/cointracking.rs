@@ -0,0 +1,128 @@
+//! CoinTracking csv import, with the columns `Type`, `Buy Amount`, `Buy Currency`,
+//! `Sell Amount`, `Sell Currency`, `Fee`, `Fee Currency`, `Date`, `Tx-ID`,
+//! `Buy Value in Account Currency` and `Sell Value in Account Currency`. Values are in the
+//! account currency, which must be the quote currency of the import. Dates are UTC. Tags are
+//! read from an optional `Comment` column
+
+use std::collections::HashMap;
+
+use super::{columns, exchanged, field, number, reader, row_id, side, tags, timestamp};
+use crate::{Error, Exchange, Fill, TransactionKind};
+
+const COLUMNS: [&str; 11] = [
+    "Type",
+    "Buy Amount",
+    "Buy Currency",
+    "Sell Amount",
+    "Sell Currency",
+    "Fee",
+    "Fee Currency",
+    "Date",
+    "Tx-ID",
+    "Buy Value in Account Currency",
+    "Sell Value in Account Currency",
+];
+
+pub fn fills(text: &str) -> Result<Vec<Result<Fill, String>>, Error> {
+    let mut reader = reader(text);
+    let indexes = columns(reader.headers()?, COLUMNS)?;
+    let comment = reader
+        .headers()?
+        .iter()
+        .position(|header| header.eq_ignore_ascii_case("comment"));
+
+    let mut seen = HashMap::new();
+    let mut fills = vec![];
+    for record in reader.records() {
+        let record = record?;
+        let fields = indexes.map(|index| field(&record, index));
+        let external_id = match fields[8] {
+            "" => row_id(Exchange::Cointracking, &fields[..8].join(" "), &mut seen),
+            id => format!("{}:{}", Exchange::Cointracking, id),
+        };
+        let note = comment.map_or("", |index| field(&record, index));
+        fills.push(fill(external_id, fields, note));
+    }
+    Ok(fills)
+}
+
+fn fill(external_id: String, fields: [&str; 11], note: &str) -> Result<Fill, String> {
+    let [kind, bought, buy_currency, sold, sell_currency, fee, fee_currency, date, _, buy_value, sell_value] =
+        fields;
+    let kind = match kind.to_ascii_lowercase().as_str() {
+        "trade" => TransactionKind::Buy,
+        "deposit" => TransactionKind::Deposit,
+        "withdrawal" => TransactionKind::Withdrawal,
+        "staking" => TransactionKind::Staking,
+        "interest income" => TransactionKind::Interest,
+        "airdrop" => TransactionKind::Airdrop,
+        "gift" => TransactionKind::Gift,
+        "other fee" => TransactionKind::Fee,
+        _ => return Err(format!("unsupported type {}", kind)),
+    };
+    let received = side(bought, buy_currency)?;
+    let sent = side(sold, sell_currency)?;
+    if kind == TransactionKind::Buy && (received.is_none() || sent.is_none()) {
+        return Err("trade must have a buy and a sell amount".to_string());
+    }
+    if kind != TransactionKind::Buy && received.is_some() == sent.is_some() {
+        return Err(format!(
+            "{} must have only a buy or a sell amount",
+            fields[0]
+        ));
+    }
+    let value = match (received.is_some(), buy_value, sell_value) {
+        (true, "", _) | (false, _, "") => 0.0,
+        (true, value, _) | (false, _, value) => number(value)?,
+    };
+    let (fee, fee_asset) = side(fee, fee_currency)?.unwrap_or_default();
+    Ok(Fill {
+        external_id,
+        executed_at: timestamp(date)?,
+        fee,
+        fee_asset,
+        tags: tags(note),
+        ..exchanged(kind, sent, received, value)?
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_import_csv() {
+        let text = "\
+\"Type\",\"Buy Amount\",\"Buy Currency\",\"Sell Amount\",\"Sell Currency\",\"Fee\",\"Fee Currency\",\"Exchange\",\"Trade-Group\",\"Comment\",\"Date\",\"Tx-ID\",\"Buy Value in Account Currency\",\"Sell Value in Account Currency\"
+\"Trade\",\"5000\",\"AUD\",\"1\",\"ETH\",\"5\",\"AUD\",\"\",\"\",\"\",\"15.04.2022 05:20\",\"T1\",\"5000\",\"5000\"
+\"Staking\",\"2\",\"DOT\",\"\",\"\",\"\",\"\",\"\",\"\",\"\",\"2022-04-15 05:20:00\",\"\",\"50\",\"\"
+\"Withdrawal\",\"1\",\"ETH\",\"1\",\"ETH\",\"\",\"\",\"\",\"\",\"\",\"2022-04-15 05:20:00\",\"T3\",\"\",\"\"
+\"Margin Profit\",\"1\",\"ETH\",\"\",\"\",\"\",\"\",\"\",\"\",\"\",\"2022-04-15 05:20:00\",\"T4\",\"\",\"\"
+";
+
+        let fills = fills(text).expect("failed to read export");
+
+        assert_eq!(
+            fills[0],
+            Ok(Fill {
+                external_id: "cointracking:T1".to_string(),
+                executed_at: 1650000000,
+                kind: TransactionKind::Sell,
+                asset: "ETH".to_string(),
+                amount: 1.0,
+                quote: "AUD".to_string(),
+                total: 5000.0,
+                fee: 5.0,
+                fee_asset: "AUD".to_string(),
+                ..Default::default()
+            })
+        );
+        let staking = fills[1].as_ref().expect("failed to read staking");
+        assert_eq!(
+            (staking.kind, staking.amount, staking.total),
+            (TransactionKind::Staking, 2.0, 50.0)
+        );
+        assert!(fills[2].is_err());
+        assert_eq!(fills[3], Err("unsupported type Margin Profit".to_string()));
+    }
+}
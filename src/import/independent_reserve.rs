@@ -0,0 +1,109 @@
+//! Independent Reserve order history, with the columns `Date Created`, `Order Guid`,
+//! `Order Type`, `Primary Currency`, `Secondary Currency`, `Volume Filled`, `Avg. Price`,
+//! `Brokerage` and `Brokerage Currency`. Times are UTC, orders that weren't filled are skipped
+
+use super::{asset, columns, field, number, reader, timestamp};
+use crate::{Error, Exchange, Fill, TransactionKind};
+
+const COLUMNS: [&str; 9] = [
+    "Date Created",
+    "Order Guid",
+    "Order Type",
+    "Primary Currency",
+    "Secondary Currency",
+    "Volume Filled",
+    "Avg. Price",
+    "Brokerage",
+    "Brokerage Currency",
+];
+
+pub fn fills(text: &str) -> Result<Vec<Result<Fill, String>>, Error> {
+    let mut reader = reader(text);
+    let indexes = columns(reader.headers()?, COLUMNS)?;
+
+    let mut fills = vec![];
+    for record in reader.records() {
+        let record = record?;
+        let fields = indexes.map(|index| field(&record, index));
+        if number(fields[5]).is_ok_and(|filled| filled == 0.0) {
+            continue;
+        }
+        fills.push(fill(fields));
+    }
+    Ok(fills)
+}
+
+fn fill(fields: [&str; 9]) -> Result<Fill, String> {
+    let [date, guid, order_type, primary, secondary, filled, price, fee, fee_currency] = fields;
+    if guid.is_empty() {
+        return Err("missing Order Guid".to_string());
+    }
+    // e.g. LimitBid or MarketOffer
+    let kind = if order_type.ends_with("Bid") {
+        TransactionKind::Buy
+    } else if order_type.ends_with("Offer") {
+        TransactionKind::Sell
+    } else {
+        return Err(format!("unknown order type {}", order_type));
+    };
+    let amount = number(filled)?;
+    let fee = match fee {
+        "" => 0.0,
+        fee => number(fee)?,
+    };
+    Ok(Fill {
+        external_id: format!("{}:{}", Exchange::IndependentReserve, guid),
+        executed_at: timestamp(date)?,
+        kind,
+        asset: asset(primary),
+        amount,
+        quote: asset(secondary),
+        total: amount * number(price)?,
+        fee,
+        fee_asset: if fee > 0.0 {
+            asset(fee_currency)
+        } else {
+            String::new()
+        },
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_order_history() {
+        let text = "\
+Date Created,Order Guid,Order Type,Status,Primary Currency,Secondary Currency,Volume Ordered,Volume Filled,Avg. Price,Value,Brokerage,Brokerage Currency
+2022-04-15T05:20:00.1234Z,c7347e4c-b865-4c94-8f74-d934d4b0b177,LimitBid,Filled,Xbt,Aud,0.1,0.1,\"53,000.00\",5300,26.5,Aud
+2022-04-16T00:00:00Z,0a4ae1d4-1a55-4b2b-a6e4-3ea7a3e1f7c6,MarketOffer,Cancelled,Eth,Aud,1,0,0,0,0,Aud
+2022-04-17T00:00:00Z,5d1ad2a9-8bb4-4f0f-93d2-5f9a3c8f6a3e,MarketOffer,Filled,Eth,Aud,1,1,4000,4000,20,Aud
+";
+
+        let fills = fills(text).expect("failed to read export");
+
+        assert_eq!(fills.len(), 2);
+        assert_eq!(
+            fills[0],
+            Ok(Fill {
+                external_id: "independent_reserve:c7347e4c-b865-4c94-8f74-d934d4b0b177".to_string(),
+                executed_at: 1650000000,
+                kind: TransactionKind::Buy,
+                asset: "BTC".to_string(),
+                amount: 0.1,
+                quote: "AUD".to_string(),
+                total: 5300.0,
+                fee: 26.5,
+                fee_asset: "AUD".to_string(),
+                ..Default::default()
+            })
+        );
+        let sell = fills[1].as_ref().expect("failed to read sell");
+        assert_eq!(
+            (sell.kind, sell.asset.as_str()),
+            (TransactionKind::Sell, "ETH")
+        );
+    }
+}
@@ -0,0 +1,123 @@
+//! Koinly universal csv, with the columns `Date`, `Sent Amount`, `Sent Currency`,
+//! `Received Amount`, `Received Currency`, `Fee Amount`, `Fee Currency`, `Net Worth Amount`,
+//! `Label` and `TxHash`. A row with both sides is a trade, a row with one side is a transfer or
+//! the kind its label says. Dates are UTC and may end in `UTC`. Tags are read from an optional
+//! `Description` column
+
+use std::collections::HashMap;
+
+use super::{columns, exchanged, field, number, reader, row_id, side, tags, timestamp};
+use crate::{Error, Exchange, Fill, TransactionKind};
+
+const COLUMNS: [&str; 10] = [
+    "Date",
+    "Sent Amount",
+    "Sent Currency",
+    "Received Amount",
+    "Received Currency",
+    "Fee Amount",
+    "Fee Currency",
+    "Net Worth Amount",
+    "Label",
+    "TxHash",
+];
+
+pub fn fills(text: &str) -> Result<Vec<Result<Fill, String>>, Error> {
+    let mut reader = reader(text);
+    let indexes = columns(reader.headers()?, COLUMNS)?;
+    let description = reader
+        .headers()?
+        .iter()
+        .position(|header| header.eq_ignore_ascii_case("description"));
+
+    let mut seen = HashMap::new();
+    let mut fills = vec![];
+    for record in reader.records() {
+        let record = record?;
+        let fields = indexes.map(|index| field(&record, index));
+        let external_id = match fields[9] {
+            "" => row_id(Exchange::Koinly, &fields[..8].join(" "), &mut seen),
+            hash => format!("{}:{}", Exchange::Koinly, hash),
+        };
+        let note = description.map_or("", |index| field(&record, index));
+        fills.push(fill(external_id, fields, note));
+    }
+    Ok(fills)
+}
+
+fn fill(external_id: String, fields: [&str; 10], note: &str) -> Result<Fill, String> {
+    let [date, sent, sent_currency, received, received_currency, fee, fee_currency, value, label, _] =
+        fields;
+    let sent = side(sent, sent_currency)?;
+    let received = side(received, received_currency)?;
+    let kind = match (label.to_ascii_lowercase().as_str(), received.is_some()) {
+        ("", true) => TransactionKind::Deposit,
+        ("", false) => TransactionKind::Withdrawal,
+        ("staking" | "reward", true) => TransactionKind::Staking,
+        ("loan interest", true) => TransactionKind::Interest,
+        ("airdrop", true) => TransactionKind::Airdrop,
+        ("gift", false) => TransactionKind::Gift,
+        ("cost", false) => TransactionKind::Fee,
+        (label, _) => return Err(format!("unsupported label {}", label)),
+    };
+    let value = match value {
+        "" => 0.0,
+        value => number(value)?,
+    };
+    let (fee, fee_asset) = side(fee, fee_currency)?.unwrap_or_default();
+    Ok(Fill {
+        external_id,
+        executed_at: timestamp(date.trim_end_matches("UTC"))?,
+        fee,
+        fee_asset,
+        tags: tags(note),
+        ..exchanged(kind, sent, received, value)?
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn reads_universal_csv() {
+        let text = "\
+Date,Sent Amount,Sent Currency,Received Amount,Received Currency,Fee Amount,Fee Currency,Net Worth Amount,Net Worth Currency,Label,Description,TxHash
+2022-04-15 05:20:00 UTC,2500,AUD,0.5,ETH,0.001,BNB,2500,AUD,,,T1
+2022-04-15 05:20 UTC,1,ETH,0.125,BTC,,,4000,AUD,,,
+2022-04-15 05:20 UTC,,,2,DOT,,,50,AUD,staking,,T3
+2022-04-15 05:20 UTC,,,2,DOT,,,50,AUD,mining,,T4
+";
+
+        let fills = fills(text).expect("failed to read export");
+
+        assert_eq!(
+            fills[0],
+            Ok(Fill {
+                external_id: "koinly:T1".to_string(),
+                executed_at: 1650000000,
+                kind: TransactionKind::Buy,
+                asset: "ETH".to_string(),
+                amount: 0.5,
+                quote: "AUD".to_string(),
+                total: 2500.0,
+                fee: 0.001,
+                fee_asset: "BNB".to_string(),
+                ..Default::default()
+            })
+        );
+        let swap = fills[1].as_ref().expect("failed to read swap");
+        assert_eq!(
+            (swap.asset.as_str(), swap.quote.as_str(), swap.value),
+            ("BTC", "ETH", 4000.0)
+        );
+        assert!(swap
+            .external_id
+            .starts_with("koinly:2022-04-15 05:20 UTC 1 ETH"));
+        let staking = fills[2].as_ref().expect("failed to read staking");
+        assert_eq!(
+            (staking.kind, staking.total),
+            (TransactionKind::Staking, 50.0)
+        );
+        assert_eq!(fills[3], Err("unsupported label mining".to_string()));
+    }
+}
@@ -0,0 +1,260 @@
+//! Kraken ledgers, with the columns `txid`, `refid`, `time`, `type`, `subtype`, `asset`,
+//! `amount` and `fee`. A trade is a row for each asset sharing a `refid`, the amount is
+//! negative for the asset spent and the fee is separate. Deposits, withdrawals and staking
+//! rewards are a single row. Moves between the spot and staking wallets aren't trades so
+//! they're skipped. Times are UTC
+
+use std::collections::HashMap;
+
+use super::{asset, columns, field, is_fiat, number, reader, timestamp};
+use crate::{Error, Exchange, Fill, TransactionKind};
+
+const COLUMNS: [&str; 7] = [
+    "txid", "refid", "time", "type", "subtype", "asset", "amount",
+];
+
+/// Assets listed before Kraken used plain codes, coins have an `X` prefix and fiat a `Z` prefix
+const LEGACY: [&str; 16] = [
+    "XETC", "XETH", "XLTC", "XMLN", "XREP", "XXBT", "XXDG", "XXLM", "XXMR", "XXRP", "XZEC", "ZAUD",
+    "ZCAD", "ZEUR", "ZGBP", "ZUSD",
+];
+
+/// A ledger row once its numbers are read
+struct Entry {
+    refid: String,
+    executed_at: i64,
+    asset: String,
+    amount: f64,
+    fee: f64,
+}
+
+/// The asset code of a Kraken asset, staked coins have a suffix e.g. `DOT.S` and ETH staked
+/// before the merge is `ETH2`
+fn kraken_asset(code: &str) -> String {
+    let code = code.split('.').next().unwrap_or_default();
+    let code = if LEGACY.contains(&code) {
+        &code[1..]
+    } else {
+        code
+    };
+    match code {
+        "XDG" => "DOGE".to_string(),
+        "ETH2" => "ETH".to_string(),
+        code => asset(code),
+    }
+}
+
+pub fn fills(text: &str) -> Result<Vec<Result<Fill, String>>, Error> {
+    let mut reader = reader(text);
+    let [txid, refid, time, kind, subtype, asset, amount] = columns(reader.headers()?, COLUMNS)?;
+    let fee = reader
+        .headers()?
+        .iter()
+        .position(|header| header.eq_ignore_ascii_case("fee"));
+
+    // Trades are collected by refid and read once every row is seen, `order` keeps them in
+    // the position of their first row
+    let mut order: Vec<Result<Result<Fill, String>, String>> = vec![];
+    let mut trades: HashMap<String, Vec<Entry>> = HashMap::new();
+    for record in reader.records() {
+        let record = record?;
+        let entry = (|| -> Result<Entry, String> {
+            Ok(Entry {
+                refid: field(&record, refid).to_string(),
+                executed_at: timestamp(field(&record, time))?,
+                asset: kraken_asset(field(&record, asset)),
+                amount: number(field(&record, amount))?,
+                fee: match fee.map(|index| field(&record, index)) {
+                    None | Some("") => 0.0,
+                    Some(fee) => number(fee)?,
+                },
+            })
+        })();
+        let entry = match entry {
+            Ok(v) => v,
+            Err(message) => {
+                order.push(Ok(Err(message)));
+                continue;
+            }
+        };
+        if entry.refid.is_empty() {
+            order.push(Ok(Err(format!(
+                "ledger entry {} has no refid",
+                field(&record, txid)
+            ))));
+            continue;
+        }
+
+        let kind = match (field(&record, kind), field(&record, subtype)) {
+            ("trade", _) | ("spend", _) | ("receive", _) => {
+                if !trades.contains_key(&entry.refid) {
+                    order.push(Err(entry.refid.clone()));
+                }
+                trades.entry(entry.refid.clone()).or_default().push(entry);
+                continue;
+            }
+            ("deposit", _) => TransactionKind::Deposit,
+            ("withdrawal", _) => TransactionKind::Withdrawal,
+            ("staking", _) | ("earn", "reward") => TransactionKind::Staking,
+            ("transfer", _) | ("earn", _) => continue,
+            (other, _) => {
+                order.push(Ok(Err(format!("unknown ledger type {}", other))));
+                continue;
+            }
+        };
+        order.push(Ok(Ok(Fill {
+            external_id: format!("{}:{}", Exchange::Kraken, entry.refid),
+            executed_at: entry.executed_at,
+            kind,
+            asset: entry.asset.clone(),
+            amount: entry.amount.abs(),
+            fee: entry.fee,
+            fee_asset: if entry.fee > 0.0 {
+                entry.asset
+            } else {
+                String::new()
+            },
+            ..Default::default()
+        })));
+    }
+
+    Ok(order
+        .into_iter()
+        .map(|fill| match fill {
+            Ok(fill) => fill,
+            Err(refid) => trade(&refid, &trades[&refid]),
+        })
+        .collect())
+}
+
+/// A trade from its two ledger rows. Selling a coin for fiat is a sell, anything else is a
+/// buy of the coin received
+fn trade(refid: &str, entries: &[Entry]) -> Result<Fill, String> {
+    let (spent, received) = match entries {
+        [a, b] if a.amount < 0.0 && b.amount > 0.0 => (a, b),
+        [a, b] if b.amount < 0.0 && a.amount > 0.0 => (b, a),
+        _ => return Err(format!("trade {} doesn't have a row for each asset", refid)),
+    };
+    let (fee, fee_asset) = match entries.iter().find(|entry| entry.fee > 0.0) {
+        Some(entry) => (entry.fee, entry.asset.clone()),
+        None => (0.0, String::new()),
+    };
+    let (kind, coin, quote) = if is_fiat(&received.asset) {
+        (TransactionKind::Sell, spent, received)
+    } else {
+        (TransactionKind::Buy, received, spent)
+    };
+    Ok(Fill {
+        external_id: format!("{}:{}", Exchange::Kraken, refid),
+        executed_at: received.executed_at,
+        kind,
+        asset: coin.asset.clone(),
+        amount: coin.amount.abs(),
+        quote: quote.asset.clone(),
+        total: quote.amount.abs(),
+        fee,
+        fee_asset,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEDGER: &str = "\
+\"txid\",\"refid\",\"time\",\"type\",\"subtype\",\"aclass\",\"asset\",\"amount\",\"fee\",\"balance\"
+\"L1\",\"T1\",\"2022-04-15 05:20:00\",\"trade\",\"\",\"currency\",\"ZAUD\",-2500.0000,4.0000,1000.0000
+\"L2\",\"T1\",\"2022-04-15 05:20:00\",\"trade\",\"\",\"currency\",\"XETH\",0.5000000000,0.0000000000,0.5000000000
+\"L3\",\"S1\",\"2022-04-16 00:00:00\",\"staking\",\"\",\"currency\",\"DOT.S\",0.2500000000,0.0000000000,10.2500000000
+\"L4\",\"X1\",\"2022-04-16 01:00:00\",\"transfer\",\"spottostaking\",\"currency\",\"DOT.S\",10.0000000000,0.0000000000,10.0000000000
+\"L5\",\"T2\",\"2022-04-17 00:00:00\",\"trade\",\"\",\"currency\",\"XETH\",-0.2500000000,0.0000000000,0.2500000000
+\"L6\",\"D1\",\"2022-04-17 00:30:00\",\"deposit\",\"\",\"currency\",\"ZAUD\",500.0000,0.0000,1500.0000
+\"L7\",\"T2\",\"2022-04-17 00:00:00\",\"trade\",\"\",\"currency\",\"XXBT\",0.0187500000,0.0000300000,0.0187200000
+\"L8\",\"W1\",\"2022-04-18 00:00:00\",\"withdrawal\",\"\",\"currency\",\"XXBT\",-0.0100000000,0.0000500000,0.0086700000
+";
+
+    #[test]
+    fn reads_ledgers() {
+        let fills: Vec<Fill> = fills(LEDGER)
+            .expect("failed to read export")
+            .into_iter()
+            .collect::<Result<_, _>>()
+            .expect("failed to read fill");
+
+        let summary: Vec<(&str, TransactionKind, &str, f64, &str, f64)> = fills
+            .iter()
+            .map(|f| {
+                (
+                    f.external_id.as_str(),
+                    f.kind,
+                    f.asset.as_str(),
+                    f.amount,
+                    f.quote.as_str(),
+                    f.total,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("kraken:T1", TransactionKind::Buy, "ETH", 0.5, "AUD", 2500.0),
+                ("kraken:S1", TransactionKind::Staking, "DOT", 0.25, "", 0.0),
+                (
+                    "kraken:T2",
+                    TransactionKind::Buy,
+                    "BTC",
+                    0.01875,
+                    "ETH",
+                    0.25
+                ),
+                ("kraken:D1", TransactionKind::Deposit, "AUD", 500.0, "", 0.0),
+                (
+                    "kraken:W1",
+                    TransactionKind::Withdrawal,
+                    "BTC",
+                    0.01,
+                    "",
+                    0.0
+                ),
+            ]
+        );
+        assert_eq!((fills[0].fee, fills[0].fee_asset.as_str()), (4.0, "AUD"));
+        assert_eq!(fills[0].executed_at, 1650000000);
+        assert_eq!(fills[4].fee_asset, "BTC");
+    }
+
+    #[test]
+    fn selling_for_fiat_is_a_sell() {
+        let text = "\
+txid,refid,time,type,subtype,aclass,asset,amount,fee,balance
+L1,T1,2022-04-15 05:20:00,trade,,currency,XETH,-1,0,0
+L2,T1,2022-04-15 05:20:00,trade,,currency,ZUSD,3000,5,3000
+L3,T3,2022-04-15 06:00:00,trade,,currency,XETH,-1,0,0
+";
+
+        let fills = fills(text).expect("failed to read export");
+
+        let sell = fills[0].as_ref().expect("failed to read sell");
+        assert_eq!(
+            (
+                sell.kind,
+                sell.asset.as_str(),
+                sell.quote.as_str(),
+                sell.total
+            ),
+            (TransactionKind::Sell, "ETH", "USD", 3000.0)
+        );
+        assert!(fills[1].is_err());
+    }
+
+    #[test]
+    fn reads_asset_codes() {
+        assert_eq!(kraken_asset("XXBT"), "BTC");
+        assert_eq!(kraken_asset("ZAUD"), "AUD");
+        assert_eq!(kraken_asset("XXDG"), "DOGE");
+        assert_eq!(kraken_asset("ETH2.S"), "ETH");
+        assert_eq!(kraken_asset("SOL"), "SOL");
+        assert_eq!(kraken_asset("XTZ"), "XTZ");
+    }
+}
@@ -0,0 +1,405 @@
+//! A user's own spreadsheet of trades, read with a saved `ImportMapping` that names its columns
+//! and says how its dates, numbers, kinds and assets are written. Each row is a single trade
+//! or movement, amounts are read without their sign since the kind says which way coins move
+
+use std::collections::HashMap;
+
+use super::{asset, column, delimited_reader, field, row_id, timestamp};
+use crate::{tax::Date, DecimalSeparator, Error, Fill, ImportMapping, TransactionKind};
+
+/// Start of the external id of rows read with a mapping, followed by the mapping's name
+pub const SOURCE: &str = "custom";
+
+/// Index of each mapped column, `None` for optional columns the mapping doesn't have
+struct Columns {
+    date: usize,
+    kind: usize,
+    asset: usize,
+    amount: usize,
+    price: Option<usize>,
+    total: Option<usize>,
+    quote: Option<usize>,
+    fee: Option<usize>,
+    fee_asset: Option<usize>,
+    id: Option<usize>,
+}
+
+fn optional_column(headers: &csv::StringRecord, name: &str) -> Result<Option<usize>, Error> {
+    if name.is_empty() {
+        return Ok(None);
+    }
+    column(headers, name).map(Some)
+}
+
+/// Reads a spreadsheet into fills in the order of its rows, a row that can't be read is an
+/// error message naming the column. Trades without a quote column are against `fiat`
+pub fn fills(
+    mapping: &ImportMapping,
+    text: &str,
+    fiat: &str,
+) -> Result<Vec<Result<Fill, String>>, Error> {
+    let mut reader = delimited_reader(text, mapping.delimiter_char() as u8);
+    let headers = reader.headers()?.clone();
+    let names = &mapping.columns;
+    let columns = Columns {
+        date: column(&headers, &names.date)?,
+        kind: column(&headers, &names.kind)?,
+        asset: column(&headers, &names.asset)?,
+        amount: column(&headers, &names.amount)?,
+        price: optional_column(&headers, &names.price)?,
+        total: optional_column(&headers, &names.total)?,
+        quote: optional_column(&headers, &names.quote)?,
+        fee: optional_column(&headers, &names.fee)?,
+        fee_asset: optional_column(&headers, &names.fee_asset)?,
+        id: optional_column(&headers, &names.id)?,
+    };
+
+    let source = format!("{}:{}", SOURCE, mapping.name);
+    let mut seen = HashMap::new();
+    let mut fills = vec![];
+    for record in reader.records() {
+        let record = record?;
+        let external_id = match columns.id.map(|index| field(&record, index)) {
+            Some(id) if !id.is_empty() => format!("{}:{}", source, id),
+            _ => {
+                let row = record
+                    .iter()
+                    .collect::<Vec<_>>()
+                    .join(&mapping.delimiter_char().to_string());
+                row_id(&source, &row, &mut seen)
+            }
+        };
+        fills.push(fill(mapping, &columns, &record, external_id, fiat));
+    }
+    Ok(fills)
+}
+
+fn fill(
+    mapping: &ImportMapping,
+    columns: &Columns,
+    record: &csv::StringRecord,
+    external_id: String,
+    fiat: &str,
+) -> Result<Fill, String> {
+    let names = &mapping.columns;
+    let optional = |index: Option<usize>| index.map(|index| field(record, index)).unwrap_or("");
+    let number = |name: &str, text: &str| {
+        localized_number(text, mapping.decimal)
+            .map(f64::abs)
+            .map_err(|err| format!("{}: {}", name, err))
+    };
+
+    let executed_at = date(field(record, columns.date), mapping)
+        .map_err(|err| format!("{}: {}", names.date, err))?;
+    let kind = kind(mapping, field(record, columns.kind))
+        .map_err(|err| format!("{}: {}", names.kind, err))?;
+    let asset = normalize(mapping, field(record, columns.asset));
+    if asset.is_empty() {
+        return Err(format!("{}: must not be empty", names.asset));
+    }
+    let amount = number(&names.amount, field(record, columns.amount))?;
+    let total = match (optional(columns.total), optional(columns.price)) {
+        ("", "") => 0.0,
+        ("", price) => amount * number(&names.price, price)?,
+        (total, _) => number(&names.total, total)?,
+    };
+    let fee = match optional(columns.fee) {
+        "" => 0.0,
+        fee => number(&names.fee, fee)?,
+    };
+    let quote = match optional(columns.quote) {
+        "" => fiat.to_string(),
+        code => normalize(mapping, code),
+    };
+    let fee_asset = match optional(columns.fee_asset) {
+        _ if fee == 0.0 => String::new(),
+        "" => quote.clone(),
+        code => normalize(mapping, code),
+    };
+    Ok(Fill {
+        external_id,
+        executed_at,
+        kind,
+        asset,
+        amount,
+        quote,
+        total,
+        fee,
+        fee_asset,
+        ..Default::default()
+    })
+}
+
+/// The kind a value of the kind column is, from the mapping or by its name
+fn kind(mapping: &ImportMapping, value: &str) -> Result<TransactionKind, String> {
+    match mapping
+        .kinds
+        .iter()
+        .find(|(from, _)| from.trim().eq_ignore_ascii_case(value))
+    {
+        Some((_, kind)) => Ok(*kind),
+        None => value.to_ascii_lowercase().parse(),
+    }
+}
+
+/// The code an asset is tracked as, from the mapping or as it's written
+fn normalize(mapping: &ImportMapping, code: &str) -> String {
+    match mapping
+        .assets
+        .iter()
+        .find(|(from, _)| from.trim().eq_ignore_ascii_case(code.trim()))
+    {
+        Some((_, to)) => asset(to),
+        None => asset(code),
+    }
+}
+
+/// A number with `decimal` before the fraction, the other separator and spaces are ignored
+/// as thousands separators
+fn localized_number(text: &str, decimal: DecimalSeparator) -> Result<f64, String> {
+    let (thousands, point) = match decimal {
+        DecimalSeparator::Point => (',', '.'),
+        DecimalSeparator::Comma => ('.', ','),
+    };
+    text.chars()
+        .filter(|c| *c != thousands && !c.is_whitespace())
+        .map(|c| if c == point { '.' } else { c })
+        .collect::<String>()
+        .parse::<f64>()
+        .map_err(|_| format!("invalid number {}", text))
+}
+
+/// Seconds since the epoch of a date read with the mapping's `date_format`, an empty format
+/// reads the same dates as exchange exports. `%s` is already UTC so the offset isn't applied
+fn date(text: &str, mapping: &ImportMapping) -> Result<i64, String> {
+    let format = mapping.date_format.as_str();
+    if format.is_empty() {
+        return Ok(timestamp(text)? - mapping.utc_offset);
+    }
+
+    let invalid = || format!("{} doesn't match date format {}", text, format);
+    let mut rest = text.trim();
+    let (mut year, mut month, mut day) = (1970, 1, 1);
+    let (mut hour, mut minute, mut second) = (0, 0, 0);
+    let mut pm = None;
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        let directive = match c {
+            '%' => chars.next().ok_or_else(invalid)?,
+            c => {
+                rest = rest.strip_prefix(c).ok_or_else(invalid)?;
+                continue;
+            }
+        };
+        match directive {
+            '%' => rest = rest.strip_prefix('%').ok_or_else(invalid)?,
+            'p' => {
+                pm = match rest.get(..2).map(str::to_ascii_uppercase).as_deref() {
+                    Some("AM") => Some(false),
+                    Some("PM") => Some(true),
+                    _ => return Err(invalid()),
+                };
+                rest = &rest[2..];
+            }
+            directive => {
+                let width = match directive {
+                    'Y' => 4,
+                    'f' | 's' => usize::MAX,
+                    _ => 2,
+                };
+                let digits = rest
+                    .chars()
+                    .take(width)
+                    .take_while(char::is_ascii_digit)
+                    .count();
+                if digits == 0 {
+                    return Err(invalid());
+                }
+                let (digits, after) = rest.split_at(digits);
+                rest = after;
+                if directive == 'f' {
+                    continue;
+                }
+                let value: i64 = digits.parse().map_err(|_| invalid())?;
+                match directive {
+                    'Y' => year = value,
+                    'y' => year = 2000 + value,
+                    'm' => month = value,
+                    'd' => day = value,
+                    'H' | 'I' => hour = value,
+                    'M' => minute = value,
+                    'S' => second = value,
+                    's' => return Ok(value),
+                    _ => return Err(invalid()),
+                }
+            }
+        }
+    }
+    match pm {
+        Some(true) if hour < 12 => hour += 12,
+        Some(false) if hour == 12 => hour = 0,
+        _ => {}
+    }
+    if !rest.is_empty()
+        || !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return Err(invalid());
+    }
+    Ok(Date::new(year, month, day).start(mapping.utc_offset) + hour * 3600 + minute * 60 + second)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ColumnMapping;
+
+    fn mapping() -> ImportMapping {
+        ImportMapping {
+            name: "depot".to_string(),
+            columns: ColumnMapping {
+                date: "Datum".to_string(),
+                kind: "Typ".to_string(),
+                asset: "Coin".to_string(),
+                amount: "Menge".to_string(),
+                price: "Kurs".to_string(),
+                fee: "Gebühr".to_string(),
+                ..Default::default()
+            },
+            date_format: "%d.%m.%Y %H:%M".to_string(),
+            utc_offset: 2 * 60 * 60,
+            delimiter: ";".to_string(),
+            decimal: DecimalSeparator::Comma,
+            kinds: HashMap::from([
+                ("Kauf".to_string(), TransactionKind::Buy),
+                ("Verkauf".to_string(), TransactionKind::Sell),
+            ]),
+            assets: HashMap::from([("Ether".to_string(), "ETH".to_string())]),
+        }
+    }
+
+    #[test]
+    fn reads_spreadsheet_with_mapping() {
+        let text = "\
+Datum;Typ;Coin;Menge;Kurs;Gebühr
+15.04.2022 07:20;Kauf;Ether;0,5;5.000,00;1,5
+16.04.2022 07:20;verkauf;XBT;-0,1;60.000;
+16.04.2022 07:20;verkauf;XBT;-0,1;60.000;
+17.04.2022 02:00;staking;ETH;0,01;;
+";
+
+        let fills = fills(&mapping(), text, "EUR").expect("failed to read spreadsheet");
+
+        assert_eq!(
+            fills[0],
+            Ok(Fill {
+                external_id: "custom:depot:15.04.2022 07:20;Kauf;Ether;0,5;5.000,00;1,5"
+                    .to_string(),
+                executed_at: 1650000000,
+                kind: TransactionKind::Buy,
+                asset: "ETH".to_string(),
+                amount: 0.5,
+                quote: "EUR".to_string(),
+                total: 2500.0,
+                fee: 1.5,
+                fee_asset: "EUR".to_string(),
+                ..Default::default()
+            })
+        );
+        let sell = fills[1].as_ref().expect("failed to read sell");
+        assert_eq!(
+            (sell.kind, sell.asset.as_str(), sell.amount, sell.total),
+            (TransactionKind::Sell, "BTC", 0.1, 6000.0)
+        );
+        assert!(sell.fee_asset.is_empty());
+        let repeated = fills[2].as_ref().expect("failed to read repeated sell");
+        assert_eq!(repeated.external_id, format!("{}#2", sell.external_id));
+        // Income without a price is valued from the price history
+        let staking = fills[3].as_ref().expect("failed to read staking");
+        assert_eq!(
+            (staking.kind, staking.total),
+            (TransactionKind::Staking, 0.0)
+        );
+    }
+
+    #[test]
+    fn reports_the_column_of_row_errors() {
+        let text = "\
+Datum;Typ;Coin;Menge;Kurs;Gebühr
+15.04.2022;Kauf;ETH;0,5;5000;
+15.04.2022 07:20;Tausch;ETH;0,5;5000;
+15.04.2022 07:20;Kauf;ETH;viel;5000;
+";
+
+        let fills = fills(&mapping(), text, "EUR").expect("failed to read spreadsheet");
+
+        assert_eq!(
+            fills[0],
+            Err("Datum: 15.04.2022 doesn't match date format %d.%m.%Y %H:%M".to_string())
+        );
+        assert_eq!(
+            fills[1],
+            Err("Typ: unknown transaction kind tausch".to_string())
+        );
+        assert_eq!(fills[2], Err("Menge: invalid number viel".to_string()));
+    }
+
+    #[test]
+    fn uses_id_column_when_mapped() {
+        let mut mapping = mapping();
+        mapping.columns.id = "Nr".to_string();
+        let text = "\
+Nr;Datum;Typ;Coin;Menge;Kurs;Gebühr
+A1;15.04.2022 07:20;Kauf;ETH;0,5;5000;
+";
+
+        let fills = fills(&mapping, text, "EUR").expect("failed to read spreadsheet");
+
+        let fill = fills[0].as_ref().expect("failed to read row");
+        assert_eq!(fill.external_id, "custom:depot:A1");
+
+        mapping.columns.total = "Summe".to_string();
+        assert!(super::fills(&mapping, text, "EUR").is_err());
+    }
+
+    #[test]
+    fn reads_dates_with_format() {
+        let with_format = |format: &str| ImportMapping {
+            date_format: format.to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            date("04/15/22 5:20:00 AM", &with_format("%m/%d/%y %I:%M:%S %p")),
+            Ok(1650000000)
+        );
+        assert_eq!(
+            date("20220415T052000.250", &with_format("%Y%m%dT%H%M%S.%f")),
+            Ok(1650000000)
+        );
+        assert_eq!(date("1650000000", &with_format("%s")), Ok(1650000000));
+        assert_eq!(date("2022-04-15 05:20", &with_format("")), Ok(1650000000));
+        assert!(date("2022-13-15", &with_format("%Y-%m-%d")).is_err());
+        assert!(date("2022-04-15 extra", &with_format("%Y-%m-%d")).is_err());
+    }
+
+    #[test]
+    fn reads_localized_numbers() {
+        assert_eq!(
+            localized_number("1.234,5", DecimalSeparator::Comma),
+            Ok(1234.5)
+        );
+        assert_eq!(
+            localized_number("1 234,5", DecimalSeparator::Comma),
+            Ok(1234.5)
+        );
+        assert_eq!(
+            localized_number("1,234.5", DecimalSeparator::Point),
+            Ok(1234.5)
+        );
+        assert!(localized_number("", DecimalSeparator::Point).is_err());
+    }
+}
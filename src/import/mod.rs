@@ -0,0 +1,482 @@
+//! Reads trade histories exported from exchanges. Each exchange has its own module that reads
+//! its csv into fills, which are then priced and turned into transactions. Columns are found by
+//! their header so the order they're in doesn't matter
+
+pub mod binance;
+pub mod coinspot;
+pub mod cointracking;
+pub mod independent_reserve;
+pub mod koinly;
+pub mod kraken;
+pub mod mapping;
+
+use std::{collections::HashMap, fmt};
+use ulid::Ulid;
+
+use crate::{
+    tax::Date, Error, Exchange, Fill, SwapRequest, Transaction, TransactionKind, Treatment,
+};
+
+/// Currencies that are never tracked as coins, a trade against one of them is a buy or sell
+pub const FIAT: [&str; 7] = ["AUD", "USD", "EUR", "GBP", "CAD", "JPY", "NZD"];
+
+/// Reads an export into fills in the order they appear, a row that can't be read is an error
+/// message in its place. Fails if the file isn't csv or is missing a column
+pub fn parse(exchange: Exchange, text: &str) -> Result<Vec<Result<Fill, String>>, Error> {
+    match exchange {
+        Exchange::Binance => binance::fills(text),
+        Exchange::Kraken => kraken::fills(text),
+        Exchange::Coinspot => coinspot::fills(text),
+        Exchange::IndependentReserve => independent_reserve::fills(text),
+        Exchange::Koinly => koinly::fills(text),
+        Exchange::Cointracking => cointracking::fills(text),
+    }
+}
+
+/// A csv reader for an export, fields are trimmed and rows can have missing columns
+fn reader(text: &str) -> csv::Reader<&[u8]> {
+    delimited_reader(text, b',')
+}
+
+/// A csv reader for an export with fields separated by `delimiter`
+fn delimited_reader(text: &str, delimiter: u8) -> csv::Reader<&[u8]> {
+    csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(text.as_bytes())
+}
+
+/// Index of the column `name` in the header row
+fn column(headers: &csv::StringRecord, name: &str) -> Result<usize, Error> {
+    Ok(headers
+        .iter()
+        .position(|header| header.eq_ignore_ascii_case(name))
+        .ok_or(format!("missing column {}", name))?)
+}
+
+/// Index of each of the `names` in the header row
+fn columns<const N: usize>(
+    headers: &csv::StringRecord,
+    names: [&str; N],
+) -> Result<[usize; N], Error> {
+    let mut indexes = [0; N];
+    for (index, name) in indexes.iter_mut().zip(names) {
+        *index = column(headers, name)?;
+    }
+    Ok(indexes)
+}
+
+/// The field at `index`, empty if the row is too short
+fn field(record: &csv::StringRecord, index: usize) -> &str {
+    record.get(index).unwrap_or_default()
+}
+
+/// A number that may have thousands separators
+fn number(text: &str) -> Result<f64, String> {
+    text.replace(',', "")
+        .parse::<f64>()
+        .map_err(|_| format!("invalid number {}", text))
+}
+
+/// A number followed by its asset e.g. `0.5ETH`
+fn amount_of(text: &str) -> Result<(f64, String), String> {
+    let split = text
+        .find(|c: char| c.is_ascii_alphabetic())
+        .ok_or(format!("{} has no asset", text))?;
+    Ok((number(&text[..split])?, asset(&text[split..])))
+}
+
+/// An amount and its asset, `None` if the side is empty
+fn side(amount: &str, currency: &str) -> Result<Option<(f64, String)>, String> {
+    match amount {
+        "" => Ok(None),
+        amount => Ok(Some((number(amount)?, asset(currency)))),
+    }
+}
+
+/// Tags in a note column separated by `;`, as tax software exports are written
+fn tags(text: &str) -> Vec<String> {
+    text.split(';')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// An asset code as it's used for symbols, exchanges use `XBT` for bitcoin
+fn asset(code: &str) -> String {
+    match code.trim().to_ascii_uppercase().as_str() {
+        "XBT" => "BTC".to_string(),
+        code => code.to_string(),
+    }
+}
+
+fn is_fiat(asset: &str) -> bool {
+    FIAT.contains(&asset)
+}
+
+/// Seconds since the epoch of a date and time as if it was UTC. Dates are `YYYY-MM-DD`,
+/// `DD/MM/YYYY` or `DD.MM.YYYY`, times are `HH:MM` with optional seconds, fractions of a second and `AM` or
+/// `PM`, and the date and time can be separated by `T` with a trailing `Z` as in ISO 8601
+fn timestamp(text: &str) -> Result<i64, String> {
+    let invalid = || format!("invalid date {}", text);
+    let text = text.trim().trim_end_matches('Z');
+    let (date, time) = text.split_once(['T', ' ']).unwrap_or((text, "00:00"));
+    let parts: Vec<i64> = date
+        .split(['-', '/', '.'])
+        .map(|part| part.parse::<i64>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| invalid())?;
+    let date = match (parts.as_slice(), date.contains(['/', '.'])) {
+        ([year, month, day], false) => Date::new(*year, *month, *day),
+        ([day, month, year], true) => Date::new(*year, *month, *day),
+        _ => return Err(invalid()),
+    };
+    if !(1..=12).contains(&date.month) || !(1..=31).contains(&date.day) {
+        return Err(invalid());
+    }
+
+    let (time, meridiem) = match time.trim().split_once(' ') {
+        Some((time, meridiem)) => (time, Some(meridiem.trim().to_ascii_uppercase())),
+        None => (time.trim(), None),
+    };
+    let parts: Vec<f64> = time
+        .split(':')
+        .map(|part| part.parse::<f64>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| invalid())?;
+    let (mut hour, minute, second) = match parts.as_slice() {
+        [hour, minute] => (*hour as i64, *minute as i64, 0),
+        [hour, minute, second] => (*hour as i64, *minute as i64, *second as i64),
+        _ => return Err(invalid()),
+    };
+    match meridiem.as_deref() {
+        Some("AM") if hour == 12 => hour = 0,
+        Some("PM") if hour < 12 => hour += 12,
+        Some("AM") | Some("PM") | None => {}
+        Some(_) => return Err(invalid()),
+    }
+    Ok(date.start(0) + hour * 3600 + minute * 60 + second)
+}
+
+/// A fill from a row of tax software that has what was sent and what was received, a row with
+/// both is a trade and `kind` is the kind of a row with only one. Selling a coin for fiat is a
+/// sell and any other trade is a buy of what was received. `value` is the fiat value of what
+/// was received, or what was sent if nothing was
+fn exchanged(
+    kind: TransactionKind,
+    sent: Option<(f64, String)>,
+    received: Option<(f64, String)>,
+    value: f64,
+) -> Result<Fill, String> {
+    let fill = match (sent, received) {
+        (Some((total, quote)), Some((amount, asset))) if !is_fiat(&asset) => Fill {
+            kind: TransactionKind::Buy,
+            asset,
+            amount,
+            value: if is_fiat(&quote) { 0.0 } else { value },
+            quote,
+            total,
+            ..Default::default()
+        },
+        (Some((amount, asset)), Some((total, quote))) => Fill {
+            kind: TransactionKind::Sell,
+            asset,
+            amount,
+            quote,
+            total,
+            ..Default::default()
+        },
+        (Some((amount, asset)), None) | (None, Some((amount, asset))) => Fill {
+            kind,
+            asset,
+            amount,
+            total: value,
+            ..Default::default()
+        },
+        (None, None) => return Err("row doesn't send or receive anything".to_string()),
+    };
+    Ok(fill)
+}
+
+/// Builds an id from a row for exports that don't have one, a row that's repeated is numbered
+/// so every trade is kept and importing the same file again gives the same ids
+fn row_id(source: impl fmt::Display, row: &str, seen: &mut HashMap<String, usize>) -> String {
+    let count = seen.entry(row.to_string()).or_default();
+    *count += 1;
+    if *count == 1 {
+        format!("{}:{}", source, row)
+    } else {
+        format!("{}:{}#{}", source, row, count)
+    }
+}
+
+/// Symbols that need a price from the price history at the fill's `executed_at` to turn it into
+/// transactions, with `fiat` as the user's quote currency
+pub fn prices_needed(fill: &Fill, fiat: &str) -> Vec<String> {
+    if fill.asset == fiat {
+        return vec![];
+    }
+    let mut assets = vec![];
+    match fill.kind {
+        TransactionKind::Buy | TransactionKind::Sell if fill.quote != fiat && fill.value == 0.0 => {
+            assets.push(&fill.asset);
+            assets.push(&fill.quote);
+        }
+        TransactionKind::Buy | TransactionKind::Sell => {}
+        kind if kind.treatment() == Treatment::Income && fill.total == 0.0 => {
+            assets.push(&fill.asset)
+        }
+        _ => {}
+    }
+    if fill.fee > 0.0
+        && !fill.fee_asset.is_empty()
+        && fill.fee_asset != fiat
+        && trade_fee_price(fill, fiat).is_none()
+    {
+        assets.push(&fill.fee_asset);
+    }
+    let mut symbols: Vec<String> = vec![];
+    for asset in assets {
+        let symbol = format!("{}{}", asset, fiat);
+        if !symbols.contains(&symbol) {
+            symbols.push(symbol);
+        }
+    }
+    symbols
+}
+
+/// Price of the fee from the trade itself, when it was paid in the coin bought or sold for
+/// fiat or in either coin of a swap that has its value. `None` if it needs the price history
+fn trade_fee_price(fill: &Fill, fiat: &str) -> Option<f64> {
+    if fill.fee_asset == fill.asset && fill.quote == fiat && fill.amount > 0.0 {
+        Some(fill.total / fill.amount)
+    } else if fill.value > 0.0 && fill.fee_asset == fill.asset && fill.amount > 0.0 {
+        Some(fill.value / fill.amount)
+    } else if fill.value > 0.0 && fill.fee_asset == fill.quote && fill.total > 0.0 {
+        Some(fill.value / fill.total)
+    } else {
+        None
+    }
+}
+
+/// Turns a fill into the transactions for `username`, a trade against `fiat` is a buy or sell
+/// and any other trade is a swap with a leg for each coin. `prices` has the price of each of
+/// [`prices_needed`] when the fill was executed. A movement of `fiat` has no transactions
+pub fn transactions(
+    fill: &Fill,
+    username: &str,
+    fiat: &str,
+    prices: &HashMap<String, f64>,
+) -> Result<Vec<Transaction>, String> {
+    let symbol = |asset: &str| format!("{}{}", asset, fiat);
+    let price = |asset: &str| {
+        prices.get(&symbol(asset)).copied().ok_or(format!(
+            "no price history for {} at the time of the trade",
+            symbol(asset)
+        ))
+    };
+    if fill.asset == fiat {
+        return Ok(vec![]);
+    }
+    let trade_price = if fill.amount > 0.0 {
+        fill.total / fill.amount
+    } else {
+        0.0
+    };
+    // A fee in the quote currency has no asset
+    let (fee_asset, fee_price) =
+        if fill.fee <= 0.0 || fill.fee_asset.is_empty() || fill.fee_asset == fiat {
+            (String::new(), 0.0)
+        } else if let Some(price) = trade_fee_price(fill, fiat) {
+            (symbol(&fill.fee_asset), price)
+        } else {
+            (symbol(&fill.fee_asset), price(&fill.fee_asset)?)
+        };
+
+    let mut transactions = match fill.kind {
+        TransactionKind::Buy | TransactionKind::Sell if fill.quote != fiat => {
+            let (sell, sell_amount, buy, buy_amount) = match fill.kind {
+                TransactionKind::Buy => (&fill.quote, fill.total, &fill.asset, fill.amount),
+                _ => (&fill.asset, fill.amount, &fill.quote, fill.total),
+            };
+            let swap = SwapRequest {
+                username: username.to_string(),
+                sell_coin: symbol(sell),
+                sell_amount,
+                buy_coin: symbol(buy),
+                buy_amount,
+                executed_at: fill.executed_at,
+                fee: fill.fee,
+                fee_asset: fee_asset.clone(),
+                allow_short: false,
+            };
+            swap.validate()?;
+            // Both legs are worth the value of the trade when the file has it
+            let (sell_price, buy_price) = if fill.value > 0.0 {
+                (fill.value / sell_amount, fill.value / buy_amount)
+            } else {
+                (price(sell)?, price(buy)?)
+            };
+            swap.legs(&Ulid::new().to_string(), sell_price, buy_price, fee_price)
+                .to_vec()
+        }
+        kind => vec![Transaction {
+            username: username.to_string(),
+            coin: symbol(&fill.asset),
+            kind,
+            amount: fill.amount,
+            price: match kind.treatment() {
+                Treatment::Income if fill.total == 0.0 => price(&fill.asset)?,
+                _ => trade_price,
+            },
+            fee: fill.fee,
+            fee_asset,
+            fee_price,
+            executed_at: fill.executed_at,
+            ..Default::default()
+        }],
+    };
+    for transaction in &mut transactions {
+        transaction.external_id = fill.external_id.clone();
+        transaction.tags = fill.tags.clone();
+        transaction.validate()?;
+    }
+    Ok(transactions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill(kind: TransactionKind, asset: &str, amount: f64, quote: &str, total: f64) -> Fill {
+        Fill {
+            external_id: "kraken:T1".to_string(),
+            executed_at: 1650000000,
+            kind,
+            asset: asset.to_string(),
+            amount,
+            quote: quote.to_string(),
+            total,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn reads_dates_and_times() {
+        assert_eq!(timestamp("2022-04-15 05:20:00"), Ok(1650000000));
+        assert_eq!(timestamp("2022-04-15T05:20:00.5Z"), Ok(1650000000));
+        assert_eq!(timestamp("15/04/2022 5:20 AM"), Ok(1650000000));
+        assert_eq!(
+            timestamp("15/04/2022 5:20:00 PM"),
+            Ok(1650000000 + 12 * 3600)
+        );
+        assert_eq!(
+            timestamp("15/04/2022 12:10 AM"),
+            Ok(1650000000 - 5 * 3600 - 600)
+        );
+        assert!(timestamp("2022-13-01 00:00:00").is_err());
+        assert!(timestamp("yesterday").is_err());
+    }
+
+    #[test]
+    fn reads_amounts_with_assets() {
+        assert_eq!(amount_of("0.5ETH"), Ok((0.5, "ETH".to_string())));
+        assert_eq!(amount_of("1,250.75AUD"), Ok((1250.75, "AUD".to_string())));
+        assert_eq!(amount_of("0.1XBT"), Ok((0.1, "BTC".to_string())));
+        assert!(amount_of("12").is_err());
+    }
+
+    #[test]
+    fn repeated_rows_get_their_own_id() {
+        let mut seen = HashMap::new();
+        let first = row_id(Exchange::Binance, "a", &mut seen);
+        let second = row_id(Exchange::Binance, "a", &mut seen);
+        assert_eq!(first, "binance:a");
+        assert_eq!(second, "binance:a#2");
+        assert_eq!(row_id(Exchange::Binance, "b", &mut seen), "binance:b");
+    }
+
+    #[test]
+    fn trade_against_fiat_is_a_buy() {
+        let mut buy = fill(TransactionKind::Buy, "ETH", 0.5, "AUD", 2500.0);
+        buy.fee = 0.001;
+        buy.fee_asset = "ETH".to_string();
+        assert!(prices_needed(&buy, "AUD").is_empty());
+
+        let transactions =
+            transactions(&buy, "testuser", "AUD", &HashMap::new()).expect("failed to map fill");
+
+        assert_eq!(
+            transactions,
+            vec![Transaction {
+                username: "testuser".to_string(),
+                coin: "ETHAUD".to_string(),
+                kind: TransactionKind::Buy,
+                amount: 0.5,
+                price: 5000.0,
+                fee: 0.001,
+                fee_asset: "ETHAUD".to_string(),
+                fee_price: 5000.0,
+                external_id: "kraken:T1".to_string(),
+                executed_at: 1650000000,
+                ..Default::default()
+            }]
+        );
+    }
+
+    #[test]
+    fn trade_between_coins_is_a_swap() {
+        let mut buy = fill(TransactionKind::Buy, "ETH", 10.0, "BTC", 0.75);
+        buy.fee = 5.0;
+        buy.fee_asset = "AUD".to_string();
+        assert_eq!(prices_needed(&buy, "AUD"), vec!["ETHAUD", "BTCAUD"]);
+        let prices = HashMap::from([
+            ("ETHAUD".to_string(), 4000.0),
+            ("BTCAUD".to_string(), 53000.0),
+        ]);
+
+        let legs = transactions(&buy, "testuser", "AUD", &prices).expect("failed to map fill");
+
+        assert_eq!(legs.len(), 2);
+        assert_eq!(
+            (legs[0].coin.as_str(), legs[0].kind, legs[0].amount),
+            ("BTCAUD", TransactionKind::Sell, 0.75)
+        );
+        assert_eq!(legs[0].price, 53000.0);
+        assert_eq!(
+            (legs[1].coin.as_str(), legs[1].kind, legs[1].amount),
+            ("ETHAUD", TransactionKind::Buy, 10.0)
+        );
+        assert_eq!((legs[1].fee, legs[1].fee_asset.as_str()), (5.0, ""));
+        assert_eq!(legs[0].swap_id, legs[1].swap_id);
+        assert!(legs.iter().all(|leg| leg.external_id == "kraken:T1"));
+
+        assert!(transactions(&buy, "testuser", "AUD", &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn income_is_priced_and_fiat_is_ignored() {
+        let staking = fill(TransactionKind::Staking, "DOT", 2.0, "", 0.0);
+        assert_eq!(prices_needed(&staking, "AUD"), vec!["DOTAUD"]);
+        let prices = HashMap::from([("DOTAUD".to_string(), 25.0)]);
+
+        let income =
+            transactions(&staking, "testuser", "AUD", &prices).expect("failed to map fill");
+        assert_eq!(income[0].price, 25.0);
+
+        // Income with its value in the file keeps it
+        let valued = fill(TransactionKind::Staking, "DOT", 2.0, "AUD", 60.0);
+        assert!(prices_needed(&valued, "AUD").is_empty());
+        let income =
+            transactions(&valued, "testuser", "AUD", &HashMap::new()).expect("failed to map fill");
+        assert_eq!(income[0].price, 30.0);
+
+        let deposit = fill(TransactionKind::Deposit, "AUD", 1000.0, "", 0.0);
+        assert_eq!(
+            transactions(&deposit, "testuser", "AUD", &prices),
+            Ok(vec![])
+        );
+    }
+}
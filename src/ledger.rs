@@ -0,0 +1,769 @@
+//! Folds a user's transactions into what they currently hold. Transactions are never changed
+//! so everything is derived from the full log each time, with voids and amends applied by
+//! [`effective`] before anything is calculated
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::{
+    sort_by_execution, Correction, FeeSummary, IncomeReport, IncomeSummary, Transaction,
+    TransactionKind, Treatment,
+};
+
+/// Applies voids and amends to the log, leaving the transactions currently in effect ordered
+/// by execution. Corrections are applied in the order they were recorded, so the latest wins
+pub fn effective(transactions: &[Transaction]) -> Vec<Transaction> {
+    let mut effective = vec![];
+    let mut current: HashMap<&str, Option<&Transaction>> = HashMap::new();
+    for transaction in transactions.iter().filter(|t| t.correction.is_none()) {
+        // Transactions stored before ids existed can't be corrected
+        if transaction.id.is_empty() {
+            effective.push(transaction.clone());
+        } else {
+            current.insert(&transaction.id, Some(transaction));
+        }
+    }
+
+    let mut corrections: Vec<&Transaction> = transactions
+        .iter()
+        .filter(|t| t.correction.is_some())
+        .collect();
+    corrections.sort_by_key(|t| t.recorded_at);
+    for correction in corrections {
+        if let Some(entry) = current.get_mut(correction.corrects.as_str()) {
+            *entry = match correction.correction {
+                Some(Correction::Amend) => Some(correction),
+                _ => None,
+            };
+        }
+    }
+
+    effective.extend(current.into_values().flatten().cloned());
+    sort_by_execution(&mut effective);
+    effective
+}
+
+/// The transactions executed at or before `timestamp`, used to fold holdings at a point in
+/// time. Corrections recorded later still apply, they fix what happened back then
+pub fn executed_by(transactions: &[Transaction], timestamp: i64) -> Vec<Transaction> {
+    transactions
+        .iter()
+        .filter(|t| t.executed_at <= timestamp)
+        .cloned()
+        .collect()
+}
+
+/// Id of the original transaction that the transaction with `id` is, or corrects
+fn original_id<'a>(transactions: &'a [Transaction], id: &str) -> Option<&'a str> {
+    transactions
+        .iter()
+        .find(|t| !id.is_empty() && t.id == id)
+        .map(|t| {
+            if t.corrects.is_empty() {
+                t.id.as_str()
+            } else {
+                t.corrects.as_str()
+            }
+        })
+}
+
+/// Builds the correction of the transaction with `id`, which can be the original or one of
+/// its amends. The `amendment` replaces the transaction, `None` voids it. Returns a message
+/// if the transaction doesn't exist or has already been voided
+pub fn correct(
+    transactions: &[Transaction],
+    id: &str,
+    amendment: Option<Transaction>,
+) -> Result<Transaction, String> {
+    let original =
+        original_id(transactions, id).ok_or(format!("transaction {} doesn't exist", id))?;
+    let current = effective(transactions)
+        .into_iter()
+        .find(|t| t.id == original || t.corrects == original)
+        .ok_or(format!("transaction {} has been voided", id))?;
+    let (correction, transaction) = match amendment {
+        Some(amendment) => (Correction::Amend, amendment),
+        None => (Correction::Void, current),
+    };
+    Ok(Transaction {
+        id: String::new(),
+        recorded_at: 0,
+        correction: Some(correction),
+        corrects: original.to_string(),
+        ..transaction
+    })
+}
+
+/// Ids of the original transactions of every leg in the swap that the transaction with `id`
+/// belongs to, empty if it isn't part of a swap. Legs are only corrected together so a swap is
+/// never left one sided
+pub fn swap_legs(transactions: &[Transaction], id: &str) -> Vec<String> {
+    let swap_id = match original_id(transactions, id)
+        .and_then(|original| transactions.iter().find(|t| t.id == original))
+    {
+        Some(t) if !t.swap_id.is_empty() => &t.swap_id,
+        _ => return vec![],
+    };
+    transactions
+        .iter()
+        .filter(|t| t.correction.is_none() && t.swap_id == *swap_id)
+        .map(|t| t.id.clone())
+        .collect()
+}
+
+/// The original transaction followed by each of its corrections in the order they were
+/// recorded, empty if there's no transaction with `id`
+pub fn audit_chain(transactions: &[Transaction], id: &str) -> Vec<Transaction> {
+    let original = match original_id(transactions, id) {
+        Some(v) => v,
+        None => return vec![],
+    };
+    let mut chain: Vec<Transaction> = transactions
+        .iter()
+        .filter(|t| t.id == original || t.corrects == original)
+        .cloned()
+        .collect();
+    chain.sort_by_key(|t| (t.correction.is_some(), t.recorded_at));
+    chain
+}
+
+/// Total amount held of each coin keyed by symbol, coins that were fully sold will be 0.
+/// Fees paid in a coin reduce the balance of that coin
+pub fn balances(transactions: &[Transaction]) -> HashMap<String, f64> {
+    let mut balances = HashMap::new();
+    for transaction in transactions {
+        *balances.entry(transaction.coin.clone()).or_insert(0.0) += transaction.signed_amount();
+        if !transaction.fee_asset.is_empty() {
+            *balances.entry(transaction.fee_asset.clone()).or_insert(0.0) -= transaction.fee;
+        }
+    }
+    balances
+}
+
+/// Balances within this of 0 are treated as 0, so selling everything that was bought in
+/// several parts isn't rejected because of floating point error
+const BALANCE_TOLERANCE: f64 = 1e-9;
+
+/// A new transaction that would remove more of a coin than was held when it was executed, or
+/// would leave a later transaction removing more than was held then
+#[derive(Debug, Clone, PartialEq)]
+pub struct Overdraft {
+    // Id of the new transaction
+    pub id: String,
+    pub coin: String,
+    // When the balance was overdrawn, later than the new transaction if it overdrew a later one
+    pub executed_at: i64,
+    // Balance just before the overdrawn transaction
+    pub held: f64,
+    // How much more than `held` the overdrawn transaction removes
+    pub shortfall: f64,
+}
+
+impl fmt::Display for Overdraft {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "only {} {} was held at {}, {} short. Set allow_short to open a short position",
+            self.held, self.coin, self.executed_at, self.shortfall
+        )
+    }
+}
+
+/// Every transaction in the timeline that removes more of a coin than was held when it was
+/// executed, except those with `allow_short`. The timeline must be ordered by execution
+fn overdrawn(timeline: &[Transaction]) -> Vec<Overdraft> {
+    let mut overdrawn = vec![];
+    let mut balances: HashMap<&str, f64> = HashMap::new();
+    for transaction in timeline {
+        let mut changes = vec![(transaction.coin.as_str(), transaction.signed_amount())];
+        if !transaction.fee_asset.is_empty() {
+            changes.push((transaction.fee_asset.as_str(), -transaction.fee));
+        }
+        if !transaction.allow_short {
+            let overdraft = changes.iter().find_map(|(coin, change)| {
+                let held = balances.get(coin).copied().unwrap_or_default().max(0.0);
+                let removed = -changes
+                    .iter()
+                    .filter(|(c, _)| c == coin)
+                    .map(|(_, change)| change)
+                    .sum::<f64>();
+                (*change < 0.0 && removed - held > BALANCE_TOLERANCE).then(|| Overdraft {
+                    id: transaction.id.clone(),
+                    coin: coin.to_string(),
+                    executed_at: transaction.executed_at,
+                    held,
+                    shortfall: removed - held,
+                })
+            });
+            overdrawn.extend(overdraft);
+        }
+        for (coin, change) in changes {
+            *balances.entry(coin).or_insert(0.0) += change;
+        }
+    }
+    overdrawn
+}
+
+/// Shortfall of each overdrawn transaction in the timeline keyed by id
+fn shortfalls(timeline: &[Transaction]) -> HashMap<String, f64> {
+    overdrawn(timeline)
+        .into_iter()
+        .map(|overdraft| (overdraft.id, overdraft.shortfall))
+        .collect()
+}
+
+/// The first overdraft in the timeline that isn't in `before`, or is worse than it was. Logs
+/// that were already overdrawn, e.g. from before overdrafts were checked, don't block changes
+/// that don't make them worse
+fn caused(before: &HashMap<String, f64>, timeline: &[Transaction]) -> Option<Overdraft> {
+    overdrawn(timeline).into_iter().find(|overdraft| {
+        before
+            .get(&overdraft.id)
+            .is_none_or(|shortfall| overdraft.shortfall - shortfall > BALANCE_TOLERANCE)
+    })
+}
+
+/// Checks new transactions against the balances projected from the user's transactions, from
+/// when each was executed onwards. A new transaction is overdrawn if it removes more of a coin
+/// than was held then, or if it leaves any later transaction removing more than was held.
+/// New transactions are checked in execution order and the ones that aren't overdrawn count
+/// towards what's held for the ones after. The legs of a swap are checked together, if one is
+/// overdrawn every leg is. New transactions must already be recorded so they have ids, those
+/// with `allow_short` can go short themselves but can't overdraw later transactions
+pub fn overdrafts(transactions: &[Transaction], new: &[Transaction]) -> Vec<Overdraft> {
+    let mut new = new.to_vec();
+    sort_by_execution(&mut new);
+    let mut groups: Vec<Vec<Transaction>> = vec![];
+    for transaction in new {
+        let swap = groups.iter_mut().find(|group| {
+            !transaction.swap_id.is_empty() && group[0].swap_id == transaction.swap_id
+        });
+        match swap {
+            Some(group) => group.push(transaction),
+            None => groups.push(vec![transaction]),
+        }
+    }
+
+    let mut overdrafts = vec![];
+    let mut accepted = effective(transactions);
+    let mut before = shortfalls(&accepted);
+    for group in groups {
+        let mut timeline = [accepted.as_slice(), &group].concat();
+        sort_by_execution(&mut timeline);
+        match caused(&before, &timeline) {
+            Some(overdraft) => overdrafts.extend(group.iter().map(|transaction| Overdraft {
+                id: transaction.id.clone(),
+                ..overdraft.clone()
+            })),
+            None => {
+                before = shortfalls(&timeline);
+                accepted = timeline;
+            }
+        }
+    }
+    overdrafts
+}
+
+/// The overdraft that appending the corrections would cause anywhere in the log, e.g. voiding
+/// a buy that a later sale depends on. It's reported against the first correction
+pub fn correction_overdraft(
+    transactions: &[Transaction],
+    corrections: &[Transaction],
+) -> Option<Overdraft> {
+    let before = shortfalls(&effective(transactions));
+    let corrected = effective(&[transactions, corrections].concat());
+    caused(&before, &corrected).map(|overdraft| Overdraft {
+        id: corrections
+            .first()
+            .map(|t| t.id.clone())
+            .unwrap_or_default(),
+        ..overdraft
+    })
+}
+
+/// Totals of the fees paid in each asset ordered by asset, fees paid in the quote currency
+/// have an empty asset
+pub fn fee_summary(transactions: &[Transaction]) -> Vec<FeeSummary> {
+    let mut summaries: HashMap<&str, FeeSummary> = HashMap::new();
+    for transaction in transactions.iter().filter(|t| t.fee > 0.0) {
+        let summary = summaries
+            .entry(&transaction.fee_asset)
+            .or_insert_with(|| FeeSummary {
+                asset: transaction.fee_asset.clone(),
+                ..Default::default()
+            });
+        summary.amount += transaction.fee;
+        summary.value += transaction.fee_value();
+        summary.transactions += 1;
+    }
+    let mut summaries: Vec<FeeSummary> = summaries.into_values().collect();
+    summaries.sort_by(|a, b| a.asset.cmp(&b.asset));
+    summaries
+}
+
+/// Totals of the income received between `from` and `to` epoch seconds for each coin and
+/// kind of income, valued at the price it was recorded with
+pub fn income_report(transactions: &[Transaction], from: i64, to: i64) -> IncomeReport {
+    let mut summaries: HashMap<(&str, TransactionKind), IncomeSummary> = HashMap::new();
+    for transaction in transactions.iter().filter(|t| {
+        t.kind.treatment() == Treatment::Income && t.executed_at >= from && t.executed_at < to
+    }) {
+        let summary = summaries
+            .entry((&transaction.coin, transaction.kind))
+            .or_insert_with(|| IncomeSummary {
+                coin: transaction.coin.clone(),
+                kind: transaction.kind,
+                ..Default::default()
+            });
+        summary.amount += transaction.amount;
+        summary.value += transaction.value();
+        summary.transactions += 1;
+    }
+    let mut income: Vec<IncomeSummary> = summaries.into_values().collect();
+    income.sort_by(|a, b| (&a.coin, a.kind.as_str()).cmp(&(&b.coin, b.kind.as_str())));
+    IncomeReport {
+        from,
+        to,
+        total: income.iter().map(|summary| summary.value).sum(),
+        income,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recorded(id: &str, recorded_at: i64, transaction: Transaction) -> Transaction {
+        Transaction {
+            id: id.to_string(),
+            executed_at: 1650000000,
+            recorded_at,
+            ..transaction
+        }
+    }
+
+    fn transaction(coin: &str, kind: TransactionKind, amount: f64) -> Transaction {
+        Transaction {
+            username: "testuser".to_string(),
+            coin: coin.to_string(),
+            kind,
+            amount,
+            price: 1.0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn balances_follow_kinds() {
+        let transactions = vec![
+            transaction("ETHAUD", TransactionKind::Buy, 2.0),
+            transaction("ETHAUD", TransactionKind::Staking, 0.5),
+            transaction("ETHAUD", TransactionKind::Sell, 1.0),
+            transaction("ETHAUD", TransactionKind::Fee, 0.1),
+            transaction("BTCAUD", TransactionKind::Deposit, 1.0),
+            transaction("BTCAUD", TransactionKind::Withdrawal, 0.25),
+            transaction("ADAAUD", TransactionKind::Airdrop, 100.0),
+            transaction("ADAAUD", TransactionKind::Gift, 100.0),
+        ];
+
+        let balances = balances(&transactions);
+
+        assert!((balances["ETHAUD"] - 1.4).abs() < 1e-9);
+        assert_eq!(balances["BTCAUD"], 0.75);
+        assert_eq!(balances["ADAAUD"], 0.0);
+    }
+
+    #[test]
+    fn fees_reduce_fee_asset_balance() {
+        let mut buy = transaction("ETHAUD", TransactionKind::Buy, 2.0);
+        buy.fee = 0.01;
+        buy.fee_asset = "BNBAUD".to_string();
+        buy.fee_price = 500.0;
+        let mut sell = transaction("ETHAUD", TransactionKind::Sell, 1.0);
+        sell.fee = 0.5;
+
+        let balances = balances(&[transaction("BNBAUD", TransactionKind::Buy, 1.0), buy, sell]);
+
+        assert_eq!(balances["ETHAUD"], 1.0);
+        assert_eq!(balances["BNBAUD"], 0.99);
+    }
+
+    #[test]
+    fn fee_summary_groups_by_asset() {
+        let mut bnb_fee = transaction("ETHAUD", TransactionKind::Buy, 2.0);
+        bnb_fee.fee = 0.01;
+        bnb_fee.fee_asset = "BNBAUD".to_string();
+        bnb_fee.fee_price = 500.0;
+        let mut quote_fee = transaction("ETHAUD", TransactionKind::Sell, 1.0);
+        quote_fee.fee = 2.5;
+        let mut other_quote_fee = transaction("BTCAUD", TransactionKind::Buy, 1.0);
+        other_quote_fee.fee = 1.5;
+
+        let summary = fee_summary(&[
+            bnb_fee,
+            quote_fee,
+            transaction("ETHAUD", TransactionKind::Buy, 1.0),
+            other_quote_fee,
+        ]);
+
+        assert_eq!(
+            summary,
+            vec![
+                FeeSummary {
+                    asset: "".to_string(),
+                    amount: 4.0,
+                    value: 4.0,
+                    transactions: 2,
+                },
+                FeeSummary {
+                    asset: "BNBAUD".to_string(),
+                    amount: 0.01,
+                    value: 5.0,
+                    transactions: 1,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn income_report_groups_by_coin_and_kind() {
+        let income =
+            |coin: &str, kind: TransactionKind, amount: f64, executed_at: i64| Transaction {
+                price: 2.0,
+                executed_at,
+                ..transaction(coin, kind, amount)
+            };
+
+        let report = income_report(
+            &[
+                income("ETHAUD", TransactionKind::Staking, 1.0, 100),
+                income("ETHAUD", TransactionKind::Staking, 0.5, 150),
+                income("ETHAUD", TransactionKind::Airdrop, 3.0, 160),
+                income("ADAAUD", TransactionKind::Interest, 10.0, 170),
+                income("ETHAUD", TransactionKind::Staking, 1.0, 200),
+                income("ETHAUD", TransactionKind::Buy, 1.0, 120),
+            ],
+            100,
+            200,
+        );
+
+        let rows: Vec<(&str, TransactionKind, f64, f64, usize)> = report
+            .income
+            .iter()
+            .map(|s| (s.coin.as_str(), s.kind, s.amount, s.value, s.transactions))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                ("ADAAUD", TransactionKind::Interest, 10.0, 20.0, 1),
+                ("ETHAUD", TransactionKind::Airdrop, 3.0, 6.0, 1),
+                ("ETHAUD", TransactionKind::Staking, 1.5, 3.0, 2),
+            ]
+        );
+        assert_eq!(report.total, 29.0);
+    }
+
+    #[test]
+    fn no_transactions_no_balances() {
+        assert!(balances(&[]).is_empty());
+    }
+
+    #[test]
+    fn void_removes_transaction() {
+        let log = vec![
+            recorded("a", 1, transaction("ETHAUD", TransactionKind::Buy, 2.0)),
+            recorded("b", 2, transaction("ETHAUD", TransactionKind::Buy, 1.0)),
+        ];
+        let void = correct(&log, "a", None).expect("failed to void");
+        assert_eq!(void.correction, Some(Correction::Void));
+        assert_eq!(void.corrects, "a");
+        assert_eq!(void.amount, 2.0);
+
+        let log = [log, vec![recorded("c", 3, void)]].concat();
+        let effective = effective(&log);
+        assert_eq!(effective.len(), 1);
+        assert_eq!(balances(&effective)["ETHAUD"], 1.0);
+
+        assert!(correct(&log, "a", None).is_err());
+        assert!(correct(&log, "missing", None).is_err());
+    }
+
+    #[test]
+    fn latest_amend_replaces_transaction() {
+        let log = vec![recorded(
+            "a",
+            1,
+            transaction("ETHAUD", TransactionKind::Buy, 20.0),
+        )];
+        let first = correct(
+            &log,
+            "a",
+            Some(transaction("ETHAUD", TransactionKind::Buy, 2.0)),
+        )
+        .expect("failed to amend");
+        let log = [log, vec![recorded("b", 2, first)]].concat();
+
+        // Amending the amend still refers to the original transaction
+        let second = correct(
+            &log,
+            "b",
+            Some(transaction("ETHAUD", TransactionKind::Buy, 3.0)),
+        )
+        .expect("failed to amend");
+        assert_eq!(second.corrects, "a");
+        let log = [log, vec![recorded("c", 3, second)]].concat();
+
+        assert_eq!(balances(&effective(&log))["ETHAUD"], 3.0);
+
+        let chain = audit_chain(&log, "c");
+        let ids: Vec<&str> = chain.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(ids, ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn amend_then_void() {
+        let log = vec![recorded(
+            "a",
+            1,
+            transaction("ETHAUD", TransactionKind::Buy, 20.0),
+        )];
+        let amend = correct(
+            &log,
+            "a",
+            Some(transaction("ETHAUD", TransactionKind::Buy, 2.0)),
+        )
+        .expect("failed to amend");
+        let log = [log, vec![recorded("b", 2, amend)]].concat();
+        let void = correct(&log, "b", None).expect("failed to void");
+        assert_eq!(void.amount, 2.0);
+        let log = [log, vec![recorded("c", 3, void)]].concat();
+
+        assert!(effective(&log).is_empty());
+        assert!(correct(&log, "b", None).is_err());
+    }
+
+    #[test]
+    fn finds_every_leg_of_a_swap() {
+        let leg = |id: &str, kind: TransactionKind| Transaction {
+            swap_id: "swap".to_string(),
+            ..recorded(id, 1, transaction("ETHAUD", kind, 1.0))
+        };
+        let log = vec![
+            leg("a", TransactionKind::Sell),
+            leg("b", TransactionKind::Buy),
+            recorded("c", 2, transaction("ETHAUD", TransactionKind::Buy, 1.0)),
+        ];
+        let amend = correct(
+            &log,
+            "a",
+            Some(transaction("ETHAUD", TransactionKind::Sell, 0.5)),
+        )
+        .expect("failed to amend");
+        let log = [log, vec![recorded("d", 3, amend)]].concat();
+
+        assert_eq!(swap_legs(&log, "b"), ["a", "b"]);
+        // An amend of a leg finds the legs of the original
+        assert_eq!(swap_legs(&log, "d"), ["a", "b"]);
+        assert!(swap_legs(&log, "c").is_empty());
+        assert!(swap_legs(&log, "missing").is_empty());
+    }
+
+    #[test]
+    fn transactions_without_ids_stay_in_effect() {
+        let log = vec![
+            transaction("ETHAUD", TransactionKind::Buy, 2.0),
+            transaction("ETHAUD", TransactionKind::Sell, 1.0),
+        ];
+        assert_eq!(effective(&log), log);
+        assert!(correct(&log, "", None).is_err());
+    }
+
+    #[test]
+    fn holdings_at_a_point_in_time() {
+        let mut later = recorded("b", 2, transaction("ETHAUD", TransactionKind::Sell, 1.0));
+        later.executed_at = 1660000000;
+        let log = vec![
+            recorded("a", 1, transaction("ETHAUD", TransactionKind::Buy, 2.0)),
+            later,
+        ];
+
+        let before = executed_by(&effective(&log), 1655000000);
+        assert_eq!(balances(&before)["ETHAUD"], 2.0);
+        let after = executed_by(&effective(&log), 1660000000);
+        assert_eq!(balances(&after)["ETHAUD"], 1.0);
+    }
+
+    #[test]
+    fn rejects_selling_more_than_held() {
+        let log = vec![recorded(
+            "a",
+            1,
+            transaction("ETHAUD", TransactionKind::Buy, 2.0),
+        )];
+        let mut sell = recorded("b", 2, transaction("ETHAUD", TransactionKind::Sell, 5.0));
+
+        assert_eq!(
+            overdrafts(&log, &[sell.clone()]),
+            vec![Overdraft {
+                id: "b".to_string(),
+                coin: "ETHAUD".to_string(),
+                executed_at: 1650000000,
+                held: 2.0,
+                shortfall: 3.0,
+            }]
+        );
+
+        sell.allow_short = true;
+        assert!(overdrafts(&log, &[sell]).is_empty());
+    }
+
+    #[test]
+    fn checks_balance_when_executed() {
+        let mut later_buy = recorded("a", 1, transaction("ETHAUD", TransactionKind::Buy, 2.0));
+        later_buy.executed_at = 1660000000;
+        let log = vec![
+            recorded("b", 2, transaction("ETHAUD", TransactionKind::Buy, 0.1)),
+            recorded("c", 3, transaction("ETHAUD", TransactionKind::Buy, 0.2)),
+            later_buy,
+        ];
+
+        let mut backdated = recorded("d", 4, transaction("ETHAUD", TransactionKind::Sell, 1.0));
+        backdated.executed_at = 1655000000;
+        assert_eq!(overdrafts(&log, &[backdated.clone()]).len(), 1);
+
+        backdated.amount = 0.3;
+        assert!(overdrafts(&log, &[backdated]).is_empty());
+    }
+
+    #[test]
+    fn backdated_sale_overdraws_later_sale() {
+        let at = |id: &str, kind: TransactionKind, amount: f64, executed_at: i64| Transaction {
+            executed_at,
+            ..recorded(id, 1, transaction("ETHAUD", kind, amount))
+        };
+        let log = vec![
+            at("a", TransactionKind::Buy, 2.0, 1650000000),
+            at("b", TransactionKind::Sell, 2.0, 1670000000),
+        ];
+
+        // Enough was held for the backdated sale itself, but not for the sale after it
+        let backdated = at("c", TransactionKind::Sell, 1.0, 1660000000);
+        assert_eq!(
+            overdrafts(&log, std::slice::from_ref(&backdated)),
+            vec![Overdraft {
+                id: "c".to_string(),
+                coin: "ETHAUD".to_string(),
+                executed_at: 1670000000,
+                held: 1.0,
+                shortfall: 1.0,
+            }]
+        );
+
+        // A short doesn't get to overdraw a later sale either
+        let short = Transaction {
+            allow_short: true,
+            ..backdated
+        };
+        assert_eq!(overdrafts(&log, &[short]).len(), 1);
+
+        let buy = at("d", TransactionKind::Buy, 1.0, 1655000000);
+        let backdated = at("e", TransactionKind::Sell, 1.0, 1660000000);
+        assert!(overdrafts(&log, &[buy, backdated]).is_empty());
+    }
+
+    #[test]
+    fn existing_overdrafts_dont_block_new_transactions() {
+        let log = vec![
+            recorded("a", 1, transaction("ETHAUD", TransactionKind::Sell, 1.0)),
+            recorded("b", 2, transaction("BTCAUD", TransactionKind::Buy, 1.0)),
+        ];
+        let sell = recorded("c", 3, transaction("BTCAUD", TransactionKind::Sell, 1.0));
+        assert!(overdrafts(&log, &[sell]).is_empty());
+
+        let worse = Transaction {
+            executed_at: 1600000000,
+            ..recorded("d", 3, transaction("ETHAUD", TransactionKind::Sell, 1.0))
+        };
+        assert_eq!(overdrafts(&log, &[worse]).len(), 1);
+    }
+
+    #[test]
+    fn swap_legs_are_overdrawn_together() {
+        let log = vec![recorded(
+            "a",
+            1,
+            transaction("ETHAUD", TransactionKind::Buy, 1.0),
+        )];
+        let leg = |id: &str, coin: &str, kind: TransactionKind, amount: f64| Transaction {
+            swap_id: "swap".to_string(),
+            ..recorded(id, 2, transaction(coin, kind, amount))
+        };
+        let mut spend = recorded("d", 3, transaction("BTCAUD", TransactionKind::Sell, 0.1));
+        spend.executed_at = 1660000000;
+        let new = vec![
+            leg("b", "ETHAUD", TransactionKind::Sell, 2.0),
+            leg("c", "BTCAUD", TransactionKind::Buy, 0.1),
+            spend,
+        ];
+
+        // The bought leg isn't held because the swap was overdrawn, so it can't be spent
+        let ids: Vec<String> = overdrafts(&log, &new).into_iter().map(|o| o.id).collect();
+        assert_eq!(ids, ["b", "c", "d"]);
+    }
+
+    #[test]
+    fn voiding_a_buy_overdraws_later_sale() {
+        let log = vec![
+            recorded("a", 1, transaction("ETHAUD", TransactionKind::Buy, 2.0)),
+            Transaction {
+                executed_at: 1660000000,
+                ..recorded("b", 2, transaction("ETHAUD", TransactionKind::Sell, 2.0))
+            },
+        ];
+        let void = recorded("c", 3, correct(&log, "a", None).expect("failed to void"));
+
+        let overdraft = correction_overdraft(&log, &[void]).expect("should be overdrawn");
+        assert_eq!(overdraft.id, "c");
+        assert_eq!(overdraft.shortfall, 2.0);
+
+        let amend = recorded(
+            "d",
+            3,
+            correct(
+                &log,
+                "a",
+                Some(transaction("ETHAUD", TransactionKind::Buy, 3.0)),
+            )
+            .expect("failed to amend"),
+        );
+        assert!(correction_overdraft(&log, &[amend]).is_none());
+    }
+
+    #[test]
+    fn fees_can_overdraw_fee_asset() {
+        let mut buy = recorded("a", 1, transaction("ETHAUD", TransactionKind::Buy, 2.0));
+        buy.fee = 0.01;
+        buy.fee_asset = "BNBAUD".to_string();
+
+        let overdrawn = overdrafts(&[], &[buy]);
+        assert_eq!(overdrawn[0].coin, "BNBAUD");
+        assert_eq!(overdrawn[0].shortfall, 0.01);
+    }
+
+    #[test]
+    fn overdrawn_transactions_are_left_out() {
+        let log = vec![recorded(
+            "a",
+            1,
+            transaction("ETHAUD", TransactionKind::Buy, 2.0),
+        )];
+        let new = vec![
+            recorded("b", 2, transaction("ETHAUD", TransactionKind::Sell, 5.0)),
+            recorded("c", 3, transaction("ETHAUD", TransactionKind::Sell, 2.0)),
+        ];
+
+        let overdrawn = overdrafts(&log, &new);
+        assert_eq!(overdrawn.len(), 1);
+        assert_eq!(overdrawn[0].id, "b");
+    }
+}
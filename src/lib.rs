@@ -8,3 +8,23 @@ pub use res::Res;
 
 pub mod errors;
 pub use errors::Error;
+
+pub mod store;
+
+pub mod guard;
+pub use guard::{PriceGuard, Verdict};
+
+pub mod ledger;
+
+pub mod basis;
+
+pub mod tax;
+
+pub mod harvest;
+
+pub mod import;
+
+pub mod export;
+
+#[cfg(test)]
+mod testing;
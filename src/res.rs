@@ -8,7 +8,7 @@
 //! will contain an `"error"` key with the returned error that caused the lambda to fail.
 //!
 //! For methods with a body, it overwrites the other fields, used to return a map or struct
-//! after serializing it
+//! after serializing it, or a csv file
 use lambda_http::{Body, Error, IntoResponse, Response};
 use serde::Serialize;
 use tracing::{error, info};
@@ -22,6 +22,10 @@ pub struct Res {
     error: String,
     #[serde(skip_serializing_if = "String::is_empty")]
     body: String,
+    #[serde(skip_serializing)]
+    content_type: String,
+    #[serde(skip_serializing)]
+    headers: Vec<(String, String)>,
 }
 
 impl Res {
@@ -43,6 +47,29 @@ impl Res {
         }
     }
 
+    /// Returns a csv body, anything that isn't in the csv such as a pagination cursor
+    /// can be added with `with_header`
+    pub fn ok_csv(body: &str) -> Res {
+        Res {
+            status: 200,
+            body: body.to_string(),
+            content_type: "text/csv".to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Adds a header to the response, it's exposed to the browser through CORS
+    pub fn with_header(mut self, name: &str, value: &str) -> Res {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Changes the status code, for a custom body that isn't a plain 200 such as a partial success
+    pub fn with_status(mut self, status: u16) -> Res {
+        self.status = status;
+        self
+    }
+
     /// when failing to parse json from body, returns the error in the `error` key
     pub fn parse_body_error(error: serde_json::Error) -> Res {
         Res {
@@ -63,6 +90,35 @@ impl Res {
         }
     }
 
+    /// when the path params refer to something that doesn't exist
+    pub fn not_found(message: &str) -> Res {
+        Res {
+            status: 404,
+            message: message.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// when the request is well formed but can't be processed e.g. an idempotency key reused
+    /// with a different body
+    pub fn unprocessable_entity(message: &str) -> Res {
+        Res {
+            status: 422,
+            message: message.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// when the request can't be done because of the current state of the resource, use the
+    /// message to explain what is in the way
+    pub fn conflict(message: &str) -> Res {
+        Res {
+            status: 409,
+            message: message.to_string(),
+            ..Default::default()
+        }
+    }
+
     /// when failing to parse json from a reqwest response, returns the error in the `error` key
     pub fn parse_response_error(message: &str, error: reqwest::Error) -> Res {
         Res {
@@ -82,17 +138,36 @@ impl Res {
             ..Default::default()
         }
     }
+
+    /// Returns a json response that was sent earlier, e.g. when a request is retried with
+    /// the same idempotency key
+    pub fn replay(status: u16, body: &str) -> Res {
+        Res {
+            status,
+            body: body.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// The body that will be sent, either the custom body or the other fields as json
+    pub fn body(&self) -> String {
+        if !self.body.is_empty() {
+            self.body.clone()
+        } else {
+            serde_json::to_string(&self).expect("failed to convert struct to json string")
+        }
+    }
 }
 
 impl IntoResponse for Res {
     /// Custom implementation for into_response so lambda_http can return a response in the correct format
     /// If it's 400 or above will log to stderr which will count as a failed execution in AWS lambda stats
     fn into_response(self) -> Response<Body> {
-        let body = if !self.body.is_empty() {
-            self.body
-        } else {
-            serde_json::to_string(&self).expect("failed to convert struct to json string")
-        };
+        let body = self.body();
 
         info!(r#"{{"status_code": {}}}"#, self.status);
         if self.status < 400 {
@@ -101,10 +176,21 @@ impl IntoResponse for Res {
             error!("{}", body)
         }
 
-        Response::builder()
+        let mut builder = Response::builder()
             .header("Access-Control-Allow-Origin", "https://holdcrypt.com")
             .header("Access-Control-Allow-Methods", "*")
-            .header("Access-Control-Allow-Headers", "*")
+            .header("Access-Control-Allow-Headers", "*");
+        if !self.content_type.is_empty() {
+            builder = builder.header("Content-Type", &self.content_type);
+        }
+        if !self.headers.is_empty() {
+            let names: Vec<&str> = self.headers.iter().map(|(name, _)| name.as_str()).collect();
+            builder = builder.header("Access-Control-Expose-Headers", names.join(", "));
+        }
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+        builder
             .status(self.status)
             .body(Body::Text(body))
             .expect("unable to build http::Response")
@@ -113,7 +199,7 @@ impl IntoResponse for Res {
 
 #[cfg(test)]
 mod tests {
-    use lambda_http::IntoResponse;
+    use lambda_http::{Body, IntoResponse};
     use serde::{Deserialize, Serialize};
     use serde_json::json;
 
@@ -139,6 +225,21 @@ mod tests {
         assert_eq!(res.body(), body.into_response().body());
     }
 
+    #[test]
+    fn ok_with_csv() {
+        let res = Res::ok_csv("coin,amount\nETHAUD,1\n")
+            .with_header("Next-Cursor", "abc")
+            .into_response();
+
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.headers()["Content-Type"], "text/csv");
+        assert_eq!(res.headers()["Next-Cursor"], "abc");
+        assert_eq!(
+            res.body(),
+            &Body::Text("coin,amount\nETHAUD,1\n".to_string())
+        );
+    }
+
     #[test]
     fn parse_body_error() {
         #[derive(Serialize, Deserialize, Debug, Default)]
@@ -175,11 +276,58 @@ mod tests {
         )
     }
 
+    #[test]
+    fn not_found() {
+        let res = Res::not_found("testing not found").into_response();
+        assert_eq!(res.status(), 404);
+        assert_eq!(
+            res.body(),
+            json!({"message": "testing not found"})
+                .into_response()
+                .body()
+        )
+    }
+
+    #[test]
+    fn unprocessable_entity() {
+        let res = Res::unprocessable_entity("testing an unprocessable entity").into_response();
+        assert_eq!(res.status(), 422);
+        assert_eq!(
+            res.body(),
+            json!({"message": "testing an unprocessable entity"})
+                .into_response()
+                .body()
+        )
+    }
+
+    #[test]
+    fn replay_stored_response() {
+        let original = Res::conflict("testing a replay");
+        let res = Res::replay(original.status(), &original.body()).into_response();
+        assert_eq!(res.status(), 409);
+        assert_eq!(
+            res.body(),
+            json!({"message": "testing a replay"})
+                .into_response()
+                .body()
+        )
+    }
+
+    #[test]
+    fn conflict() {
+        let res = Res::conflict("testing a conflict").into_response();
+        assert_eq!(res.status(), 409);
+        assert_eq!(
+            res.body(),
+            json!({"message": "testing a conflict"})
+                .into_response()
+                .body()
+        )
+    }
+
     #[test]
     fn parse_response_error() {
-        let error = reqwest::get("fakeurl")
-            .err()
-            .expect("failed to generate error");
+        let error = reqwest::get("fakeurl").expect_err("failed to generate error");
         let res = Res::parse_response_error("testing a reqwest error", error);
         assert_eq!(res.message, "testing a reqwest error");
         assert_eq!(res.error, "relative URL without a base");
@@ -190,7 +338,7 @@ mod tests {
 
     #[test]
     fn internal_server_error() {
-        let error = std::io::Error::new(std::io::ErrorKind::Other, "test internal error");
+        let error = std::io::Error::other("test internal error");
         let res = Res::internal_server_error(
             "testing an arbitrary internal server error",
             Box::new(error),
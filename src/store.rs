@@ -0,0 +1,1401 @@
+//! Helpers for reading and writing items in dynamodb that are shared across lambdas,
+//! attributes that were added after the tables were created are optional so older
+//! items still parse
+
+use aws_sdk_dynamodb::{
+    client::fluent_builders::UpdateItem,
+    model::{AttributeValue, ConditionCheck, Put, PutRequest, TransactWriteItem, WriteRequest},
+    types::SdkError,
+    Client,
+};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use ulid::Ulid;
+
+use crate::{
+    sort_by_execution, CoinMetadata, CoinStats, Correction, CostBasisMethod, Error, ImportMapping,
+    Jurisdiction, LotSelection, Transaction, TransactionKind, TransactionPage, TransactionQuery,
+};
+
+/// A single dynamodb item as returned from a scan or query
+pub type Item = HashMap<String, AttributeValue>;
+
+/// Gets a string attribute that must exist
+pub fn get_s<'a>(item: &'a Item, key: &str) -> Result<&'a String, Error> {
+    Ok(item
+        .get(key)
+        .ok_or(format!("{} key doesn't exist", key))?
+        .as_s()
+        .map_err(|_| std::io::Error::other(format!("{} not of type string", key)))?)
+}
+
+/// Gets a string attribute, returning an empty string if the attribute doesn't exist
+pub fn get_s_or_default(item: &Item, key: &str) -> Result<String, Error> {
+    match item.get(key) {
+        Some(_) => Ok(get_s(item, key)?.clone()),
+        None => Ok(String::new()),
+    }
+}
+
+/// Gets a number attribute, returning `None` if the attribute doesn't exist
+pub fn get_n_opt(item: &Item, key: &str) -> Result<Option<f64>, Error> {
+    match item.get(key) {
+        Some(value) => Ok(Some(
+            value
+                .as_n()
+                .map_err(|_| std::io::Error::other(format!("{} not of type number", key)))?
+                .parse::<f64>()?,
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Gets a number attribute, returning 0 if the attribute doesn't exist
+pub fn get_n_or_default(item: &Item, key: &str) -> Result<f64, Error> {
+    Ok(get_n_opt(item, key)?.unwrap_or_default())
+}
+
+/// Gets a whole number attribute such as a timestamp, returning 0 if the attribute doesn't exist
+pub fn get_i64_or_default(item: &Item, key: &str) -> Result<i64, Error> {
+    match item.get(key) {
+        Some(value) => Ok(value
+            .as_n()
+            .map_err(|_| std::io::Error::other(format!("{} not of type number", key)))?
+            .parse::<i64>()?),
+        None => Ok(0),
+    }
+}
+
+/// Reads the 24 hour stats from a coin item, coins that haven't been refreshed
+/// since stats were added will have all stats set to 0
+pub fn coin_stats(item: &Item) -> Result<CoinStats, Error> {
+    Ok(CoinStats {
+        price_change_percent: get_n_or_default(item, "price_change_percent")?,
+        high_price: get_n_or_default(item, "high_price")?,
+        low_price: get_n_or_default(item, "low_price")?,
+        volume: get_n_or_default(item, "volume")?,
+        quote_volume: get_n_or_default(item, "quote_volume")?,
+    })
+}
+
+/// Converts the 24 hour stats into attributes to be stored on a coin item
+pub fn coin_stats_attributes(stats: &CoinStats) -> Vec<(String, AttributeValue)> {
+    vec![
+        (
+            "price_change_percent".to_string(),
+            AttributeValue::N(stats.price_change_percent.to_string()),
+        ),
+        (
+            "high_price".to_string(),
+            AttributeValue::N(stats.high_price.to_string()),
+        ),
+        (
+            "low_price".to_string(),
+            AttributeValue::N(stats.low_price.to_string()),
+        ),
+        (
+            "volume".to_string(),
+            AttributeValue::N(stats.volume.to_string()),
+        ),
+        (
+            "quote_volume".to_string(),
+            AttributeValue::N(stats.quote_volume.to_string()),
+        ),
+    ]
+}
+
+/// Reads the display metadata from a coin item, any attributes that don't exist use the
+/// default metadata values
+pub fn coin_metadata(item: &Item) -> Result<CoinMetadata, Error> {
+    let default = CoinMetadata::default();
+    let category = match item.get("category") {
+        Some(value) => serde_json::from_value(serde_json::Value::String(
+            value
+                .as_s()
+                .map_err(|_| std::io::Error::other("category not of type string"))?
+                .clone(),
+        ))?,
+        None => default.category,
+    };
+    let active = match item.get("active") {
+        Some(value) => *value
+            .as_bool()
+            .map_err(|_| std::io::Error::other("active not of type bool"))?,
+        None => default.active,
+    };
+    Ok(CoinMetadata {
+        decimals: get_n_opt(item, "decimals")?.map_or(default.decimals, |v| v as u32),
+        display_precision: get_n_opt(item, "display_precision")?
+            .map_or(default.display_precision, |v| v as u32),
+        icon: get_s_or_default(item, "icon")?,
+        category,
+        active,
+        sort_order: get_n_opt(item, "sort_order")?.map_or(default.sort_order, |v| v as i32),
+    })
+}
+
+/// Converts the display metadata into attributes to be stored on a coin item
+pub fn coin_metadata_attributes(
+    metadata: &CoinMetadata,
+) -> Result<Vec<(String, AttributeValue)>, Error> {
+    let category = match serde_json::to_value(metadata.category)? {
+        serde_json::Value::String(v) => v,
+        _ => return Err("category didn't serialize to a string".into()),
+    };
+    Ok(vec![
+        (
+            "decimals".to_string(),
+            AttributeValue::N(metadata.decimals.to_string()),
+        ),
+        (
+            "display_precision".to_string(),
+            AttributeValue::N(metadata.display_precision.to_string()),
+        ),
+        ("icon".to_string(), AttributeValue::S(metadata.icon.clone())),
+        ("category".to_string(), AttributeValue::S(category)),
+        ("active".to_string(), AttributeValue::Bool(metadata.active)),
+        (
+            "sort_order".to_string(),
+            AttributeValue::N(metadata.sort_order.to_string()),
+        ),
+    ])
+}
+
+/// Builds a `set` update expression for the attributes, every attribute name is aliased
+/// so reserved words such as `name` can be used
+fn set_expression(attributes: &[(String, AttributeValue)]) -> String {
+    let assignments: Vec<String> = attributes
+        .iter()
+        .map(|(key, _)| format!("#{key} = :{key}"))
+        .collect();
+    format!("set {}", assignments.join(", "))
+}
+
+/// Adds the aliased names and values used by `set_expression` to an update request
+fn alias_attributes(
+    mut request: UpdateItem,
+    attributes: Vec<(String, AttributeValue)>,
+) -> UpdateItem {
+    for (key, value) in attributes {
+        request = request
+            .expression_attribute_names(format!("#{key}"), key.clone())
+            .expression_attribute_values(format!(":{key}"), value);
+    }
+    request
+}
+
+/// Gets the display name for every active coin in the `coin` table keyed by symbol,
+/// disabled coins are left out so they aren't refreshed
+pub async fn active_coin_names(client: &Client) -> Result<HashMap<String, String>, Error> {
+    let items = client.scan().table_name("coin").send().await?;
+    let mut names = HashMap::new();
+    for item in items.items().unwrap_or_default() {
+        if coin_metadata(item)?.active {
+            names.insert(get_s(item, "symbol")?.clone(), get_s(item, "name")?.clone());
+        }
+    }
+    Ok(names)
+}
+
+/// Gets a single coin item by symbol, `None` if the coin doesn't exist
+pub async fn get_coin(client: &Client, symbol: &str) -> Result<Option<Item>, Error> {
+    let output = client
+        .get_item()
+        .table_name("coin")
+        .key("symbol", AttributeValue::S(symbol.to_string()))
+        .send()
+        .await?;
+    Ok(output.item().cloned())
+}
+
+/// Saves the latest price and 24 hour stats for a coin, creating the coin if it doesn't
+/// exist. Used by every process that refreshes prices so they are stored the same way.
+/// Any quarantined price is cleared as the stored price is now current
+pub async fn save_coin_price(
+    client: &Client,
+    name: &str,
+    symbol: &str,
+    price: f64,
+    stats: &CoinStats,
+) -> Result<(), Error> {
+    let mut attributes = vec![
+        ("name".to_string(), AttributeValue::S(name.to_string())),
+        ("price".to_string(), AttributeValue::N(price.to_string())),
+    ];
+    attributes.append(&mut coin_stats_attributes(stats));
+
+    let request = client
+        .update_item()
+        .table_name("coin")
+        .key("symbol", AttributeValue::S(symbol.to_string()))
+        .update_expression(format!(
+            "{} remove quarantined_price, quarantined_at",
+            set_expression(&attributes)
+        ));
+    alias_attributes(request, attributes).send().await?;
+    Ok(())
+}
+
+/// Replaces the display metadata of an existing coin, fails if the coin doesn't exist
+pub async fn save_coin_metadata(
+    client: &Client,
+    symbol: &str,
+    metadata: &CoinMetadata,
+) -> Result<(), Error> {
+    let attributes = coin_metadata_attributes(metadata)?;
+    let request = client
+        .update_item()
+        .table_name("coin")
+        .key("symbol", AttributeValue::S(symbol.to_string()))
+        .condition_expression("attribute_exists(symbol)")
+        .update_expression(set_expression(&attributes));
+    alias_attributes(request, attributes).send().await?;
+    Ok(())
+}
+
+/// Saves the name and display metadata of a coin that has never been priced, creating the coin
+/// if it doesn't exist. Coins without a price are skipped when prices are read
+pub async fn save_unpriced_coin(
+    client: &Client,
+    name: &str,
+    symbol: &str,
+    metadata: &CoinMetadata,
+) -> Result<(), Error> {
+    let mut attributes = vec![("name".to_string(), AttributeValue::S(name.to_string()))];
+    attributes.append(&mut coin_metadata_attributes(metadata)?);
+    let request = client
+        .update_item()
+        .table_name("coin")
+        .key("symbol", AttributeValue::S(symbol.to_string()))
+        .update_expression(set_expression(&attributes));
+    alias_attributes(request, attributes).send().await?;
+    Ok(())
+}
+
+/// Holds a suspicious price on the coin without changing the current price, so it can be
+/// confirmed by the next fetch
+pub async fn quarantine_price(
+    client: &Client,
+    symbol: &str,
+    price: f64,
+    timestamp: i64,
+) -> Result<(), Error> {
+    client
+        .update_item()
+        .table_name("coin")
+        .key("symbol", AttributeValue::S(symbol.to_string()))
+        .condition_expression("attribute_exists(symbol)")
+        .update_expression("set quarantined_price = :price, quarantined_at = :timestamp")
+        .expression_attribute_values(":price", AttributeValue::N(price.to_string()))
+        .expression_attribute_values(":timestamp", AttributeValue::N(timestamp.to_string()))
+        .send()
+        .await?;
+    Ok(())
+}
+
+/// Appends a price to the `price_history` table, `timestamp` is seconds since the unix epoch
+pub async fn record_price(
+    client: &Client,
+    symbol: &str,
+    timestamp: i64,
+    price: f64,
+) -> Result<(), Error> {
+    client
+        .put_item()
+        .table_name("price_history")
+        .item("symbol", AttributeValue::S(symbol.to_string()))
+        .item("timestamp", AttributeValue::N(timestamp.to_string()))
+        .item("price", AttributeValue::N(price.to_string()))
+        .send()
+        .await?;
+    Ok(())
+}
+
+/// Gets the most recent price from the `price_history` table at or before `timestamp`,
+/// `None` if there is no history before then
+pub async fn price_at(client: &Client, symbol: &str, timestamp: i64) -> Result<Option<f64>, Error> {
+    let output = client
+        .query()
+        .table_name("price_history")
+        .key_condition_expression("symbol = :symbol and #timestamp <= :timestamp")
+        .expression_attribute_names("#timestamp", "timestamp")
+        .expression_attribute_values(":symbol", AttributeValue::S(symbol.to_string()))
+        .expression_attribute_values(":timestamp", AttributeValue::N(timestamp.to_string()))
+        .scan_index_forward(false)
+        .limit(1)
+        .send()
+        .await?;
+    match output.items().unwrap_or_default().first() {
+        Some(item) => Ok(Some(get_n_or_default(item, "price")?)),
+        None => Ok(None),
+    }
+}
+
+/// Fills in the prices a client can leave out of a transaction. Staking, interest and airdrops
+/// without a price are valued from the price history at `executed_at`, and a fee paid in another
+/// coin without a `fee_price` is valued at that coin's current price. The inner error is a
+/// message for the client when there's no price to use
+pub async fn fill_prices(
+    client: &Client,
+    transaction: &mut Transaction,
+) -> Result<Result<(), String>, Error> {
+    if transaction.needs_market_value() {
+        transaction.price =
+            match price_at(client, &transaction.coin, transaction.executed_at).await? {
+                Some(v) => v,
+                None => {
+                    return Ok(Err(format!(
+                        "no price history for {} at executed_at, include a price",
+                        transaction.coin
+                    )))
+                }
+            };
+    }
+
+    if transaction.fee > 0.0 && !transaction.fee_asset.is_empty() && transaction.fee_price == 0.0 {
+        transaction.fee_price = if transaction.fee_asset == transaction.coin {
+            transaction.price
+        } else {
+            let item = match get_coin(client, &transaction.fee_asset).await? {
+                Some(v) => v,
+                None => {
+                    return Ok(Err(format!(
+                        "fee asset {} isn't a known coin, include a fee_price",
+                        transaction.fee_asset
+                    )))
+                }
+            };
+            match get_n_opt(&item, "price")? {
+                Some(v) => v,
+                None => {
+                    return Ok(Err(format!(
+                        "fee asset {} has never been priced, include a fee_price",
+                        transaction.fee_asset
+                    )))
+                }
+            }
+        };
+    }
+    Ok(Ok(()))
+}
+
+/// Gets up to `limit` of the most recent prices from the `price_history` table, most recent first
+pub async fn recent_prices(client: &Client, symbol: &str, limit: usize) -> Result<Vec<f64>, Error> {
+    let output = client
+        .query()
+        .table_name("price_history")
+        .key_condition_expression("symbol = :symbol")
+        .expression_attribute_values(":symbol", AttributeValue::S(symbol.to_string()))
+        .scan_index_forward(false)
+        .limit(limit as i32)
+        .send()
+        .await?;
+    let mut prices = vec![];
+    for item in output.items().unwrap_or_default() {
+        prices.push(get_n_or_default(item, "price")?);
+    }
+    Ok(prices)
+}
+
+/// Every item in the price history of a coin, oldest first
+async fn price_history_items(client: &Client, symbol: &str) -> Result<Vec<Item>, Error> {
+    let mut items = vec![];
+    let mut start_key = None;
+    loop {
+        let output = client
+            .query()
+            .table_name("price_history")
+            .key_condition_expression("symbol = :symbol")
+            .expression_attribute_values(":symbol", AttributeValue::S(symbol.to_string()))
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await?;
+        items.extend(output.items().unwrap_or_default().iter().cloned());
+        match output.last_evaluated_key() {
+            Some(key) => start_key = Some(key.clone()),
+            None => return Ok(items),
+        }
+    }
+}
+
+async fn delete_price(client: &Client, item: &Item) -> Result<(), Error> {
+    client
+        .delete_item()
+        .table_name("price_history")
+        .key("symbol", AttributeValue::S(get_s(item, "symbol")?.clone()))
+        .key(
+            "timestamp",
+            item.get("timestamp")
+                .ok_or("timestamp key doesn't exist")?
+                .clone(),
+        )
+        .send()
+        .await?;
+    Ok(())
+}
+
+/// Deletes the whole price history of a coin, returns how many prices were deleted
+pub async fn delete_price_history(client: &Client, symbol: &str) -> Result<usize, Error> {
+    let items = price_history_items(client, symbol).await?;
+    for item in &items {
+        delete_price(client, item).await?;
+    }
+    Ok(items.len())
+}
+
+/// Moves the whole price history of a coin to a new symbol, returns how many prices were moved
+pub async fn move_price_history(client: &Client, from: &str, to: &str) -> Result<usize, Error> {
+    let items = price_history_items(client, from).await?;
+    for item in &items {
+        let mut moved = item.clone();
+        moved.insert("symbol".to_string(), AttributeValue::S(to.to_string()));
+        client
+            .put_item()
+            .table_name("price_history")
+            .set_item(Some(moved))
+            .send()
+            .await?;
+        delete_price(client, item).await?;
+    }
+    Ok(items.len())
+}
+
+/// Copies a coin item to a new symbol, optionally with a new display name. The copy keeps the
+/// symbol it came from in `renamed_from`, so an interrupted rename can be recognised and resumed.
+/// Fails if a coin with the new symbol already exists
+pub async fn copy_coin(
+    client: &Client,
+    item: &Item,
+    symbol: &str,
+    name: Option<&str>,
+) -> Result<(), Error> {
+    let mut copy = item.clone();
+    copy.insert(
+        "renamed_from".to_string(),
+        AttributeValue::S(get_s(item, "symbol")?.clone()),
+    );
+    copy.insert("symbol".to_string(), AttributeValue::S(symbol.to_string()));
+    if let Some(name) = name {
+        copy.insert("name".to_string(), AttributeValue::S(name.to_string()));
+    }
+    client
+        .put_item()
+        .table_name("coin")
+        .set_item(Some(copy))
+        .condition_expression("attribute_not_exists(symbol)")
+        .send()
+        .await?;
+    Ok(())
+}
+
+/// Removes a coin from the `coin` table
+pub async fn delete_coin(client: &Client, symbol: &str) -> Result<(), Error> {
+    client
+        .delete_item()
+        .table_name("coin")
+        .key("symbol", AttributeValue::S(symbol.to_string()))
+        .send()
+        .await?;
+    Ok(())
+}
+
+/// The transactions stored on a user item before they moved to the `transaction` table,
+/// users without any have an empty list
+fn legacy_list(item: &Item) -> Result<&[AttributeValue], Error> {
+    match item.get("transactions") {
+        Some(value) => Ok(value
+            .as_l()
+            .map_err(|_| std::io::Error::other("transactions type incorrect"))?
+            .as_slice()),
+        None => Ok(&[]),
+    }
+}
+
+/// Converts a transaction into an item in the `transaction` table
+pub fn transaction_attributes(transaction: &Transaction) -> Item {
+    let mut map = HashMap::from([
+        (
+            "username".to_string(),
+            AttributeValue::S(transaction.username.clone()),
+        ),
+        ("id".to_string(), AttributeValue::S(transaction.id.clone())),
+        (
+            "coin".to_string(),
+            AttributeValue::S(transaction.coin.clone()),
+        ),
+        (
+            "kind".to_string(),
+            AttributeValue::S(transaction.kind.to_string()),
+        ),
+        (
+            "amount".to_string(),
+            AttributeValue::N(transaction.amount.to_string()),
+        ),
+        (
+            "price".to_string(),
+            AttributeValue::N(transaction.price.to_string()),
+        ),
+        (
+            "executed_at".to_string(),
+            AttributeValue::N(transaction.executed_at.to_string()),
+        ),
+        (
+            "recorded_at".to_string(),
+            AttributeValue::N(transaction.recorded_at.to_string()),
+        ),
+    ]);
+    if !transaction.swap_id.is_empty() {
+        map.insert(
+            "swap_id".to_string(),
+            AttributeValue::S(transaction.swap_id.clone()),
+        );
+    }
+    if !transaction.external_id.is_empty() {
+        map.insert(
+            "external_id".to_string(),
+            AttributeValue::S(transaction.external_id.clone()),
+        );
+    }
+    if let Some(correction) = transaction.correction {
+        map.insert(
+            "correction".to_string(),
+            AttributeValue::S(correction.to_string()),
+        );
+        map.insert(
+            "corrects".to_string(),
+            AttributeValue::S(transaction.corrects.clone()),
+        );
+    }
+    if transaction.fee > 0.0 {
+        map.insert(
+            "fee".to_string(),
+            AttributeValue::N(transaction.fee.to_string()),
+        );
+        map.insert(
+            "fee_asset".to_string(),
+            AttributeValue::S(transaction.fee_asset.clone()),
+        );
+        map.insert(
+            "fee_price".to_string(),
+            AttributeValue::N(transaction.fee_price.to_string()),
+        );
+    }
+    // A string set can't be empty and can't hold duplicates
+    let mut tags = transaction.tags.clone();
+    tags.sort();
+    tags.dedup();
+    if !tags.is_empty() {
+        map.insert("tags".to_string(), AttributeValue::Ss(tags));
+    }
+    if transaction.allow_short {
+        map.insert("allow_short".to_string(), AttributeValue::Bool(true));
+    }
+    if !transaction.lots.is_empty() {
+        let lots = transaction
+            .lots
+            .iter()
+            .map(|lot| {
+                AttributeValue::M(HashMap::from([
+                    ("id".to_string(), AttributeValue::S(lot.id.clone())),
+                    (
+                        "amount".to_string(),
+                        AttributeValue::N(lot.amount.to_string()),
+                    ),
+                ]))
+            })
+            .collect();
+        map.insert("lots".to_string(), AttributeValue::L(lots));
+    }
+    map
+}
+
+/// Parses an item from the `transaction` table. Transactions stored before kinds existed
+/// used a negative amount for a sale, so they're read as a buy or sell. Transactions stored
+/// before timestamps existed have an `executed_at` of 0
+pub fn parse_transaction(item: &Item) -> Result<Transaction, Error> {
+    let amount = get_n_opt(item, "amount")?.ok_or("amount key doesn't exist")?;
+    let (kind, amount) = match item.get("kind") {
+        Some(_) => (get_s(item, "kind")?.parse::<TransactionKind>()?, amount),
+        None if amount < 0.0 => (TransactionKind::Sell, -amount),
+        None => (TransactionKind::Buy, amount),
+    };
+    Ok(Transaction {
+        id: get_s_or_default(item, "id")?,
+        username: get_s(item, "username")?.clone(),
+        coin: get_s(item, "coin")?.clone(),
+        kind,
+        amount,
+        price: get_n_or_default(item, "price")?,
+        fee: get_n_or_default(item, "fee")?,
+        fee_asset: get_s_or_default(item, "fee_asset")?,
+        fee_price: get_n_or_default(item, "fee_price")?,
+        swap_id: get_s_or_default(item, "swap_id")?,
+        external_id: get_s_or_default(item, "external_id")?,
+        executed_at: get_i64_or_default(item, "executed_at")?,
+        recorded_at: get_i64_or_default(item, "recorded_at")?,
+        correction: match item.get("correction") {
+            Some(_) => Some(get_s(item, "correction")?.parse::<Correction>()?),
+            None => None,
+        },
+        corrects: get_s_or_default(item, "corrects")?,
+        tags: match item.get("tags") {
+            Some(value) => value
+                .as_ss()
+                .map_err(|_| std::io::Error::other("tags not of type string set"))?
+                .clone(),
+            None => vec![],
+        },
+        allow_short: match item.get("allow_short") {
+            Some(value) => *value
+                .as_bool()
+                .map_err(|_| std::io::Error::other("allow_short not of type bool"))?,
+            None => false,
+        },
+        lots: match item.get("lots") {
+            Some(value) => value
+                .as_l()
+                .map_err(|_| std::io::Error::other("lots not of type list"))?
+                .iter()
+                .map(|lot| -> Result<LotSelection, Error> {
+                    let lot = lot
+                        .as_m()
+                        .map_err(|_| std::io::Error::other("lot not of type map"))?;
+                    Ok(LotSelection {
+                        id: get_s(lot, "id")?.clone(),
+                        amount: get_n_or_default(lot, "amount")?,
+                    })
+                })
+                .collect::<Result<_, _>>()?,
+            None => vec![],
+        },
+    })
+}
+
+/// Parses the transactions still stored in the `transactions` list of a user item, in the
+/// order they were added. Transactions from before ids existed are given ids from their
+/// position, time 0 sorts them first in the order they were added. The same ids are generated
+/// every time, so they match once the list has been moved to the `transaction` table
+pub fn legacy_transactions(item: &Item) -> Result<Vec<Transaction>, Error> {
+    let username = get_s(item, "username")?;
+    let mut transactions = vec![];
+    for (index, value) in legacy_list(item)?.iter().enumerate() {
+        let mut map = value
+            .as_m()
+            .map_err(|_| std::io::Error::other("trans_map type incorrect"))?
+            .clone();
+        map.insert("username".to_string(), AttributeValue::S(username.clone()));
+        let mut transaction = parse_transaction(&map)?;
+        if transaction.id.is_empty() {
+            transaction.id = Ulid::from_parts(0, index as u128).to_string();
+        }
+        transactions.push(transaction);
+    }
+    Ok(transactions)
+}
+
+/// Removes the `transactions` list from a user item once it has been moved to the
+/// `transaction` table, only if nothing was added to it since it was read
+pub async fn remove_legacy_transactions(
+    client: &Client,
+    username: &str,
+    count: usize,
+) -> Result<(), Error> {
+    client
+        .update_item()
+        .table_name("user")
+        .key("username", AttributeValue::S(username.to_string()))
+        .condition_expression("size(transactions) = :count")
+        .update_expression("remove transactions")
+        .expression_attribute_values(":count", AttributeValue::N(count.to_string()))
+        .send()
+        .await?;
+    Ok(())
+}
+
+/// Stores transactions to the `transaction` table without any checks, overwriting any with
+/// the same id. Only used to move existing transactions, new ones go through
+/// `append_transactions`
+pub async fn put_transactions(client: &Client, transactions: &[Transaction]) -> Result<(), Error> {
+    for chunk in transactions.chunks(25) {
+        let requests = chunk
+            .iter()
+            .map(|transaction| {
+                WriteRequest::builder()
+                    .put_request(
+                        PutRequest::builder()
+                            .set_item(Some(transaction_attributes(transaction)))
+                            .build(),
+                    )
+                    .build()
+            })
+            .collect();
+        let mut unprocessed = Some(HashMap::from([("transaction".to_string(), requests)]));
+        while let Some(items) = unprocessed.filter(|items| !items.is_empty()) {
+            let output = client
+                .batch_write_item()
+                .set_request_items(Some(items))
+                .send()
+                .await?;
+            unprocessed = output.unprocessed_items().cloned();
+        }
+    }
+    Ok(())
+}
+
+/// Most actions dynamodb allows in a single transactional write
+pub const MAX_TRANSACT_ITEMS: usize = 100;
+
+/// Every coin the transactions refer to including fee assets, sorted and without duplicates
+fn referenced_coins(transactions: &[Transaction]) -> Vec<&str> {
+    let mut coins: Vec<&str> = transactions
+        .iter()
+        .flat_map(|transaction| [transaction.coin.as_str(), transaction.fee_asset.as_str()])
+        .filter(|coin| !coin.is_empty())
+        .collect();
+    coins.sort_unstable();
+    coins.dedup();
+    coins
+}
+
+/// Splits transactions into chunks that can each be stored with one `append_transactions`,
+/// which uses an action for the user check, each transaction and each coin they refer to
+pub fn transaction_chunks(transactions: &[Transaction]) -> Vec<&[Transaction]> {
+    let mut chunks = vec![];
+    let mut start = 0;
+    for end in 1..=transactions.len() {
+        let chunk = &transactions[start..end];
+        if 1 + chunk.len() + referenced_coins(chunk).len() > MAX_TRANSACT_ITEMS && end - 1 > start {
+            chunks.push(&transactions[start..end - 1]);
+            start = end - 1;
+        }
+    }
+    if start < transactions.len() {
+        chunks.push(&transactions[start..]);
+    }
+    chunks
+}
+
+/// Appends transactions to a user in a single dynamodb transaction, so either all of them are
+/// stored or none are. The user and every coin the transactions refer to, including fee
+/// assets, must exist or nothing is written
+pub async fn append_transactions(
+    client: &Client,
+    username: &str,
+    transactions: &[Transaction],
+) -> Result<(), Error> {
+    let user = ConditionCheck::builder()
+        .table_name("user")
+        .key("username", AttributeValue::S(username.to_string()))
+        .condition_expression("attribute_exists(username)")
+        .build();
+    let mut request = client
+        .transact_write_items()
+        .transact_items(TransactWriteItem::builder().condition_check(user).build());
+
+    for transaction in transactions {
+        let put = Put::builder()
+            .table_name("transaction")
+            .set_item(Some(transaction_attributes(transaction)))
+            .condition_expression("attribute_not_exists(id)")
+            .build();
+        request = request.transact_items(TransactWriteItem::builder().put(put).build());
+    }
+
+    for coin in referenced_coins(transactions) {
+        let check = ConditionCheck::builder()
+            .table_name("coin")
+            .key("symbol", AttributeValue::S(coin.to_string()))
+            .condition_expression("attribute_exists(symbol)")
+            .build();
+        request =
+            request.transact_items(TransactWriteItem::builder().condition_check(check).build());
+    }
+    request.send().await?;
+    Ok(())
+}
+
+/// Gets every transaction of a user from the `transaction` table, ordered by when they were
+/// executed. Users that `transactions_migrate` hasn't moved yet still have a `transactions`
+/// list on their item, those transactions are included unless they have already been moved
+pub async fn user_transactions(client: &Client, username: &str) -> Result<Vec<Transaction>, Error> {
+    let mut transactions = vec![];
+    let mut start_key = None;
+    loop {
+        let output = client
+            .query()
+            .table_name("transaction")
+            .key_condition_expression("username = :username")
+            .expression_attribute_values(":username", AttributeValue::S(username.to_string()))
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await?;
+        for item in output.items().unwrap_or_default() {
+            transactions.push(parse_transaction(item)?);
+        }
+        match output.last_evaluated_key() {
+            Some(key) => start_key = Some(key.clone()),
+            None => break,
+        }
+    }
+
+    if let Some(item) = get_user(client, username).await? {
+        let moved: HashSet<String> = transactions.iter().map(|t| t.id.clone()).collect();
+        transactions.extend(
+            legacy_transactions(&item)?
+                .into_iter()
+                .filter(|t| !moved.contains(&t.id)),
+        );
+    }
+    sort_by_execution(&mut transactions);
+    Ok(transactions)
+}
+
+/// Reads the cost basis method chosen by a user, users stored before it could be chosen
+/// use the default method
+pub fn cost_basis_method(item: &Item) -> Result<CostBasisMethod, Error> {
+    match item.get("cost_basis_method") {
+        Some(_) => Ok(get_s(item, "cost_basis_method")?.parse::<CostBasisMethod>()?),
+        None => Ok(CostBasisMethod::default()),
+    }
+}
+
+/// Reads the tax jurisdiction of a user, `None` if they haven't set one
+pub fn jurisdiction(item: &Item) -> Result<Option<Jurisdiction>, Error> {
+    match item.get("jurisdiction") {
+        Some(_) => Ok(Some(get_s(item, "jurisdiction")?.parse::<Jurisdiction>()?)),
+        None => Ok(None),
+    }
+}
+
+/// Whether a user with the username exists in the `user` table
+pub async fn user_exists(client: &Client, username: &str) -> Result<bool, Error> {
+    let output = client
+        .get_item()
+        .table_name("user")
+        .key("username", AttributeValue::S(username.to_string()))
+        .projection_expression("username")
+        .send()
+        .await?;
+    Ok(output.item().is_some())
+}
+
+/// Gets a single user item, `None` if the user doesn't exist
+pub async fn get_user(client: &Client, username: &str) -> Result<Option<Item>, Error> {
+    let output = client
+        .get_item()
+        .table_name("user")
+        .key("username", AttributeValue::S(username.to_string()))
+        .send()
+        .await?;
+    Ok(output.item().cloned())
+}
+
+/// Gets the transactions of a single user, `None` if the user doesn't exist
+pub async fn get_user_transactions(
+    client: &Client,
+    username: &str,
+) -> Result<Option<Vec<Transaction>>, Error> {
+    match get_user(client, username).await? {
+        Some(_) => Ok(Some(user_transactions(client, username).await?)),
+        None => Ok(None),
+    }
+}
+
+/// Saves an import mapping in the `import_mapping` table under the user and its name,
+/// replacing one with the same name. The mapping is stored as json since it's only read whole
+pub async fn put_import_mapping(
+    client: &Client,
+    username: &str,
+    mapping: &ImportMapping,
+) -> Result<(), Error> {
+    client
+        .put_item()
+        .table_name("import_mapping")
+        .item("username", AttributeValue::S(username.to_string()))
+        .item("name", AttributeValue::S(mapping.name.clone()))
+        .item(
+            "mapping",
+            AttributeValue::S(serde_json::to_string(mapping)?),
+        )
+        .send()
+        .await?;
+    Ok(())
+}
+
+/// Gets a user's import mapping by name, `None` if they haven't saved one with that name
+pub async fn get_import_mapping(
+    client: &Client,
+    username: &str,
+    name: &str,
+) -> Result<Option<ImportMapping>, Error> {
+    let output = client
+        .get_item()
+        .table_name("import_mapping")
+        .key("username", AttributeValue::S(username.to_string()))
+        .key("name", AttributeValue::S(name.to_string()))
+        .send()
+        .await?;
+    match output.item() {
+        Some(item) => Ok(Some(serde_json::from_str(get_s(item, "mapping")?)?)),
+        None => Ok(None),
+    }
+}
+
+/// Gets every import mapping a user has saved, ordered by name
+pub async fn import_mappings(client: &Client, username: &str) -> Result<Vec<ImportMapping>, Error> {
+    let mut mappings = vec![];
+    let mut start_key = None;
+    loop {
+        let output = client
+            .query()
+            .table_name("import_mapping")
+            .key_condition_expression("username = :username")
+            .expression_attribute_values(":username", AttributeValue::S(username.to_string()))
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await?;
+        for item in output.items().unwrap_or_default() {
+            mappings.push(serde_json::from_str(get_s(item, "mapping")?)?);
+        }
+        match output.last_evaluated_key() {
+            Some(key) => start_key = Some(key.clone()),
+            None => break,
+        }
+    }
+    Ok(mappings)
+}
+
+/// Gets a single page of a user's transactions that match the query. Ids are generated from
+/// `executed_at` so the sort key keeps them in execution order. Filters are applied after
+/// the page is read, so a page can hold fewer than `limit` transactions and still have a
+/// cursor to the next page. Users that `transactions_migrate` hasn't moved yet are paged from
+/// all of their transactions with `page_of`, so their history matches their balances
+pub async fn transaction_page(
+    client: &Client,
+    username: &str,
+    query: &TransactionQuery,
+) -> Result<TransactionPage, Error> {
+    if let Some(item) = get_user(client, username).await? {
+        if !legacy_list(&item)?.is_empty() {
+            return Ok(page_of(&user_transactions(client, username).await?, query));
+        }
+    }
+
+    let mut filters = vec![];
+    let mut request = client
+        .query()
+        .table_name("transaction")
+        .key_condition_expression("username = :username")
+        .expression_attribute_values(":username", AttributeValue::S(username.to_string()))
+        .scan_index_forward(!query.descending)
+        .limit(query.limit);
+    if let Some(coin) = &query.coin {
+        filters.push("coin = :coin");
+        request = request.expression_attribute_values(":coin", AttributeValue::S(coin.clone()));
+    }
+    if let Some(kind) = query.kind {
+        filters.push("kind = :kind");
+        request = request.expression_attribute_values(":kind", AttributeValue::S(kind.to_string()));
+    }
+    if let Some(from) = query.from {
+        filters.push("executed_at >= :from");
+        request = request.expression_attribute_values(":from", AttributeValue::N(from.to_string()));
+    }
+    if let Some(to) = query.to {
+        filters.push("executed_at <= :to");
+        request = request.expression_attribute_values(":to", AttributeValue::N(to.to_string()));
+    }
+    if let Some(tag) = &query.tag {
+        filters.push("contains(tags, :tag)");
+        request = request.expression_attribute_values(":tag", AttributeValue::S(tag.clone()));
+    }
+    if !filters.is_empty() {
+        request = request.filter_expression(filters.join(" and "));
+    }
+    if let Some(cursor) = &query.cursor {
+        request = request
+            .exclusive_start_key("username", AttributeValue::S(username.to_string()))
+            .exclusive_start_key("id", AttributeValue::S(cursor.clone()));
+    }
+
+    let output = request.send().await?;
+    let mut transactions = vec![];
+    for item in output.items().unwrap_or_default() {
+        transactions.push(parse_transaction(item)?);
+    }
+    let cursor = match output.last_evaluated_key() {
+        Some(key) => Some(get_s(key, "id")?.clone()),
+        None => None,
+    };
+    Ok(TransactionPage {
+        transactions,
+        cursor,
+    })
+}
+
+/// A page of transactions that match the query, ordered by id like the `transaction` table.
+/// Filters are applied before `limit` so only the last page has no cursor
+pub fn page_of(transactions: &[Transaction], query: &TransactionQuery) -> TransactionPage {
+    let mut matching: Vec<&Transaction> = transactions
+        .iter()
+        .filter(|t| query.coin.as_ref().is_none_or(|coin| t.coin == *coin))
+        .filter(|t| query.kind.is_none_or(|kind| t.kind == kind))
+        .filter(|t| query.from.is_none_or(|from| t.executed_at >= from))
+        .filter(|t| query.to.is_none_or(|to| t.executed_at <= to))
+        .filter(|t| query.tag.as_ref().is_none_or(|tag| t.tags.contains(tag)))
+        .filter(|t| match &query.cursor {
+            Some(cursor) if query.descending => t.id < *cursor,
+            Some(cursor) => t.id > *cursor,
+            None => true,
+        })
+        .collect();
+    matching.sort_by(|a, b| a.id.cmp(&b.id));
+    if query.descending {
+        matching.reverse();
+    }
+
+    let limit = query.limit.max(1) as usize;
+    let cursor = match matching.len() > limit {
+        true => Some(matching[limit - 1].id.clone()),
+        false => None,
+    };
+    TransactionPage {
+        transactions: matching.into_iter().take(limit).cloned().collect(),
+        cursor,
+    }
+}
+
+/// Every user item that still has a `transactions` list, which `transactions_migrate` hasn't
+/// moved to the `transaction` table yet
+async fn legacy_users(client: &Client) -> Result<Vec<Item>, Error> {
+    let mut items = vec![];
+    let mut start_key = None;
+    loop {
+        let output = client
+            .scan()
+            .table_name("user")
+            .filter_expression("attribute_exists(transactions)")
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await?;
+        items.extend(output.items().unwrap_or_default().iter().cloned());
+        match output.last_evaluated_key() {
+            Some(key) => start_key = Some(key.clone()),
+            None => return Ok(items),
+        }
+    }
+}
+
+/// Whether a stored transaction is for the coin, or has a fee paid in the coin
+fn references(item: &Item, symbol: &str) -> bool {
+    ["coin", "fee_asset"].iter().any(|key| {
+        item.get(*key)
+            .and_then(|value| value.as_s().ok())
+            .map(String::as_str)
+            == Some(symbol)
+    })
+}
+
+/// A stored transaction with its coin and fee asset pointed at `to` where they were `from`
+fn renamed(item: &Item, from: &str, to: &str) -> Item {
+    let mut renamed = item.clone();
+    for key in ["coin", "fee_asset"] {
+        if item
+            .get(key)
+            .and_then(|value| value.as_s().ok())
+            .map(String::as_str)
+            == Some(from)
+        {
+            renamed.insert(key.to_string(), AttributeValue::S(to.to_string()));
+        }
+    }
+    renamed
+}
+
+/// The transactions in a user's legacy list as stored
+fn legacy_maps(item: &Item) -> Result<Vec<&Item>, Error> {
+    legacy_list(item)?
+        .iter()
+        .map(|value| {
+            value
+                .as_m()
+                .map_err(|_| std::io::Error::other("trans_map type incorrect").into())
+        })
+        .collect()
+}
+
+/// Every item in the `transaction` table for the coin, or with a fee paid in the coin
+async fn transactions_referencing(client: &Client, symbol: &str) -> Result<Vec<Item>, Error> {
+    let mut items = vec![];
+    let mut start_key = None;
+    loop {
+        let output = client
+            .scan()
+            .table_name("transaction")
+            .filter_expression("coin = :symbol or fee_asset = :symbol")
+            .expression_attribute_values(":symbol", AttributeValue::S(symbol.to_string()))
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await?;
+        items.extend(output.items().unwrap_or_default().iter().cloned());
+        match output.last_evaluated_key() {
+            Some(key) => start_key = Some(key.clone()),
+            None => return Ok(items),
+        }
+    }
+}
+
+/// Usernames of every user that has a transaction for the coin, including transactions that
+/// haven't been moved from their user item yet
+pub async fn users_holding(client: &Client, symbol: &str) -> Result<Vec<String>, Error> {
+    let mut usernames = vec![];
+    for item in transactions_referencing(client, symbol).await? {
+        usernames.push(get_s(&item, "username")?.clone());
+    }
+    for item in legacy_users(client).await? {
+        if legacy_maps(&item)?
+            .iter()
+            .any(|map| references(map, symbol))
+        {
+            usernames.push(get_s(&item, "username")?.clone());
+        }
+    }
+    usernames.sort();
+    usernames.dedup();
+    Ok(usernames)
+}
+
+/// Points every transaction for a coin, or with a fee paid in the coin, at a new symbol.
+/// Legacy lists that haven't been moved from the user item yet are rewritten in place, only if
+/// nothing was added to them since they were read. Returns how many users were updated
+pub async fn rename_transaction_coin(
+    client: &Client,
+    from: &str,
+    to: &str,
+) -> Result<usize, Error> {
+    let mut usernames = vec![];
+    for item in transactions_referencing(client, from).await? {
+        client
+            .put_item()
+            .table_name("transaction")
+            .set_item(Some(renamed(&item, from, to)))
+            .send()
+            .await?;
+        usernames.push(get_s(&item, "username")?.clone());
+    }
+    for item in legacy_users(client).await? {
+        let maps = legacy_maps(&item)?;
+        if !maps.iter().any(|map| references(map, from)) {
+            continue;
+        }
+        let list = maps
+            .iter()
+            .map(|map| AttributeValue::M(renamed(map, from, to)))
+            .collect();
+        let username = get_s(&item, "username")?;
+        client
+            .update_item()
+            .table_name("user")
+            .key("username", AttributeValue::S(username.clone()))
+            .condition_expression("size(transactions) = :count")
+            .update_expression("set transactions = :transactions")
+            .expression_attribute_values(":count", AttributeValue::N(maps.len().to_string()))
+            .expression_attribute_values(":transactions", AttributeValue::L(list))
+            .send()
+            .await?;
+        usernames.push(username.clone());
+    }
+    usernames.sort();
+    usernames.dedup();
+    Ok(usernames.len())
+}
+
+/// Hash of a request body stored with its idempotency key, so a retry can be told apart from
+/// a different request that reused the key
+pub fn request_hash(body: &str) -> String {
+    format!("{:x}", Sha256::digest(body.as_bytes()))
+}
+
+/// Claims an idempotency key in the `idempotency` table before a request is processed.
+/// Returns false if the key is already held by a request that hasn't expired, `expires_at` is
+/// also the table's ttl attribute so old keys are cleaned up
+pub async fn reserve_idempotency_key(
+    client: &Client,
+    key: &str,
+    request_hash: &str,
+    now: i64,
+    expires_at: i64,
+) -> Result<bool, Error> {
+    let result = client
+        .put_item()
+        .table_name("idempotency")
+        .item("idempotency_key", AttributeValue::S(key.to_string()))
+        .item("request_hash", AttributeValue::S(request_hash.to_string()))
+        .item("expires_at", AttributeValue::N(expires_at.to_string()))
+        .condition_expression("attribute_not_exists(idempotency_key) or expires_at < :now")
+        .expression_attribute_values(":now", AttributeValue::N(now.to_string()))
+        .send()
+        .await;
+    match result {
+        Ok(_) => Ok(true),
+        Err(SdkError::ServiceError { err, .. }) if err.is_conditional_check_failed_exception() => {
+            Ok(false)
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Gets an idempotency key item, it holds the `request_hash` and once the request has
+/// finished its response `status` and `body`
+pub async fn get_idempotency_key(client: &Client, key: &str) -> Result<Option<Item>, Error> {
+    let output = client
+        .get_item()
+        .table_name("idempotency")
+        .key("idempotency_key", AttributeValue::S(key.to_string()))
+        .consistent_read(true)
+        .send()
+        .await?;
+    Ok(output.item().cloned())
+}
+
+/// Stores the response of a finished request so retries with the same key get it back
+pub async fn save_idempotent_response(
+    client: &Client,
+    key: &str,
+    status: u16,
+    body: &str,
+) -> Result<(), Error> {
+    client
+        .update_item()
+        .table_name("idempotency")
+        .key("idempotency_key", AttributeValue::S(key.to_string()))
+        .update_expression("set #status = :status, body = :body")
+        .expression_attribute_names("#status", "status")
+        .expression_attribute_values(":status", AttributeValue::N(status.to_string()))
+        .expression_attribute_values(":body", AttributeValue::S(body.to_string()))
+        .send()
+        .await?;
+    Ok(())
+}
+
+/// Frees an idempotency key when the request failed in a way that should be retried
+pub async fn release_idempotency_key(client: &Client, key: &str) -> Result<(), Error> {
+    client
+        .delete_item()
+        .table_name("idempotency")
+        .key("idempotency_key", AttributeValue::S(key.to_string()))
+        .send()
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buy(coin: &str) -> Transaction {
+        Transaction {
+            coin: coin.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn chunks_fit_in_a_transactional_write() {
+        let same_coin: Vec<Transaction> = (0..250).map(|_| buy("ETHAUD")).collect();
+        let chunks = transaction_chunks(&same_coin);
+        let sizes: Vec<usize> = chunks.iter().map(|chunk| chunk.len()).collect();
+        assert_eq!(sizes, [98, 98, 54]);
+
+        let many_coins: Vec<Transaction> = (0..60).map(|i| buy(&format!("COIN{}", i))).collect();
+        let chunks = transaction_chunks(&many_coins);
+        assert!(chunks
+            .iter()
+            .all(|chunk| 1 + chunk.len() + referenced_coins(chunk).len() <= MAX_TRANSACT_ITEMS));
+        assert_eq!(chunks.iter().map(|chunk| chunk.len()).sum::<usize>(), 60);
+
+        assert!(transaction_chunks(&[]).is_empty());
+    }
+
+    #[test]
+    fn pages_transactions_in_memory() {
+        let transactions: Vec<Transaction> = ["ETHAUD", "BTCAUD", "ETHAUD", "ETHAUD"]
+            .iter()
+            .enumerate()
+            .map(|(index, coin)| Transaction {
+                id: Ulid::from_parts(0, index as u128).to_string(),
+                executed_at: index as i64,
+                ..buy(coin)
+            })
+            .collect();
+        let ids = |page: &TransactionPage| -> Vec<i64> {
+            page.transactions.iter().map(|t| t.executed_at).collect()
+        };
+        let query = TransactionQuery {
+            coin: Some("ETHAUD".to_string()),
+            limit: 2,
+            ..Default::default()
+        };
+
+        let first = page_of(&transactions, &query);
+        assert_eq!(ids(&first), [0, 2]);
+        let next = TransactionQuery {
+            cursor: first.cursor.clone(),
+            ..query.clone()
+        };
+        let last = page_of(&transactions, &next);
+        assert_eq!(ids(&last), [3]);
+        assert_eq!(last.cursor, None);
+
+        let descending = TransactionQuery {
+            descending: true,
+            limit: 10,
+            ..query
+        };
+        assert_eq!(ids(&page_of(&transactions, &descending)), [3, 2, 0]);
+    }
+
+    #[test]
+    fn legacy_transactions_get_stable_ids() {
+        let legacy = |coin: &str, amount: &str, id: Option<&str>| {
+            let mut map = HashMap::from([
+                ("coin".to_string(), AttributeValue::S(coin.to_string())),
+                ("amount".to_string(), AttributeValue::N(amount.to_string())),
+            ]);
+            if let Some(id) = id {
+                map.insert("id".to_string(), AttributeValue::S(id.to_string()));
+            }
+            AttributeValue::M(map)
+        };
+        let item = HashMap::from([
+            (
+                "username".to_string(),
+                AttributeValue::S("testuser".to_string()),
+            ),
+            (
+                "transactions".to_string(),
+                AttributeValue::L(vec![
+                    legacy("ETHAUD", "2", None),
+                    legacy("ETHAUD", "-1", None),
+                    legacy("BTCAUD", "1", Some("01G0000000000000000000000")),
+                ]),
+            ),
+        ]);
+
+        let transactions = legacy_transactions(&item).expect("failed to parse transactions");
+        let ids: Vec<&str> = transactions.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            [
+                Ulid::from_parts(0, 0).to_string(),
+                Ulid::from_parts(0, 1).to_string(),
+                "01G0000000000000000000000".to_string(),
+            ]
+        );
+        assert_eq!(transactions[1].kind, TransactionKind::Sell);
+        assert_eq!(
+            legacy_transactions(&item).expect("failed to parse transactions"),
+            transactions
+        );
+    }
+}
@@ -0,0 +1,293 @@
+//! Australian capital gains tax. Coins are CGT assets, each disposal matched by the cost basis
+//! engine is a CGT event with the matched cost as its cost base. Gains on coins held for at
+//! least 12 months get the 50% discount for individuals
+
+use super::Date;
+use crate::{AuCgtReport, AuDisposal, CostBasis, CostBasisMethod};
+
+/// The method disposals are matched with for a user. Every lot method keeps the date each coin
+/// was acquired for the discount, the Section 104 pool doesn't so it's matched first in first
+/// out
+pub fn method(user: CostBasisMethod) -> CostBasisMethod {
+    match user {
+        CostBasisMethod::Section104 => CostBasisMethod::Fifo,
+        method => method,
+    }
+}
+
+/// Offset of Australian Eastern Standard Time from UTC. The financial year changes over in
+/// winter so daylight saving never applies to it
+pub const AEST_OFFSET: i64 = 10 * 60 * 60;
+
+const HOUR_SECS: i64 = 60 * 60;
+
+/// Share of a discountable gain that isn't taxed
+pub const CGT_DISCOUNT: f64 = 0.5;
+
+/// Epoch seconds from the start of 1 July to the end of 30 June for the financial year ending
+/// in `year`, the end is exclusive
+pub fn financial_year(year: i64) -> (i64, i64) {
+    (
+        Date::new(year - 1, 7, 1).start(AEST_OFFSET),
+        Date::new(year, 7, 1).start(AEST_OFFSET),
+    )
+}
+
+/// The first Sunday of a month, when the clocks change
+fn first_sunday(year: i64, month: i64) -> Date {
+    let first = Date::new(year, month, 1);
+    Date::new(year, month, 1 + (7 - first.weekday()) % 7)
+}
+
+/// Offset of Sydney time from UTC at `timestamp`, daylight saving runs from 2am AEST on the
+/// first Sunday of October to 3am AEDT on the first Sunday of April
+pub fn sydney_offset(timestamp: i64) -> i64 {
+    let year = Date::at(timestamp, AEST_OFFSET).year;
+    let ends = first_sunday(year, 4).start(AEST_OFFSET) + 2 * HOUR_SECS;
+    let starts = first_sunday(year, 10).start(AEST_OFFSET) + 2 * HOUR_SECS;
+    if timestamp < ends || timestamp >= starts {
+        AEST_OFFSET + HOUR_SECS
+    } else {
+        AEST_OFFSET
+    }
+}
+
+/// The financial year `timestamp` is in, named by the year it ends
+pub fn financial_year_of(timestamp: i64) -> i64 {
+    let date = Date::at(timestamp, AEST_OFFSET);
+    if date.month >= 7 {
+        date.year + 1
+    } else {
+        date.year
+    }
+}
+
+/// Whether coins were held for at least 12 months, not counting the days they were acquired
+/// and disposed of, so the disposal must be after the first anniversary
+pub fn discountable(acquired_at: i64, disposed_at: i64) -> bool {
+    Date::at(disposed_at, AEST_OFFSET) > Date::at(acquired_at, AEST_OFFSET).add_years(1)
+}
+
+/// The capital gains report for the financial year ending 30 June of `year`, from a user's
+/// cost basis. Coins disposed of without being held have no cost base or discount
+pub fn cgt_report(basis: &CostBasis, year: i64) -> AuCgtReport {
+    let (from, to) = financial_year(year);
+    let disposals: Vec<AuDisposal> = basis
+        .matches
+        .iter()
+        .filter(|m| m.disposed_at >= from && m.disposed_at < to)
+        .map(|m| {
+            let held = !m.lot_id.is_empty();
+            AuDisposal {
+                disposal_id: m.disposal_id.clone(),
+                coin: m.coin.clone(),
+                acquired: if held {
+                    Date::at(m.acquired_at, AEST_OFFSET).to_string()
+                } else {
+                    String::new()
+                },
+                disposed: Date::at(m.disposed_at, AEST_OFFSET).to_string(),
+                amount: m.amount,
+                cost_base: m.cost,
+                proceeds: m.proceeds,
+                gain: m.gain(),
+                discountable: held && discountable(m.acquired_at, m.disposed_at),
+            }
+        })
+        .collect();
+
+    let gains = |discountable: bool| -> f64 {
+        disposals
+            .iter()
+            .filter(|d| d.gain > 0.0 && d.discountable == discountable)
+            .map(|d| d.gain)
+            .sum()
+    };
+    let non_discountable_gains = gains(false);
+    let discountable_gains = gains(true);
+    let capital_losses: f64 = disposals
+        .iter()
+        .filter(|d| d.gain < 0.0)
+        .map(|d| -d.gain)
+        .sum();
+
+    // Losses are applied where they save the most tax, gains without the discount first
+    let non_discountable_left = (non_discountable_gains - capital_losses).max(0.0);
+    let losses_left = (capital_losses - non_discountable_gains).max(0.0);
+    let discountable_left = (discountable_gains - losses_left).max(0.0);
+    let discount = discountable_left * CGT_DISCOUNT;
+
+    AuCgtReport {
+        financial_year: format!("{}-{:02}", year - 1, year % 100),
+        disposals,
+        non_discountable_gains,
+        discountable_gains,
+        capital_losses,
+        discount,
+        net_capital_gain: non_discountable_left + discountable_left - discount,
+        net_capital_loss: (losses_left - discountable_gains).max(0.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LotMatch;
+
+    fn at(year: i64, month: i64, day: i64) -> i64 {
+        // Midday so the date is the same in any Australian timezone
+        Date::new(year, month, day).start(AEST_OFFSET) + 12 * 60 * 60
+    }
+
+    fn matched(acquired_at: i64, disposed_at: i64, cost: f64, proceeds: f64) -> LotMatch {
+        LotMatch {
+            disposal_id: "d".to_string(),
+            coin: "ETHAUD".to_string(),
+            disposed_at,
+            lot_id: "a".to_string(),
+            acquired_at,
+            amount: 1.0,
+            cost,
+            proceeds,
+            rule: None,
+        }
+    }
+
+    fn report(matches: Vec<LotMatch>) -> AuCgtReport {
+        cgt_report(
+            &CostBasis {
+                matches,
+                ..Default::default()
+            },
+            2023,
+        )
+    }
+
+    #[test]
+    fn keeps_lot_methods() {
+        assert_eq!(method(CostBasisMethod::Hifo), CostBasisMethod::Hifo);
+        assert_eq!(
+            method(CostBasisMethod::AverageCost),
+            CostBasisMethod::AverageCost
+        );
+        assert_eq!(method(CostBasisMethod::Section104), CostBasisMethod::Fifo);
+    }
+
+    #[test]
+    fn financial_year_runs_july_to_june() {
+        let (from, to) = financial_year(2023);
+        assert_eq!(Date::at(from, AEST_OFFSET), Date::new(2022, 7, 1));
+        assert_eq!(Date::at(to - 1, AEST_OFFSET), Date::new(2023, 6, 30));
+        // Midnight 1 July in Sydney is 2pm 30 June UTC
+        assert_eq!(Date::at(from, 0), Date::new(2022, 6, 30));
+        assert_eq!(financial_year_of(from), 2023);
+        assert_eq!(financial_year_of(from - 1), 2022);
+    }
+
+    #[test]
+    fn sydney_daylight_saving() {
+        // Clocks went back on 3 April 2022 and forward on 2 October 2022
+        assert_eq!(first_sunday(2022, 4), Date::new(2022, 4, 3));
+        assert_eq!(first_sunday(2022, 10), Date::new(2022, 10, 2));
+        assert_eq!(first_sunday(2023, 10), Date::new(2023, 10, 1));
+        let back = Date::new(2022, 4, 3).start(AEST_OFFSET) + 2 * HOUR_SECS;
+        assert_eq!(sydney_offset(back - 1), AEST_OFFSET + HOUR_SECS);
+        assert_eq!(sydney_offset(back), AEST_OFFSET);
+        let forward = Date::new(2022, 10, 2).start(AEST_OFFSET) + 2 * HOUR_SECS;
+        assert_eq!(sydney_offset(forward - 1), AEST_OFFSET);
+        assert_eq!(sydney_offset(forward), AEST_OFFSET + HOUR_SECS);
+    }
+
+    #[test]
+    fn discount_needs_more_than_a_year() {
+        let bought = at(2021, 7, 1);
+        assert!(!discountable(bought, at(2022, 6, 30)));
+        assert!(!discountable(bought, at(2022, 7, 1)));
+        assert!(discountable(bought, at(2022, 7, 2)));
+
+        let leap_day = at(2020, 2, 29);
+        assert!(!discountable(leap_day, at(2021, 2, 28)));
+        assert!(discountable(leap_day, at(2021, 3, 1)));
+    }
+
+    #[test]
+    fn lists_disposals_in_the_year() {
+        let report = report(vec![
+            matched(at(2021, 1, 10), at(2022, 6, 30), 1000.0, 1500.0),
+            matched(at(2021, 1, 10), at(2022, 7, 1), 1000.0, 1500.0),
+            matched(at(2022, 3, 5), at(2023, 6, 30), 2000.0, 1800.0),
+            matched(at(2022, 3, 5), at(2023, 7, 1), 2000.0, 1800.0),
+        ]);
+
+        assert_eq!(report.financial_year, "2022-23");
+        assert_eq!(report.disposals.len(), 2);
+        assert_eq!(
+            report.disposals[0],
+            AuDisposal {
+                disposal_id: "d".to_string(),
+                coin: "ETHAUD".to_string(),
+                acquired: "2021-01-10".to_string(),
+                disposed: "2022-07-01".to_string(),
+                amount: 1.0,
+                cost_base: 1000.0,
+                proceeds: 1500.0,
+                gain: 500.0,
+                discountable: true,
+            }
+        );
+        assert_eq!(report.disposals[1].gain, -200.0);
+        assert!(report.disposals[1].discountable);
+    }
+
+    #[test]
+    fn discounts_gains_held_over_a_year() {
+        let report = report(vec![
+            matched(at(2021, 1, 1), at(2022, 8, 1), 1000.0, 2000.0),
+            matched(at(2022, 8, 1), at(2022, 9, 1), 1000.0, 1400.0),
+        ]);
+
+        assert_eq!(report.discountable_gains, 1000.0);
+        assert_eq!(report.non_discountable_gains, 400.0);
+        assert_eq!(report.discount, 500.0);
+        assert_eq!(report.net_capital_gain, 900.0);
+        assert_eq!(report.net_capital_loss, 0.0);
+    }
+
+    #[test]
+    fn losses_reduce_non_discountable_gains_first() {
+        let report = report(vec![
+            matched(at(2021, 1, 1), at(2022, 8, 1), 1000.0, 2000.0),
+            matched(at(2022, 8, 1), at(2022, 9, 1), 1000.0, 1400.0),
+            matched(at(2022, 8, 1), at(2022, 10, 1), 1000.0, 400.0),
+        ]);
+
+        assert_eq!(report.capital_losses, 600.0);
+        // 400 of the loss uses up the short term gain, 200 reduces the long term gain to 800
+        assert_eq!(report.discount, 400.0);
+        assert_eq!(report.net_capital_gain, 400.0);
+    }
+
+    #[test]
+    fn carries_forward_a_net_loss() {
+        let report = report(vec![
+            matched(at(2021, 1, 1), at(2022, 8, 1), 1000.0, 1300.0),
+            matched(at(2022, 8, 1), at(2022, 10, 1), 1000.0, 200.0),
+        ]);
+
+        assert_eq!(report.net_capital_gain, 0.0);
+        assert_eq!(report.discount, 0.0);
+        assert_eq!(report.net_capital_loss, 500.0);
+    }
+
+    #[test]
+    fn disposals_without_a_lot_have_no_discount() {
+        let mut short = matched(0, at(2022, 8, 1), 0.0, 500.0);
+        short.lot_id = String::new();
+
+        let report = report(vec![short]);
+
+        assert_eq!(report.disposals[0].acquired, "");
+        assert!(!report.disposals[0].discountable);
+        assert_eq!(report.net_capital_gain, 500.0);
+    }
+}
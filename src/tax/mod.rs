@@ -0,0 +1,134 @@
+//! Tax reports built from a user's cost basis. Each jurisdiction has its own module since
+//! the tax year, holding periods and how gains are reduced all differ
+
+pub mod au;
+pub mod uk;
+pub mod us;
+
+use std::fmt;
+
+const DAY_SECS: i64 = 24 * 60 * 60;
+
+/// A calendar date, tax years and holding periods are counted in whole days in the taxpayer's
+/// timezone rather than in seconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    pub year: i64,
+    pub month: i64,
+    pub day: i64,
+}
+
+impl Date {
+    pub fn new(year: i64, month: i64, day: i64) -> Date {
+        Date { year, month, day }
+    }
+
+    /// The date at `timestamp` epoch seconds in a timezone `offset` seconds ahead of UTC
+    pub fn at(timestamp: i64, offset: i64) -> Date {
+        // Days since 1970-01-01 converted to a civil date, from Howard Hinnant's date algorithms
+        let days = (timestamp + offset).div_euclid(DAY_SECS) + 719468;
+        let era = days.div_euclid(146097);
+        let day_of_era = days - era * 146097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+        Date { year, month, day }
+    }
+
+    /// Epoch seconds at the start of the date in a timezone `offset` seconds ahead of UTC
+    pub fn start(&self, offset: i64) -> i64 {
+        let year = self.year - i64::from(self.month <= 2);
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let shifted_month = if self.month > 2 {
+            self.month - 3
+        } else {
+            self.month + 9
+        };
+        let day_of_year = (153 * shifted_month + 2) / 5 + self.day - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        (era * 146097 + day_of_era - 719468) * DAY_SECS - offset
+    }
+
+    /// Days since 1 January 1970, for counting the days between dates
+    pub fn days(&self) -> i64 {
+        self.start(0).div_euclid(DAY_SECS)
+    }
+
+    /// Day of the week from 0 for Sunday to 6 for Saturday
+    pub fn weekday(&self) -> i64 {
+        // 1 January 1970 was a Thursday
+        (self.days() + 4).rem_euclid(7)
+    }
+
+    /// The same day and month `years` later, 29 February stays as is so it sorts between
+    /// 28 February and 1 March of a year without it
+    pub fn add_years(&self, years: i64) -> Date {
+        Date {
+            year: self.year + years,
+            ..*self
+        }
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_from_timestamps() {
+        assert_eq!(Date::at(0, 0), Date::new(1970, 1, 1));
+        assert_eq!(Date::at(1656597599, 0), Date::new(2022, 6, 30));
+        assert_eq!(Date::at(-1, 0), Date::new(1969, 12, 31));
+        assert_eq!(Date::at(951782400, 0), Date::new(2000, 2, 29));
+        // 30 June 14:00 UTC is already 1 July in Sydney
+        assert_eq!(Date::at(1656597600, 10 * 60 * 60), Date::new(2022, 7, 1));
+    }
+
+    #[test]
+    fn start_of_date_round_trips() {
+        for date in [
+            Date::new(1970, 1, 1),
+            Date::new(2000, 2, 29),
+            Date::new(2022, 7, 1),
+            Date::new(2024, 12, 31),
+        ] {
+            for offset in [0, 10 * 60 * 60, -5 * 60 * 60] {
+                assert_eq!(Date::at(date.start(offset), offset), date);
+                assert!(Date::at(date.start(offset) - 1, offset) < date);
+            }
+        }
+        assert_eq!(Date::new(2022, 7, 1).start(0), 1656633600);
+    }
+
+    #[test]
+    fn counts_days_and_weekdays() {
+        assert_eq!(Date::new(1970, 1, 2).days(), 1);
+        assert_eq!(
+            Date::new(2022, 7, 31).days() - Date::new(2022, 7, 1).days(),
+            30
+        );
+        assert_eq!(Date::new(1970, 1, 1).weekday(), 4);
+        assert_eq!(Date::new(2023, 3, 26).weekday(), 0);
+        assert_eq!(Date::new(1969, 12, 31).weekday(), 3);
+    }
+
+    #[test]
+    fn formats_as_iso_date() {
+        assert_eq!(Date::new(2022, 7, 1).to_string(), "2022-07-01");
+    }
+}
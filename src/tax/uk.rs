@@ -0,0 +1,209 @@
+//! UK capital gains tax on cryptoassets. HMRC treats coins like shares, so disposals must be
+//! matched with `CostBasisMethod::Section104` rather than the user's chosen method. Days are
+//! UK days, which follow British Summer Time
+
+use super::Date;
+use crate::{CostBasis, UkDisposal, UkGainsReport};
+
+const HOUR_SECS: i64 = 60 * 60;
+
+/// The last Sunday of a month with 31 days, when the clocks change
+fn last_sunday(year: i64, month: i64) -> Date {
+    let last = Date::new(year, month, 31);
+    Date::new(year, month, 31 - last.weekday())
+}
+
+/// Offset of UK time from UTC at `timestamp`, British Summer Time runs from 1am UTC on the
+/// last Sunday of March to 1am UTC on the last Sunday of October
+pub fn offset(timestamp: i64) -> i64 {
+    let year = Date::at(timestamp, 0).year;
+    let starts = last_sunday(year, 3).start(0) + HOUR_SECS;
+    let ends = last_sunday(year, 10).start(0) + HOUR_SECS;
+    if timestamp >= starts && timestamp < ends {
+        HOUR_SECS
+    } else {
+        0
+    }
+}
+
+/// The UK date at `timestamp`
+pub fn date(timestamp: i64) -> Date {
+    Date::at(timestamp, offset(timestamp))
+}
+
+/// Epoch seconds from the start of 6 April to the end of 5 April for the tax year ending in
+/// `year`, the end is exclusive. 6 April is always in British Summer Time
+pub fn tax_year(year: i64) -> (i64, i64) {
+    (
+        Date::new(year - 1, 4, 6).start(HOUR_SECS),
+        Date::new(year, 4, 6).start(HOUR_SECS),
+    )
+}
+
+/// The tax year `timestamp` is in, named by the year it ends
+pub fn tax_year_of(timestamp: i64) -> i64 {
+    let date = date(timestamp);
+    if (date.month, date.day) >= (4, 6) {
+        date.year + 1
+    } else {
+        date.year
+    }
+}
+
+/// The gains report for the tax year ending 5 April of `year`, `basis` must be built with
+/// `CostBasisMethod::Section104`
+pub fn gains_report(basis: &CostBasis, year: i64) -> UkGainsReport {
+    let (from, to) = tax_year(year);
+    let disposals: Vec<UkDisposal> = basis
+        .matches
+        .iter()
+        .filter(|m| m.disposed_at >= from && m.disposed_at < to)
+        .map(|m| UkDisposal {
+            disposal_id: m.disposal_id.clone(),
+            coin: m.coin.clone(),
+            disposed: date(m.disposed_at).to_string(),
+            amount: m.amount,
+            proceeds: m.proceeds,
+            allowable_cost: m.cost,
+            gain: m.gain(),
+            rule: m.rule,
+        })
+        .collect();
+
+    let gains: f64 = disposals
+        .iter()
+        .filter(|d| d.gain > 0.0)
+        .map(|d| d.gain)
+        .sum();
+    let losses: f64 = disposals
+        .iter()
+        .filter(|d| d.gain < 0.0)
+        .map(|d| -d.gain)
+        .sum();
+
+    UkGainsReport {
+        tax_year: format!("{}-{:02}", year - 1, year % 100),
+        total_proceeds: disposals.iter().map(|d| d.proceeds).sum(),
+        allowable_costs: disposals.iter().map(|d| d.allowable_cost).sum(),
+        disposals,
+        pool: basis
+            .pool
+            .iter()
+            .filter(|event| event.executed_at >= from && event.executed_at < to)
+            .cloned()
+            .collect(),
+        gains,
+        losses,
+        net_gain: gains - losses,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::transaction;
+    use crate::{basis, CostBasisMethod, MatchRule, TransactionKind};
+
+    #[test]
+    fn british_summer_time() {
+        // Clocks went forward on 27 March 2022 and back on 30 October 2022
+        assert_eq!(last_sunday(2022, 3), Date::new(2022, 3, 27));
+        assert_eq!(last_sunday(2022, 10), Date::new(2022, 10, 30));
+        let forward = Date::new(2022, 3, 27).start(0) + HOUR_SECS;
+        assert_eq!(offset(forward - 1), 0);
+        assert_eq!(offset(forward), HOUR_SECS);
+        let back = Date::new(2022, 10, 30).start(0) + HOUR_SECS;
+        assert_eq!(offset(back - 1), HOUR_SECS);
+        assert_eq!(offset(back), 0);
+
+        // 11:30pm UTC in summer is already the next day in the UK
+        let late = Date::new(2022, 7, 1).start(0) + 23 * HOUR_SECS + 30 * 60;
+        assert_eq!(date(late), Date::new(2022, 7, 2));
+    }
+
+    #[test]
+    fn tax_year_runs_april_to_april() {
+        let (from, to) = tax_year(2023);
+        assert_eq!(date(from), Date::new(2022, 4, 6));
+        assert_eq!(date(to - 1), Date::new(2023, 4, 5));
+        assert_eq!(tax_year_of(from), 2023);
+        assert_eq!(tax_year_of(to - 1), 2023);
+        assert_eq!(tax_year_of(to), 2024);
+    }
+
+    #[test]
+    fn report_for_the_tax_year() {
+        let at = |year: i64, month: i64, day: i64| Date::new(year, month, day).start(0) + 43200;
+        let log = vec![
+            transaction(
+                "a",
+                "BTCGBP",
+                TransactionKind::Buy,
+                2.0,
+                10000.0,
+                at(2022, 1, 10),
+            ),
+            transaction(
+                "b",
+                "BTCGBP",
+                TransactionKind::Sell,
+                1.0,
+                15000.0,
+                at(2022, 4, 5),
+            ),
+            transaction(
+                "c",
+                "BTCGBP",
+                TransactionKind::Sell,
+                0.5,
+                8000.0,
+                at(2022, 5, 1),
+            ),
+            transaction(
+                "d",
+                "BTCGBP",
+                TransactionKind::Buy,
+                0.2,
+                9000.0,
+                at(2022, 5, 10),
+            ),
+        ];
+
+        let report = gains_report(&basis::cost_basis(&log, CostBasisMethod::Section104), 2023);
+
+        assert_eq!(report.tax_year, "2022-23");
+        // The sale on 5 April is in the year before
+        assert_eq!(
+            report.disposals,
+            vec![
+                UkDisposal {
+                    disposal_id: "c".to_string(),
+                    coin: "BTCGBP".to_string(),
+                    disposed: "2022-05-01".to_string(),
+                    amount: 0.2,
+                    proceeds: 1600.0,
+                    allowable_cost: 1800.0,
+                    gain: -200.0,
+                    rule: Some(MatchRule::BedAndBreakfast),
+                },
+                UkDisposal {
+                    disposal_id: "c".to_string(),
+                    coin: "BTCGBP".to_string(),
+                    disposed: "2022-05-01".to_string(),
+                    amount: 0.3,
+                    proceeds: 2400.0,
+                    allowable_cost: 3000.0,
+                    gain: -600.0,
+                    rule: Some(MatchRule::Section104),
+                },
+            ]
+        );
+        assert_eq!(report.total_proceeds, 4000.0);
+        assert_eq!(report.allowable_costs, 4800.0);
+        assert_eq!(report.losses, 800.0);
+        assert_eq!(report.net_gain, -800.0);
+        // Only the sale from the pool changed it this year, the buy was matched to the sale
+        assert_eq!(report.pool.len(), 1);
+        assert_eq!(report.pool[0].pool_amount, 0.7);
+    }
+}
@@ -0,0 +1,233 @@
+//! US capital gains tax. Coins are property, each disposal is reported on Form 8949 against
+//! the lots it was matched with. Gains on coins held for more than a year are long term.
+//! Days are in UTC since a user's state and timezone aren't known
+
+use super::Date;
+use crate::{
+    CostBasis, CostBasisMethod, Form8949Report, Form8949Row, HoldingPeriod, ScheduleD,
+    ScheduleDLine,
+};
+
+/// The method disposals are matched with for a user, the IRS accepts specific identification
+/// of the units sold and otherwise requires first in first out
+pub fn method(user: CostBasisMethod) -> CostBasisMethod {
+    match user {
+        CostBasisMethod::SpecificLot => CostBasisMethod::SpecificLot,
+        _ => CostBasisMethod::Fifo,
+    }
+}
+
+/// Epoch seconds from the start of 1 January to the end of 31 December of `year`, the end is
+/// exclusive
+pub fn tax_year(year: i64) -> (i64, i64) {
+    (
+        Date::new(year, 1, 1).start(0),
+        Date::new(year + 1, 1, 1).start(0),
+    )
+}
+
+/// The tax year `timestamp` is in
+pub fn tax_year_of(timestamp: i64) -> i64 {
+    Date::at(timestamp, 0).year
+}
+
+/// Whether coins were held for more than a year. The holding period starts the day after they
+/// were acquired, so the disposal must be after the first anniversary
+pub fn holding_period(acquired_at: i64, disposed_at: i64) -> HoldingPeriod {
+    if Date::at(disposed_at, 0) > Date::at(acquired_at, 0).add_years(1) {
+        HoldingPeriod::LongTerm
+    } else {
+        HoldingPeriod::ShortTerm
+    }
+}
+
+/// A date the way it's written on IRS forms
+fn form_date(timestamp: i64) -> String {
+    let date = Date::at(timestamp, 0);
+    format!("{:02}/{:02}/{:04}", date.month, date.day, date.year)
+}
+
+fn total(rows: &[Form8949Row], holding_period: HoldingPeriod) -> ScheduleDLine {
+    let rows = rows.iter().filter(|r| r.holding_period == holding_period);
+    let mut line = ScheduleDLine::default();
+    for row in rows {
+        line.proceeds += row.proceeds;
+        line.cost_basis += row.cost_basis;
+        line.gain += row.gain;
+    }
+    line
+}
+
+/// The Form 8949 rows and Schedule D summary for `year`, `basis` should be built with
+/// [`method`]. Coins disposed of without being held have no cost basis and are short term
+pub fn form_8949(basis: &CostBasis, year: i64) -> Form8949Report {
+    let (from, to) = tax_year(year);
+    let rows: Vec<Form8949Row> = basis
+        .matches
+        .iter()
+        .filter(|m| m.disposed_at >= from && m.disposed_at < to)
+        .map(|m| {
+            let held = !m.lot_id.is_empty();
+            Form8949Row {
+                disposal_id: m.disposal_id.clone(),
+                holding_period: if held {
+                    holding_period(m.acquired_at, m.disposed_at)
+                } else {
+                    HoldingPeriod::ShortTerm
+                },
+                description: format!("{} {}", m.amount, m.coin),
+                date_acquired: if held {
+                    form_date(m.acquired_at)
+                } else {
+                    String::new()
+                },
+                date_sold: form_date(m.disposed_at),
+                proceeds: m.proceeds,
+                cost_basis: m.cost,
+                gain: m.gain(),
+            }
+        })
+        .collect();
+
+    let short_term = total(&rows, HoldingPeriod::ShortTerm);
+    let long_term = total(&rows, HoldingPeriod::LongTerm);
+    Form8949Report {
+        tax_year: year,
+        schedule_d: ScheduleD {
+            net_gain: short_term.gain + long_term.gain,
+            short_term,
+            long_term,
+        },
+        rows,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::transaction;
+    use crate::{basis, TransactionKind};
+
+    fn at(year: i64, month: i64, day: i64) -> i64 {
+        Date::new(year, month, day).start(0) + 12 * 60 * 60
+    }
+
+    #[test]
+    fn long_term_after_a_year_and_a_day() {
+        let bought = at(2021, 3, 15);
+        assert_eq!(
+            holding_period(bought, at(2022, 3, 15)),
+            HoldingPeriod::ShortTerm
+        );
+        assert_eq!(
+            holding_period(bought, at(2022, 3, 16)),
+            HoldingPeriod::LongTerm
+        );
+    }
+
+    #[test]
+    fn uses_fifo_unless_lots_are_chosen() {
+        assert_eq!(method(CostBasisMethod::Hifo), CostBasisMethod::Fifo);
+        assert_eq!(method(CostBasisMethod::Section104), CostBasisMethod::Fifo);
+        assert_eq!(
+            method(CostBasisMethod::SpecificLot),
+            CostBasisMethod::SpecificLot
+        );
+    }
+
+    #[test]
+    fn rows_and_schedule_d_for_the_year() {
+        let log = vec![
+            transaction(
+                "a",
+                "ETHUSD",
+                TransactionKind::Buy,
+                1.0,
+                1000.0,
+                at(2021, 6, 1),
+            ),
+            transaction(
+                "b",
+                "ETHUSD",
+                TransactionKind::Buy,
+                1.0,
+                3000.0,
+                at(2022, 2, 1),
+            ),
+            transaction(
+                "c",
+                "ETHUSD",
+                TransactionKind::Sell,
+                0.5,
+                1500.0,
+                at(2021, 12, 31),
+            ),
+            transaction(
+                "d",
+                "ETHUSD",
+                TransactionKind::Sell,
+                1.0,
+                2000.0,
+                at(2022, 8, 1),
+            ),
+            transaction(
+                "e",
+                "ETHUSD",
+                TransactionKind::Sell,
+                0.5,
+                2500.0,
+                at(2023, 1, 1),
+            ),
+        ];
+        let basis = basis::cost_basis(&log, method(CostBasisMethod::Fifo));
+
+        let report = form_8949(&basis, 2022);
+
+        assert_eq!(
+            report.rows,
+            vec![
+                Form8949Row {
+                    disposal_id: "d".to_string(),
+                    holding_period: HoldingPeriod::LongTerm,
+                    description: "0.5 ETHUSD".to_string(),
+                    date_acquired: "06/01/2021".to_string(),
+                    date_sold: "08/01/2022".to_string(),
+                    proceeds: 1000.0,
+                    cost_basis: 500.0,
+                    gain: 500.0,
+                },
+                Form8949Row {
+                    disposal_id: "d".to_string(),
+                    holding_period: HoldingPeriod::ShortTerm,
+                    description: "0.5 ETHUSD".to_string(),
+                    date_acquired: "02/01/2022".to_string(),
+                    date_sold: "08/01/2022".to_string(),
+                    proceeds: 1000.0,
+                    cost_basis: 1500.0,
+                    gain: -500.0,
+                },
+            ]
+        );
+        assert_eq!(report.schedule_d.short_term.gain, -500.0);
+        assert_eq!(report.schedule_d.long_term.proceeds, 1000.0);
+        assert_eq!(report.schedule_d.net_gain, 0.0);
+    }
+
+    #[test]
+    fn disposals_without_a_lot_are_short_term() {
+        let log = vec![transaction(
+            "a",
+            "ETHUSD",
+            TransactionKind::Sell,
+            1.0,
+            500.0,
+            at(2022, 5, 1),
+        )];
+
+        let report = form_8949(&basis::cost_basis(&log, CostBasisMethod::Fifo), 2022);
+
+        assert_eq!(report.rows[0].date_acquired, "");
+        assert_eq!(report.rows[0].holding_period, HoldingPeriod::ShortTerm);
+        assert_eq!(report.schedule_d.short_term.gain, 500.0);
+    }
+}
@@ -0,0 +1,24 @@
+//! Helpers shared by the unit tests
+
+use crate::{Transaction, TransactionKind};
+
+/// A transaction of `amount` of `coin` at `price` for the test user
+pub fn transaction(
+    id: &str,
+    coin: &str,
+    kind: TransactionKind,
+    amount: f64,
+    price: f64,
+    executed_at: i64,
+) -> Transaction {
+    Transaction {
+        id: id.to_string(),
+        username: "testuser".to_string(),
+        coin: coin.to_string(),
+        kind,
+        amount,
+        price,
+        executed_at,
+        ..Default::default()
+    }
+}
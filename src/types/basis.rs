@@ -0,0 +1,285 @@
+//! Cost basis of a user's coins, built from tax lots by the engine in `basis`. A lot is the
+//! coins from a single acquisition and what they cost, disposals are matched against lots
+//! using the user's chosen method
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+use super::is_default;
+
+/// How disposals are matched against the lots that are still open
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CostBasisMethod {
+    /// First in first out, the oldest lots are disposed of first
+    #[default]
+    Fifo,
+    /// Last in first out, the newest lots are disposed of first
+    Lifo,
+    /// Highest in first out, the lots that cost the most per coin are disposed of first
+    Hifo,
+    /// Every open lot is reduced by the same fraction, so each coin costs the pool's average
+    AverageCost,
+    /// The lots chosen on the disposal are used first, anything left over is matched first in
+    /// first out
+    SpecificLot,
+    /// HMRC share identification for UK taxpayers, acquisitions on the same day first, then
+    /// acquisitions in the following 30 days, then the Section 104 pool at its average cost
+    #[serde(rename = "section_104")]
+    Section104,
+}
+
+/// Which HMRC rule matched part of a disposal, only set with `CostBasisMethod::Section104`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchRule {
+    SameDay,
+    BedAndBreakfast,
+    #[serde(rename = "section_104")]
+    Section104,
+}
+
+/// Part of a lot chosen to be disposed of by a transaction, for specific lot identification
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct LotSelection {
+    // Id of the transaction that acquired the lot
+    pub id: String,
+    pub amount: f64,
+}
+
+/// Coins from a single acquisition that haven't been disposed of yet
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Lot {
+    // Id of the transaction that acquired the coins
+    pub id: String,
+    pub coin: String,
+    pub acquired_at: i64,
+    // Amount still held
+    pub amount: f64,
+    // Cost per coin in the quote currency, including the acquisition fee
+    pub unit_cost: f64,
+}
+
+/// Part of a disposal matched against a single lot. A disposal of more than was held has a
+/// match with an empty `lot_id` and no cost for the amount that wasn't held
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct LotMatch {
+    // Id of the disposing transaction
+    pub disposal_id: String,
+    pub coin: String,
+    pub disposed_at: i64,
+    pub lot_id: String,
+    pub acquired_at: i64,
+    pub amount: f64,
+    // Cost of the matched coins in the quote currency
+    pub cost: f64,
+    // Share of the disposal proceeds for the matched coins, after fees
+    pub proceeds: f64,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub rule: Option<MatchRule>,
+}
+
+/// A change to a coin's Section 104 pool, with the pool balance after it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct PoolEvent {
+    // Id of the transaction that changed the pool
+    pub id: String,
+    pub coin: String,
+    pub executed_at: i64,
+    // Negative when coins leave the pool
+    pub amount: f64,
+    pub cost: f64,
+    pub pool_amount: f64,
+    pub pool_cost: f64,
+}
+
+/// What's still held of a coin and what it cost
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct CoinBasis {
+    pub coin: String,
+    pub amount: f64,
+    // Total cost of the coins still held
+    pub cost: f64,
+    // Cost per coin still held, 0 if none are held
+    pub average_cost: f64,
+    // Open lots ordered by when they were acquired
+    pub lots: Vec<Lot>,
+}
+
+/// Cost basis of every coin a user has held, coins are ordered by symbol and matches by when
+/// the disposal was executed
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct CostBasis {
+    pub method: CostBasisMethod,
+    pub coins: Vec<CoinBasis>,
+    pub matches: Vec<LotMatch>,
+    // Every change to the Section 104 pools in execution order, only with that method
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub pool: Vec<PoolEvent>,
+}
+
+/// Profit and loss from the cost basis, of a single coin or a whole portfolio. Unrealized is
+/// for what's still held at the current price, realized is from every disposal to date
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Pnl {
+    // What the coins still held cost
+    pub cost_basis: f64,
+    // Cost per coin still held including fees, 0 for a portfolio
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub average_entry_price: f64,
+    pub unrealized: f64,
+    // Unrealized gain as a percentage of the cost basis, 0 if nothing is held
+    pub unrealized_percent: f64,
+    pub realized: f64,
+}
+
+impl Pnl {
+    /// Profit and loss of holdings that cost `cost_basis` and are now worth `value`
+    pub fn new(cost_basis: f64, value: f64, realized: f64) -> Pnl {
+        let unrealized = value - cost_basis;
+        Pnl {
+            cost_basis,
+            average_entry_price: 0.0,
+            unrealized,
+            unrealized_percent: if cost_basis > 0.0 {
+                unrealized / cost_basis * 100.0
+            } else {
+                0.0
+            },
+            realized,
+        }
+    }
+}
+
+impl CostBasis {
+    /// Cost basis of a coin, `None` if the user has never held it
+    pub fn coin(&self, coin: &str) -> Option<&CoinBasis> {
+        self.coins.iter().find(|c| c.coin == coin)
+    }
+
+    /// Total gain from every disposal of a coin, negative for a loss
+    pub fn realized(&self, coin: &str) -> f64 {
+        self.matches
+            .iter()
+            .filter(|m| m.coin == coin)
+            .map(LotMatch::gain)
+            .sum()
+    }
+
+    /// Total gain from every disposal of every coin, including coins no longer held
+    pub fn total_realized(&self) -> f64 {
+        self.matches.iter().map(LotMatch::gain).sum()
+    }
+}
+
+impl LotMatch {
+    /// Capital gain of the matched coins, negative for a loss
+    pub fn gain(&self) -> f64 {
+        self.proceeds - self.cost
+    }
+}
+
+impl CostBasisMethod {
+    pub const ALL: [CostBasisMethod; 6] = [
+        CostBasisMethod::Fifo,
+        CostBasisMethod::Lifo,
+        CostBasisMethod::Hifo,
+        CostBasisMethod::AverageCost,
+        CostBasisMethod::SpecificLot,
+        CostBasisMethod::Section104,
+    ];
+
+    /// The name used in json and when stored in dynamodb
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CostBasisMethod::Fifo => "fifo",
+            CostBasisMethod::Lifo => "lifo",
+            CostBasisMethod::Hifo => "hifo",
+            CostBasisMethod::AverageCost => "average_cost",
+            CostBasisMethod::SpecificLot => "specific_lot",
+            CostBasisMethod::Section104 => "section_104",
+        }
+    }
+}
+
+impl MatchRule {
+    /// The name used in json
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MatchRule::SameDay => "same_day",
+            MatchRule::BedAndBreakfast => "bed_and_breakfast",
+            MatchRule::Section104 => "section_104",
+        }
+    }
+}
+
+impl fmt::Display for CostBasisMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for CostBasisMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CostBasisMethod::ALL
+            .into_iter()
+            .find(|method| method.as_str() == s)
+            .ok_or(format!("unknown cost basis method {}", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pnl_from_cost_and_value() {
+        let pnl = Pnl::new(2000.0, 2500.0, -100.0);
+        assert_eq!(pnl.unrealized, 500.0);
+        assert_eq!(pnl.unrealized_percent, 25.0);
+        assert_eq!(pnl.realized, -100.0);
+
+        assert_eq!(Pnl::new(0.0, 0.0, 50.0).unrealized_percent, 0.0);
+    }
+
+    #[test]
+    fn realized_gain_by_coin() {
+        let matched = |coin: &str, cost: f64, proceeds: f64| LotMatch {
+            coin: coin.to_string(),
+            cost,
+            proceeds,
+            ..Default::default()
+        };
+        let basis = CostBasis {
+            matches: vec![
+                matched("ETHAUD", 1000.0, 1500.0),
+                matched("BTCAUD", 3000.0, 2000.0),
+                matched("ETHAUD", 500.0, 400.0),
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(basis.realized("ETHAUD"), 400.0);
+        assert_eq!(basis.realized("BTCAUD"), -1000.0);
+        assert_eq!(basis.realized("ADAAUD"), 0.0);
+        assert_eq!(basis.total_realized(), -600.0);
+    }
+
+    #[test]
+    fn method_names_match_json() {
+        for method in CostBasisMethod::ALL {
+            let json = serde_json::to_string(&method).expect("failed to serialize method");
+            assert_eq!(json, format!("\"{}\"", method));
+            assert_eq!(method.as_str().parse::<CostBasisMethod>(), Ok(method));
+        }
+        for rule in [
+            MatchRule::SameDay,
+            MatchRule::BedAndBreakfast,
+            MatchRule::Section104,
+        ] {
+            let json = serde_json::to_string(&rule).expect("failed to serialize rule");
+            assert_eq!(json, format!("\"{}\"", rule.as_str()));
+        }
+    }
+}
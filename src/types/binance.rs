@@ -1,5 +1,8 @@
 //! Used to get current market value of different coins
 use serde::{Deserialize, Serialize};
+use std::num::ParseFloatError;
+
+use super::CoinStats;
 
 /// For deserializing a response from Binance for current coin market prices (bids and asks)
 #[derive(Serialize, Deserialize)]
@@ -11,3 +14,101 @@ pub struct BinancePrices {
     /// contain pairs in array format: [[price, amount], [price, amount]....]
     pub asks: Vec<Vec<String>>,
 }
+
+/// For deserializing a response from Binance for rolling 24 hour ticker statistics, Binance
+/// returns all numbers as strings so they're converted with `stats`
+#[derive(Serialize, Deserialize)]
+pub struct BinanceTicker24h {
+    pub symbol: String,
+    #[serde(rename = "priceChangePercent")]
+    pub price_change_percent: String,
+    #[serde(rename = "highPrice")]
+    pub high_price: String,
+    #[serde(rename = "lowPrice")]
+    pub low_price: String,
+    pub volume: String,
+    #[serde(rename = "quoteVolume")]
+    pub quote_volume: String,
+}
+
+impl BinanceTicker24h {
+    /// Converts to the provider independent stats that are stored with the coin
+    pub fn stats(&self) -> Result<CoinStats, ParseFloatError> {
+        Ok(CoinStats {
+            price_change_percent: self.price_change_percent.parse()?,
+            high_price: self.high_price.parse()?,
+            low_price: self.low_price.parse()?,
+            volume: self.volume.parse()?,
+            quote_volume: self.quote_volume.parse()?,
+        })
+    }
+}
+
+/// A message from a Binance combined stream, `stream` is the name of the subscribed
+/// stream e.g. `ethaud@miniTicker`
+#[derive(Serialize, Deserialize)]
+pub struct BinanceStreamEvent {
+    pub stream: String,
+    pub data: BinanceStreamData,
+}
+
+/// The payloads that can be received from the subscribed streams
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum BinanceStreamData {
+    MiniTicker(BinanceMiniTicker),
+    BookTicker(BinanceBookTicker),
+}
+
+/// Rolling 24 hour statistics pushed every second, numbers are strings
+#[derive(Serialize, Deserialize)]
+pub struct BinanceMiniTicker {
+    #[serde(rename = "e")]
+    pub event_type: String,
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "c")]
+    pub close_price: String,
+    #[serde(rename = "o")]
+    pub open_price: String,
+    #[serde(rename = "h")]
+    pub high_price: String,
+    #[serde(rename = "l")]
+    pub low_price: String,
+    #[serde(rename = "v")]
+    pub volume: String,
+    #[serde(rename = "q")]
+    pub quote_volume: String,
+}
+
+impl BinanceMiniTicker {
+    /// Converts to the provider independent stats that are stored with the coin,
+    /// the mini ticker doesn't include the percentage change so it's calculated
+    pub fn stats(&self) -> Result<CoinStats, ParseFloatError> {
+        let open: f64 = self.open_price.parse()?;
+        let close: f64 = self.close_price.parse()?;
+        let price_change_percent = if open > 0.0 {
+            (close - open) / open * 100.0
+        } else {
+            0.0
+        };
+        Ok(CoinStats {
+            price_change_percent,
+            high_price: self.high_price.parse()?,
+            low_price: self.low_price.parse()?,
+            volume: self.volume.parse()?,
+            quote_volume: self.quote_volume.parse()?,
+        })
+    }
+}
+
+/// Best bid and ask pushed whenever either changes, numbers are strings
+#[derive(Serialize, Deserialize)]
+pub struct BinanceBookTicker {
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "b")]
+    pub bid_price: String,
+    #[serde(rename = "a")]
+    pub ask_price: String,
+}
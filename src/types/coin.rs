@@ -1,8 +1,9 @@
 //! A coin is a crypto currency denomination, with prices and amounts.
 //! `symbol` is used to query market data e.g. `ETHAUD` `name` is used as a display name e.g. `Ethereum`
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
-use super::is_default;
+use super::{is_default, Pnl};
 
 /// All stored data for a coin, can be used with just name or symbol
 /// if either doesn't exist when deserialized it will use default values which is an Empty String.
@@ -19,6 +20,27 @@ pub struct Coin {
     pub price: f64,
     // The amount of coins for a transaction or total coins owned by a user
     pub amount: f64,
+    // Rolling 24 hour market statistics, omitted if the coin hasn't been refreshed with stats yet
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub stats: CoinStats,
+    // How the coin should be displayed
+    #[serde(default)]
+    pub metadata: CoinMetadata,
+    // Profit and loss of the amount held, from the user's cost basis
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub pnl: Pnl,
+}
+
+impl Coin {
+    /// Change in value of the amount held over the last 24 hours
+    pub fn daily_pnl(&self) -> f64 {
+        self.amount * self.stats.price_change(self.price)
+    }
+
+    /// Value of the amount held at the price
+    pub fn value(&self) -> f64 {
+        self.amount * self.price
+    }
 }
 
 /// Used inside maps where a coin symbol will map to a price and full name
@@ -27,6 +49,85 @@ pub struct Coin {
 pub struct CoinPrice {
     pub name: String,
     pub price: f64,
+    #[serde(default)]
+    pub stats: CoinStats,
+    #[serde(default)]
+    pub metadata: CoinMetadata,
+}
+
+/// Rolling 24 hour statistics for a coin, refreshed and stored alongside the price.
+/// Prices are in the quote currency of the symbol e.g. AUD for `ETHAUD`
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct CoinStats {
+    pub price_change_percent: f64,
+    pub high_price: f64,
+    pub low_price: f64,
+    // Volume in the base asset e.g. ETH for `ETHAUD`
+    pub volume: f64,
+    // Volume in the quote asset e.g. AUD for `ETHAUD`
+    pub quote_volume: f64,
+}
+
+impl CoinStats {
+    /// How much the price has moved in the last 24 hours, derived from the current price
+    /// and the percentage change. A fall of 100% leaves nothing to derive the old price from,
+    /// so the change is 0
+    pub fn price_change(&self, price: f64) -> f64 {
+        if self.price_change_percent <= -100.0 {
+            return 0.0;
+        }
+        price - price / (1.0 + self.price_change_percent / 100.0)
+    }
+}
+
+/// Information about a coin that clients use to display it, so they don't need to hard code
+/// anything per coin. Coins stored before metadata existed get the default values
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CoinMetadata {
+    // Native decimals of the coin e.g. 18 for Ethereum, 8 for Bitcoin
+    pub decimals: u32,
+    // How many decimals to show when displaying amounts
+    pub display_precision: u32,
+    // A url or name of the icon to display, empty if there isn't one
+    pub icon: String,
+    pub category: CoinCategory,
+    // Disabled coins are kept for existing holdings but aren't offered to users
+    pub active: bool,
+    // Lower numbers are displayed first
+    pub sort_order: i32,
+}
+
+impl Default for CoinMetadata {
+    fn default() -> Self {
+        CoinMetadata {
+            decimals: 8,
+            display_precision: 4,
+            icon: String::new(),
+            category: CoinCategory::default(),
+            active: true,
+            sort_order: 0,
+        }
+    }
+}
+
+/// Broad grouping of coins for filtering and display
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CoinCategory {
+    #[serde(rename = "layer-1")]
+    Layer1,
+    #[serde(rename = "layer-2")]
+    Layer2,
+    #[serde(rename = "stablecoin")]
+    Stablecoin,
+    #[serde(rename = "defi")]
+    Defi,
+    #[serde(rename = "exchange")]
+    Exchange,
+    #[serde(rename = "meme")]
+    Meme,
+    #[default]
+    #[serde(rename = "other")]
+    Other,
 }
 
 /// Used to deserialize a put request for coins, the lambda takes care of finding
@@ -42,4 +143,64 @@ pub struct CoinsPutRequest {
 pub struct CoinPutRequest {
     pub name: String,
     pub symbol: String,
+    // Replaces the stored metadata if provided, otherwise the stored metadata is left as is
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub metadata: Option<CoinMetadata>,
+}
+
+/// Response from refreshing coins, keyed by symbol. Prices that failed the anomaly guard
+/// weren't written and are listed in `quarantined` instead, symbols of disabled coins that
+/// weren't refreshed are listed in `disabled`
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct CoinsPutResponse {
+    pub coins: HashMap<String, CoinPrice>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub quarantined: Vec<QuarantinedPrice>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub disabled: Vec<String>,
+}
+
+/// A fetched price that looked suspicious, it's held until the next fetch confirms it
+#[derive(Serialize, Deserialize, Debug)]
+pub struct QuarantinedPrice {
+    pub name: String,
+    pub symbol: String,
+    pub price: f64,
+    // The stored price that is still being used, `None` if the coin is new
+    pub previous_price: Option<f64>,
+    pub reason: String,
+}
+
+/// Moves a coin to a new symbol e.g. when a pair moves from `ETHAUD` to `ETHUSDT`,
+/// the display name is kept if a new one isn't provided
+#[derive(Serialize, Deserialize)]
+pub struct CoinRenameRequest {
+    pub symbol: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub name: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(price_change_percent: f64) -> CoinStats {
+        CoinStats {
+            price_change_percent,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn price_change_from_percent() {
+        assert_eq!(stats(25.0).price_change(5000.0), 1000.0);
+        assert_eq!(stats(-20.0).price_change(4000.0), -1000.0);
+        assert_eq!(stats(0.0).price_change(4000.0), 0.0);
+    }
+
+    #[test]
+    fn price_change_after_total_fall() {
+        assert_eq!(stats(-100.0).price_change(0.0), 0.0);
+        assert_eq!(stats(-100.0).price_change(4000.0), 0.0);
+    }
 }
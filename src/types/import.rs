@@ -0,0 +1,291 @@
+//! Trade histories exported from exchanges, read into fills then turned into transactions by
+//! `transactions_import`
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use super::{is_default, Transaction, TransactionKind};
+
+/// Exchanges whose exports can be imported
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Exchange {
+    /// Spot trade history
+    Binance,
+    /// Ledgers, which include deposits, withdrawals and staking as well as trades
+    Kraken,
+    /// Order history
+    Coinspot,
+    /// Order history
+    IndependentReserve,
+    /// Koinly universal csv
+    Koinly,
+    /// CoinTracking csv import
+    Cointracking,
+}
+
+/// A trade or movement of coins read from an export, before it's priced. Assets are codes
+/// e.g. `BTC` or `AUD` rather than symbols
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Fill {
+    // Id of the trade on the exchange prefixed with the exchange e.g. `kraken:TXID`, built from
+    // the row when the export doesn't have one
+    pub external_id: String,
+    pub executed_at: i64,
+    // Buy and sell trade `asset` for `quote`, other kinds only move `asset`
+    pub kind: TransactionKind,
+    pub asset: String,
+    pub amount: f64,
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub quote: String,
+    // Amount of `quote` paid or received, before the fee. For income its value if the export
+    // has one, otherwise it's valued from the price history
+    #[serde(skip_serializing_if = "super::is_default", default)]
+    pub total: f64,
+    #[serde(skip_serializing_if = "super::is_default", default)]
+    pub fee: f64,
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub fee_asset: String,
+    // Fiat value of a trade between two coins if the file has it, both legs of the swap are
+    // priced from it rather than the price history
+    #[serde(skip_serializing_if = "super::is_default", default)]
+    pub value: f64,
+    // Tags from the export's note column, only tax software exports have them
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportStatus {
+    /// Would be stored, only in a dry run
+    Ready,
+    Imported,
+    /// Already imported, or earlier in the same file
+    Duplicate,
+    /// A movement of the quote currency, which isn't tracked
+    Ignored,
+    /// Couldn't be read or failed validation so it was never written
+    Invalid,
+    /// Valid but the write failed
+    Failed,
+    /// Valid but not written because an earlier write failed
+    Skipped,
+}
+
+/// The outcome of each trade in the file, in the order they appear. A swap has a transaction
+/// for each leg
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ImportResult {
+    pub index: usize,
+    pub status: ImportStatus,
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub external_id: String,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub transactions: Vec<Transaction>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ImportResponse {
+    pub dry_run: bool,
+    // Trades stored, or that would be in a dry run
+    pub imported: usize,
+    pub duplicates: usize,
+    pub results: Vec<ImportResult>,
+}
+
+/// Which character separates the whole and fractional parts of a number, the other of `.` and
+/// `,` is read as a thousands separator
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DecimalSeparator {
+    /// e.g. `1,234.5`
+    #[default]
+    Point,
+    /// e.g. `1.234,5`
+    Comma,
+}
+
+/// Headers of the columns in a spreadsheet, the optional ones are empty if it doesn't have them
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ColumnMapping {
+    pub date: String,
+    pub kind: String,
+    pub asset: String,
+    pub amount: String,
+    // Price of one coin in the quote currency, used when there's no total
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub price: String,
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub total: String,
+    // Without it every trade is against the quote currency of the import
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub quote: String,
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub fee: String,
+    // Without it fees are in the quote currency
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub fee_asset: String,
+    // A unique id for each row, without it the id is built from the whole row
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub id: String,
+}
+
+/// How to read a user's own spreadsheet of trades, saved by name so the same layout can be
+/// imported again
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ImportMapping {
+    // Taken from the path when saved
+    #[serde(default)]
+    pub name: String,
+    pub columns: ColumnMapping,
+    // strftime style e.g. `%d.%m.%Y %H:%M`, without one common date formats are recognised
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub date_format: String,
+    // Seconds the spreadsheet's times are ahead of UTC
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub utc_offset: i64,
+    // A single character, `,` if empty
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub delimiter: String,
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub decimal: DecimalSeparator,
+    // Values of the kind column and the kind they are e.g. `Kauf` to `buy`, compared ignoring
+    // case. Values that aren't here must be the name of a kind
+    #[serde(skip_serializing_if = "HashMap::is_empty", default)]
+    pub kinds: HashMap<String, TransactionKind>,
+    // Asset codes used in the spreadsheet and the code they're tracked as e.g. `XBT` to `BTC`
+    #[serde(skip_serializing_if = "HashMap::is_empty", default)]
+    pub assets: HashMap<String, String>,
+}
+
+impl ImportMapping {
+    /// Characters that can follow `%` in `date_format`
+    pub const DATE_DIRECTIVES: &'static str = "YymdHIMSfps%";
+
+    /// Checks the mapping can be used to read a spreadsheet, returns a message explaining
+    /// what's wrong
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.is_empty() {
+            return Err("name must not be empty".to_string());
+        }
+        let columns = &self.columns;
+        for (name, column) in [
+            ("date", &columns.date),
+            ("kind", &columns.kind),
+            ("asset", &columns.asset),
+            ("amount", &columns.amount),
+        ] {
+            if column.is_empty() {
+                return Err(format!("columns.{} must not be empty", name));
+            }
+        }
+        if !self.delimiter.is_empty() && (self.delimiter.len() != 1 || !self.delimiter.is_ascii()) {
+            return Err("delimiter must be a single character".to_string());
+        }
+        let mut directives = self.date_format.split('%').skip(1);
+        while let Some(directive) = directives.next() {
+            // `%%` splits into an empty part, the part after it is literal text
+            match directive.chars().next() {
+                Some(c) if Self::DATE_DIRECTIVES.contains(c) => {}
+                None => {
+                    directives.next();
+                }
+                Some(c) => return Err(format!("unknown date_format directive %{}", c)),
+            }
+        }
+        if let Some((from, _)) = self.assets.iter().find(|(_, to)| to.trim().is_empty()) {
+            return Err(format!("asset {} must map to a code", from));
+        }
+        Ok(())
+    }
+
+    /// The character between fields, `,` if the mapping doesn't have one
+    pub fn delimiter_char(&self) -> char {
+        self.delimiter.chars().next().unwrap_or(',')
+    }
+}
+
+impl Exchange {
+    pub const ALL: [Exchange; 6] = [
+        Exchange::Binance,
+        Exchange::Kraken,
+        Exchange::Coinspot,
+        Exchange::IndependentReserve,
+        Exchange::Koinly,
+        Exchange::Cointracking,
+    ];
+
+    /// The name used in json and in the import path
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Exchange::Binance => "binance",
+            Exchange::Kraken => "kraken",
+            Exchange::Coinspot => "coinspot",
+            Exchange::IndependentReserve => "independent_reserve",
+            Exchange::Koinly => "koinly",
+            Exchange::Cointracking => "cointracking",
+        }
+    }
+}
+
+impl fmt::Display for Exchange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Exchange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Exchange::ALL
+            .into_iter()
+            .find(|exchange| exchange.as_str() == s)
+            .ok_or(format!("unknown exchange {}", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping() -> ImportMapping {
+        ImportMapping {
+            name: "sheet".to_string(),
+            columns: ColumnMapping {
+                date: "Datum".to_string(),
+                kind: "Typ".to_string(),
+                asset: "Coin".to_string(),
+                amount: "Menge".to_string(),
+                ..Default::default()
+            },
+            date_format: "%d.%m.%Y %H:%M 100%%".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn validates_mapping() {
+        assert_eq!(mapping().validate(), Ok(()));
+
+        let mut missing = mapping();
+        missing.columns.amount = String::new();
+        assert_eq!(
+            missing.validate(),
+            Err("columns.amount must not be empty".to_string())
+        );
+
+        let mut format = mapping();
+        format.date_format = "%d/%q".to_string();
+        assert_eq!(
+            format.validate(),
+            Err("unknown date_format directive %q".to_string())
+        );
+
+        let mut delimiter = mapping();
+        delimiter.delimiter = ";;".to_string();
+        assert!(delimiter.validate().is_err());
+    }
+}
@@ -12,6 +12,15 @@ pub use transaction::*;
 pub mod user;
 pub use user::*;
 
+pub mod report;
+pub use report::*;
+
+pub mod basis;
+pub use basis::*;
+
+pub mod import;
+pub use import::*;
+
 /// Used to skip serialization if the value is default e.g. 0 or ""
 pub fn is_default<T: Default + PartialEq>(t: &T) -> bool {
     t == &T::default()
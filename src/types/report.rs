@@ -0,0 +1,203 @@
+//! Reports built from a user's transactions, returned from `reports_get`
+use serde::{Deserialize, Serialize};
+
+use super::{CostBasisMethod, Jurisdiction, LotSelection, MatchRule, PoolEvent, TransactionKind};
+
+/// Total fees paid in a single asset, `asset` is empty for fees paid in the quote currency
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct FeeSummary {
+    pub asset: String,
+    // Total fees in the asset
+    pub amount: f64,
+    // Total fees in the quote currency at the time they were paid
+    pub value: f64,
+    // How many transactions had a fee in the asset
+    pub transactions: usize,
+}
+
+/// Income received in a single coin of a single kind e.g. staking rewards in ETHAUD
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct IncomeSummary {
+    pub coin: String,
+    pub kind: TransactionKind,
+    // Total coins received
+    pub amount: f64,
+    // Total market value in the quote currency when the coins were received
+    pub value: f64,
+    // How many times income was received
+    pub transactions: usize,
+}
+
+/// Income received between `from` and `to` epoch seconds, `to` is exclusive. The value of
+/// each receipt is ordinary income and the cost basis of the coins received
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct IncomeReport {
+    pub from: i64,
+    pub to: i64,
+    // Ordered by coin then kind
+    pub income: Vec<IncomeSummary>,
+    pub total: f64,
+}
+
+/// A disposal in an Australian capital gains report, dates are in Australian Eastern Standard
+/// Time. `acquired` is empty for coins disposed of without being held
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct AuDisposal {
+    pub disposal_id: String,
+    pub coin: String,
+    pub acquired: String,
+    pub disposed: String,
+    pub amount: f64,
+    pub cost_base: f64,
+    pub proceeds: f64,
+    // Negative for a capital loss
+    pub gain: f64,
+    // Held for at least 12 months so the gain can be discounted
+    pub discountable: bool,
+}
+
+/// Capital gains for an Australian financial year, 1 July to 30 June, worked out in the order
+/// of the ATO's method: losses reduce gains that can't be discounted first, then the 50%
+/// discount applies to what's left of the discountable gains
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct AuCgtReport {
+    // e.g. `2022-23` for the year ending 30 June 2023
+    pub financial_year: String,
+    pub disposals: Vec<AuDisposal>,
+    // Gains held under 12 months, before losses
+    pub non_discountable_gains: f64,
+    // Gains held for at least 12 months, before losses and the discount
+    pub discountable_gains: f64,
+    // Losses made in the year, as a positive amount
+    pub capital_losses: f64,
+    pub discount: f64,
+    pub net_capital_gain: f64,
+    // Losses left over once every gain is reduced to 0, carried forward to later years
+    pub net_capital_loss: f64,
+}
+
+/// A disposal matched by a single HMRC rule in a UK capital gains report, a disposal matched
+/// by several rules has a row for each. Dates are in UK time
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct UkDisposal {
+    pub disposal_id: String,
+    pub coin: String,
+    pub disposed: String,
+    pub amount: f64,
+    pub proceeds: f64,
+    pub allowable_cost: f64,
+    // Negative for a loss
+    pub gain: f64,
+    // `None` for coins disposed of without being held, which have no allowable cost
+    pub rule: Option<MatchRule>,
+}
+
+/// Capital gains for a UK tax year, 6 April to 5 April, with disposals matched by HMRC's share
+/// identification rules. Includes every change to the Section 104 pools in the year
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct UkGainsReport {
+    // e.g. `2022-23` for the year ending 5 April 2023
+    pub tax_year: String,
+    pub disposals: Vec<UkDisposal>,
+    pub pool: Vec<PoolEvent>,
+    pub total_proceeds: f64,
+    pub allowable_costs: f64,
+    pub gains: f64,
+    // Losses made in the year, as a positive amount
+    pub losses: f64,
+    pub net_gain: f64,
+}
+
+/// Whether a US disposal was held for more than a year, short term gains are taxed as
+/// ordinary income
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HoldingPeriod {
+    #[default]
+    ShortTerm,
+    LongTerm,
+}
+
+/// A row of IRS Form 8949, in Part I for short term and Part II for long term. Dates are
+/// MM/DD/YYYY in UTC, `date_acquired` is empty for coins disposed of without being held
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct Form8949Row {
+    pub disposal_id: String,
+    pub holding_period: HoldingPeriod,
+    // e.g. `0.5 ETHUSD`
+    pub description: String,
+    pub date_acquired: String,
+    pub date_sold: String,
+    pub proceeds: f64,
+    pub cost_basis: f64,
+    // Negative for a loss
+    pub gain: f64,
+}
+
+/// Totals of a part of Form 8949, carried to Schedule D
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct ScheduleDLine {
+    pub proceeds: f64,
+    pub cost_basis: f64,
+    pub gain: f64,
+}
+
+/// Schedule D summary of short and long term gains for a tax year
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct ScheduleD {
+    pub short_term: ScheduleDLine,
+    pub long_term: ScheduleDLine,
+    // Negative for a net loss
+    pub net_gain: f64,
+}
+
+/// US capital gains for a calendar tax year, with a Form 8949 row for each lot a disposal was
+/// matched against
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct Form8949Report {
+    pub tax_year: i64,
+    pub rows: Vec<Form8949Row>,
+    pub schedule_d: ScheduleD,
+}
+
+/// Change to the net gain of the current tax year if a position was sold now
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct TaxEffect {
+    // e.g. `2022-23`, or `2022` for a calendar year
+    pub tax_year: String,
+    // Net gain for the year so far, negative for a net loss. In Australia it's after the
+    // discount, with any net loss carried forward as a negative
+    pub net_gain: f64,
+    pub net_gain_after: f64,
+}
+
+/// A position with an unrealized loss, and the lots to sell to realize as much of it as the
+/// matching method allows
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct HarvestCandidate {
+    pub coin: String,
+    pub price: f64,
+    // Coins to sell
+    pub amount: f64,
+    // Cost of the coins to sell and what they're worth at `price`
+    pub cost: f64,
+    pub value: f64,
+    // Loss realized by selling, as a positive amount
+    pub loss: f64,
+    // Lots the sale would be matched against, in the order they're used
+    pub lots: Vec<LotSelection>,
+    // Only when the user has a jurisdiction
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub tax_effect: Option<TaxEffect>,
+}
+
+/// Positions that could be sold to realize a loss, largest loss first
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct HarvestReport {
+    // Method the lots are matched with, the jurisdiction's if it requires one
+    pub method: CostBasisMethod,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub jurisdiction: Option<Jurisdiction>,
+    pub candidates: Vec<HarvestCandidate>,
+    pub total_loss: f64,
+}
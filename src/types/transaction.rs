@@ -1,12 +1,741 @@
 //! An addition or subtraction of coins from a user, total balance is retrieved event sourcing style
-//! where you can't delete transactions
+//! where you can't delete transactions. Mistakes are fixed by appending a void or amend that
+//! refers to the original transaction
 use serde::{Deserialize, Serialize};
+use std::time::{Duration, UNIX_EPOCH};
+use std::{collections::HashMap, fmt, str::FromStr};
+use ulid::Generator;
 
-/// represents a transaction that is added to a user
-#[derive(Serialize, Deserialize)]
+use super::{is_default, LotSelection};
+
+/// How far in the future `executed_at` can be, in seconds, to allow for client clocks being ahead
+pub const MAX_CLOCK_SKEW: i64 = 300;
+
+/// represents a transaction that is added to a user, `amount` is always positive and the
+/// `kind` decides whether coins are added or removed
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct Transaction {
+    // Sortable id generated when the transaction is recorded, it's ordered by `executed_at`
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub id: String,
     pub username: String,
     pub coin: String,
+    pub kind: TransactionKind,
     pub amount: f64,
+    // Price per coin in the quote currency, for income it's the market value when received
     pub price: f64,
+    // Fee charged for the transaction in `fee_asset`, 0 if there wasn't one
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub fee: f64,
+    // Symbol of the coin the fee was paid in e.g. `BNBAUD`, empty if paid in the quote currency
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub fee_asset: String,
+    // Price per coin of the fee asset in the quote currency when the fee was paid
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub fee_price: f64,
+    // Both legs of a coin to coin swap share the same id, empty if it's not part of a swap
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub swap_id: String,
+    // Id of the trade on the exchange it was imported from e.g. `kraken:TXID`, empty if it
+    // wasn't imported
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub external_id: String,
+    // When the trade happened in epoch seconds, supplied by the client so it can be backdated
+    pub executed_at: i64,
+    // When the transaction was stored in epoch seconds, set by the server
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub recorded_at: i64,
+    // Set when this transaction voids or amends another one, `None` for a normal transaction
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub correction: Option<Correction>,
+    // Id of the original transaction this one corrects, empty if it isn't a correction
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub corrects: String,
+    // Labels chosen by the user to group transactions e.g. `ledger-wallet`
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub tags: Vec<String>,
+    // Set to deliberately sell more than is held, opening or growing a short position
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub allow_short: bool,
+    // Lots to dispose of first when the user's cost basis method is specific lot identification
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub lots: Vec<LotSelection>,
+}
+
+/// Filters for reading a user's transaction history, every filter that's set must match.
+/// `from` and `to` are inclusive epoch seconds compared to `executed_at`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct TransactionQuery {
+    pub coin: Option<String>,
+    pub kind: Option<TransactionKind>,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub tag: Option<String>,
+    // Most recently executed first when true
+    pub descending: bool,
+    pub limit: i32,
+    // Id of the last transaction on the previous page
+    pub cursor: Option<String>,
+}
+
+/// A page of a user's transaction history, `cursor` is passed back to get the next page
+/// and is `None` on the last page
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct TransactionPage {
+    pub transactions: Vec<Transaction>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+}
+
+/// A compensating event that fixes a transaction already stored, it always refers to the
+/// original transaction even when correcting an earlier amend
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Correction {
+    /// The original transaction is removed from balances and calculations
+    Void,
+    /// The original transaction is replaced by this one
+    Amend,
+}
+
+/// Trades one coin directly for another without a fiat leg, it's stored as a sell of
+/// `sell_coin` and a buy of `buy_coin`. The fee is added to the cost of the buy leg
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct SwapRequest {
+    pub username: String,
+    pub sell_coin: String,
+    pub sell_amount: f64,
+    pub buy_coin: String,
+    pub buy_amount: f64,
+    // When the swap happened in epoch seconds, prices are looked up at this time
+    pub executed_at: i64,
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub fee: f64,
+    // Symbol of the coin the fee was paid in, empty if paid in the quote currency
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub fee_asset: String,
+    // Set to deliberately sell more of `sell_coin` than is held
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub allow_short: bool,
+}
+
+/// Adds many transactions for a user in one request, every transaction is for `username`.
+/// When `atomic` is set nothing is stored unless every transaction is valid and stored
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct TransactionBatchRequest {
+    pub username: String,
+    pub transactions: Vec<Transaction>,
+    #[serde(default)]
+    pub atomic: bool,
+}
+
+/// The outcome of each transaction in a batch, in the same order as the request
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct TransactionBatchResponse {
+    pub stored: usize,
+    pub results: Vec<BatchResult>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BatchResult {
+    pub index: usize,
+    pub status: BatchStatus,
+    // Generated id of the stored transaction
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub id: Option<String>,
+    // Why the transaction wasn't stored
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchStatus {
+    Stored,
+    /// Failed validation so it was never written
+    Invalid,
+    /// Valid but the write failed
+    Failed,
+    /// Valid but not written because another transaction in an atomic batch was invalid, or
+    /// an earlier write in the batch failed
+    Skipped,
+}
+
+/// What happened in a transaction, each kind has its own validation and effect on balances
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionKind {
+    #[default]
+    Buy,
+    Sell,
+    // Coins moved in from a wallet that isn't tracked, `price` is their original cost
+    Deposit,
+    // Coins moved out to a wallet that isn't tracked
+    Withdrawal,
+    Staking,
+    Interest,
+    Airdrop,
+    // Coins given away to someone else
+    Gift,
+    // Coins spent on a fee that isn't part of a trade e.g. a network fee
+    Fee,
+}
+
+/// How a transaction is treated for tax, used by anything calculating cost basis or gains
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Treatment {
+    /// Adds coins with the transaction price as their cost
+    Acquisition,
+    /// Adds coins valued at market value, which is also assessable income
+    Income,
+    /// Removes coins and realises a gain or loss against their cost
+    Disposal,
+    /// Adds coins without a taxable event, keeping their original cost
+    TransferIn,
+    /// Removes coins without a taxable event
+    TransferOut,
+}
+
+impl TransactionKind {
+    pub const ALL: [TransactionKind; 9] = [
+        TransactionKind::Buy,
+        TransactionKind::Sell,
+        TransactionKind::Deposit,
+        TransactionKind::Withdrawal,
+        TransactionKind::Staking,
+        TransactionKind::Interest,
+        TransactionKind::Airdrop,
+        TransactionKind::Gift,
+        TransactionKind::Fee,
+    ];
+
+    /// The name used in json and when stored in dynamodb
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TransactionKind::Buy => "buy",
+            TransactionKind::Sell => "sell",
+            TransactionKind::Deposit => "deposit",
+            TransactionKind::Withdrawal => "withdrawal",
+            TransactionKind::Staking => "staking",
+            TransactionKind::Interest => "interest",
+            TransactionKind::Airdrop => "airdrop",
+            TransactionKind::Gift => "gift",
+            TransactionKind::Fee => "fee",
+        }
+    }
+
+    pub fn treatment(&self) -> Treatment {
+        match self {
+            TransactionKind::Buy => Treatment::Acquisition,
+            TransactionKind::Staking | TransactionKind::Interest | TransactionKind::Airdrop => {
+                Treatment::Income
+            }
+            TransactionKind::Sell | TransactionKind::Gift | TransactionKind::Fee => {
+                Treatment::Disposal
+            }
+            TransactionKind::Deposit => Treatment::TransferIn,
+            TransactionKind::Withdrawal => Treatment::TransferOut,
+        }
+    }
+
+    /// 1 if the transaction adds coins to the balance, -1 if it removes them
+    pub fn direction(&self) -> f64 {
+        match self.treatment() {
+            Treatment::Acquisition | Treatment::Income | Treatment::TransferIn => 1.0,
+            Treatment::Disposal | Treatment::TransferOut => -1.0,
+        }
+    }
+
+    /// Buying and selling must have a price, for other kinds it can be 0 if it's not known
+    pub fn requires_price(&self) -> bool {
+        matches!(self, TransactionKind::Buy | TransactionKind::Sell)
+    }
+}
+
+impl fmt::Display for TransactionKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for TransactionKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TransactionKind::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == s)
+            .ok_or(format!("unknown transaction kind {}", s))
+    }
+}
+
+impl Correction {
+    /// The name used in json and when stored in dynamodb
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Correction::Void => "void",
+            Correction::Amend => "amend",
+        }
+    }
+}
+
+impl fmt::Display for Correction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Correction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [Correction::Void, Correction::Amend]
+            .into_iter()
+            .find(|correction| correction.as_str() == s)
+            .ok_or(format!("unknown correction {}", s))
+    }
+}
+
+impl Transaction {
+    /// Change to the coin balance, negative if coins are removed
+    pub fn signed_amount(&self) -> f64 {
+        self.amount * self.kind.direction()
+    }
+
+    /// Value of the fee in the quote currency
+    pub fn fee_value(&self) -> f64 {
+        if self.fee_asset.is_empty() {
+            self.fee
+        } else {
+            self.fee * self.fee_price
+        }
+    }
+
+    /// Income recorded without a price, it's valued at the market price when it was received
+    /// so the value is reported as income and becomes the cost basis of the coins
+    pub fn needs_market_value(&self) -> bool {
+        self.kind.treatment() == Treatment::Income && self.price == 0.0
+    }
+
+    /// Fair market value of the coins when the transaction was executed, before fees
+    pub fn value(&self) -> f64 {
+        self.amount * self.price
+    }
+
+    /// What the coins cost including fees, used for the cost basis of acquisitions
+    pub fn cost(&self) -> f64 {
+        self.amount * self.price + self.fee_value()
+    }
+
+    /// What was received for the coins after fees are deducted, used for disposals
+    pub fn proceeds(&self) -> f64 {
+        self.amount * self.price - self.fee_value()
+    }
+
+    /// Checks the rules for the kind of transaction, returns a message explaining what's wrong
+    pub fn validate(&self) -> Result<(), String> {
+        if self.username.is_empty() {
+            return Err("username must not be empty".to_string());
+        }
+        if self.coin.is_empty() {
+            return Err("coin must not be empty".to_string());
+        }
+        if !self.amount.is_finite() || self.amount <= 0.0 {
+            return Err(format!(
+                "amount must be greater than 0, the kind {} decides if coins are added or removed",
+                self.kind
+            ));
+        }
+        if !self.price.is_finite() || self.price < 0.0 {
+            return Err("price must not be negative".to_string());
+        }
+        if self.kind.requires_price() && self.price == 0.0 {
+            return Err(format!("price must be greater than 0 for {}", self.kind));
+        }
+        if !self.fee.is_finite() || self.fee < 0.0 {
+            return Err("fee must not be negative".to_string());
+        }
+        if !self.fee_price.is_finite() || self.fee_price < 0.0 {
+            return Err("fee_price must not be negative".to_string());
+        }
+        if self.fee_asset.is_empty() && self.fee_price != 0.0 {
+            return Err("fee_price can only be set with a fee_asset".to_string());
+        }
+        if self.executed_at <= 0 {
+            return Err("executed_at must be set to when the transaction happened".to_string());
+        }
+        if self.tags.iter().any(|tag| tag.trim().is_empty()) {
+            return Err("tags must not be empty".to_string());
+        }
+        if !self.lots.is_empty() && self.kind.direction() > 0.0 {
+            return Err(format!("lots can't be chosen for {}", self.kind));
+        }
+        if self
+            .lots
+            .iter()
+            .any(|lot| lot.id.is_empty() || !lot.amount.is_finite() || lot.amount <= 0.0)
+        {
+            return Err("each lot must have an id and an amount greater than 0".to_string());
+        }
+        if self.lots.iter().map(|lot| lot.amount).sum::<f64>() > self.amount {
+            return Err("lots must not add up to more than amount".to_string());
+        }
+        if self.correction.is_some() == self.corrects.is_empty() {
+            return Err("a correction and the id it corrects must be set together".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Sets the id and `recorded_at` of transactions about to be stored. Ids are generated from
+/// `executed_at` so they sort in execution order, transactions executed at the same time are
+/// ordered as they appear in the slice
+pub fn record_transactions(
+    transactions: &mut [Transaction],
+    recorded_at: i64,
+) -> Result<(), String> {
+    // A generator only increases, so each execution time needs its own to keep ids in order
+    let mut generators: HashMap<i64, Generator> = HashMap::new();
+    for transaction in transactions {
+        if transaction.executed_at > recorded_at + MAX_CLOCK_SKEW {
+            return Err("executed_at must not be in the future".to_string());
+        }
+        let executed_at = UNIX_EPOCH + Duration::from_secs(transaction.executed_at.max(0) as u64);
+        transaction.id = generators
+            .entry(transaction.executed_at)
+            .or_default()
+            .generate_from_datetime(executed_at)
+            .map_err(|err| err.to_string())?
+            .to_string();
+        transaction.recorded_at = recorded_at;
+    }
+    Ok(())
+}
+
+/// Sorts transactions by when they were executed. Transactions stored before they had a time
+/// all have an `executed_at` of 0, the stable sort keeps them first in the order they were added
+pub fn sort_by_execution(transactions: &mut [Transaction]) {
+    transactions.sort_by(|a, b| {
+        a.executed_at
+            .cmp(&b.executed_at)
+            .then_with(|| a.id.cmp(&b.id))
+    });
+}
+
+impl SwapRequest {
+    /// Converts the swap into its sell and buy legs, the prices are the fiat value of one
+    /// coin of each leg when the swap happened
+    pub fn legs(
+        &self,
+        swap_id: &str,
+        sell_price: f64,
+        buy_price: f64,
+        fee_price: f64,
+    ) -> [Transaction; 2] {
+        let sell = Transaction {
+            username: self.username.clone(),
+            coin: self.sell_coin.clone(),
+            kind: TransactionKind::Sell,
+            amount: self.sell_amount,
+            price: sell_price,
+            swap_id: swap_id.to_string(),
+            executed_at: self.executed_at,
+            allow_short: self.allow_short,
+            ..Default::default()
+        };
+        let buy = Transaction {
+            username: self.username.clone(),
+            coin: self.buy_coin.clone(),
+            kind: TransactionKind::Buy,
+            amount: self.buy_amount,
+            price: buy_price,
+            fee: self.fee,
+            fee_asset: self.fee_asset.clone(),
+            fee_price: if self.fee_asset.is_empty() {
+                0.0
+            } else {
+                fee_price
+            },
+            swap_id: swap_id.to_string(),
+            executed_at: self.executed_at,
+            allow_short: self.allow_short,
+            ..Default::default()
+        };
+        [sell, buy]
+    }
+
+    /// Checks the swap is between two different coins, the legs are validated separately
+    /// once their prices are known
+    pub fn validate(&self) -> Result<(), String> {
+        if self.sell_coin == self.buy_coin {
+            return Err("sell_coin and buy_coin must be different".to_string());
+        }
+        if self.executed_at <= 0 {
+            return Err("executed_at must be set to when the swap happened".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transaction(kind: TransactionKind, amount: f64, price: f64) -> Transaction {
+        Transaction {
+            username: "testuser".to_string(),
+            coin: "ETHAUD".to_string(),
+            kind,
+            amount,
+            price,
+            executed_at: 1650000000,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn kinds_round_trip_through_strings() {
+        for kind in TransactionKind::ALL {
+            assert_eq!(kind.as_str().parse::<TransactionKind>(), Ok(kind));
+            assert_eq!(
+                serde_json::to_string(&kind).expect("failed to serialize kind"),
+                format!("\"{}\"", kind)
+            );
+        }
+        assert!("short".parse::<TransactionKind>().is_err());
+    }
+
+    #[test]
+    fn signed_amount_follows_kind() {
+        assert_eq!(
+            transaction(TransactionKind::Buy, 2.0, 10.0).signed_amount(),
+            2.0
+        );
+        assert_eq!(
+            transaction(TransactionKind::Staking, 2.0, 0.0).signed_amount(),
+            2.0
+        );
+        assert_eq!(
+            transaction(TransactionKind::Deposit, 2.0, 0.0).signed_amount(),
+            2.0
+        );
+        assert_eq!(
+            transaction(TransactionKind::Sell, 2.0, 10.0).signed_amount(),
+            -2.0
+        );
+        assert_eq!(
+            transaction(TransactionKind::Withdrawal, 2.0, 0.0).signed_amount(),
+            -2.0
+        );
+        assert_eq!(
+            transaction(TransactionKind::Gift, 2.0, 0.0).signed_amount(),
+            -2.0
+        );
+        assert_eq!(
+            transaction(TransactionKind::Fee, 2.0, 0.0).signed_amount(),
+            -2.0
+        );
+    }
+
+    #[test]
+    fn validates_amount() {
+        assert!(transaction(TransactionKind::Sell, -1.0, 10.0)
+            .validate()
+            .is_err());
+        assert!(transaction(TransactionKind::Buy, 0.0, 10.0)
+            .validate()
+            .is_err());
+        assert!(transaction(TransactionKind::Buy, f64::INFINITY, 10.0)
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn validates_price_per_kind() {
+        assert!(transaction(TransactionKind::Buy, 1.0, 0.0)
+            .validate()
+            .is_err());
+        assert!(transaction(TransactionKind::Sell, 1.0, 0.0)
+            .validate()
+            .is_err());
+        assert!(transaction(TransactionKind::Airdrop, 1.0, -1.0)
+            .validate()
+            .is_err());
+        assert!(transaction(TransactionKind::Airdrop, 1.0, 0.0)
+            .validate()
+            .is_ok());
+        assert!(transaction(TransactionKind::Withdrawal, 1.0, 0.0)
+            .validate()
+            .is_ok());
+        assert!(transaction(TransactionKind::Buy, 1.0, 4500.0)
+            .validate()
+            .is_ok());
+    }
+
+    #[test]
+    fn validates_names() {
+        let mut missing_coin = transaction(TransactionKind::Buy, 1.0, 1.0);
+        missing_coin.coin = String::new();
+        assert!(missing_coin.validate().is_err());
+
+        let mut missing_user = transaction(TransactionKind::Buy, 1.0, 1.0);
+        missing_user.username = String::new();
+        assert!(missing_user.validate().is_err());
+    }
+
+    #[test]
+    fn fees_in_quote_currency() {
+        let mut buy = transaction(TransactionKind::Buy, 2.0, 100.0);
+        buy.fee = 1.5;
+        assert_eq!(buy.fee_value(), 1.5);
+        assert_eq!(buy.cost(), 201.5);
+        assert_eq!(buy.proceeds(), 198.5);
+    }
+
+    #[test]
+    fn fees_in_another_asset() {
+        let mut sell = transaction(TransactionKind::Sell, 2.0, 100.0);
+        sell.fee = 0.01;
+        sell.fee_asset = "BNBAUD".to_string();
+        sell.fee_price = 500.0;
+        assert_eq!(sell.fee_value(), 5.0);
+        assert_eq!(sell.proceeds(), 195.0);
+    }
+
+    #[test]
+    fn validates_fees() {
+        let mut negative = transaction(TransactionKind::Buy, 1.0, 1.0);
+        negative.fee = -1.0;
+        assert!(negative.validate().is_err());
+
+        let mut price_without_asset = transaction(TransactionKind::Buy, 1.0, 1.0);
+        price_without_asset.fee = 1.0;
+        price_without_asset.fee_price = 2.0;
+        assert!(price_without_asset.validate().is_err());
+
+        let mut valid = transaction(TransactionKind::Buy, 1.0, 1.0);
+        valid.fee = 1.0;
+        valid.fee_asset = "BNBAUD".to_string();
+        valid.fee_price = 2.0;
+        assert!(valid.validate().is_ok());
+    }
+
+    #[test]
+    fn swap_legs() {
+        let swap = SwapRequest {
+            username: "testuser".to_string(),
+            sell_coin: "ETHAUD".to_string(),
+            sell_amount: 1.0,
+            buy_coin: "ADAAUD".to_string(),
+            buy_amount: 2000.0,
+            executed_at: 1650000000,
+            fee: 0.01,
+            fee_asset: "BNBAUD".to_string(),
+            allow_short: false,
+        };
+        assert!(swap.validate().is_ok());
+
+        let [sell, buy] = swap.legs("swap-1", 4000.0, 2.0, 500.0);
+
+        assert_eq!(sell.kind, TransactionKind::Sell);
+        assert_eq!(sell.proceeds(), 4000.0);
+        assert_eq!(sell.fee, 0.0);
+        assert_eq!(buy.kind, TransactionKind::Buy);
+        assert_eq!(buy.cost(), 4005.0);
+        assert_eq!(sell.swap_id, buy.swap_id);
+        assert!(sell.validate().is_ok());
+        assert!(buy.validate().is_ok());
+    }
+
+    #[test]
+    fn swap_between_same_coin_is_invalid() {
+        let swap = SwapRequest {
+            sell_coin: "ETHAUD".to_string(),
+            buy_coin: "ETHAUD".to_string(),
+            ..Default::default()
+        };
+        assert!(swap.validate().is_err());
+    }
+
+    #[test]
+    fn validates_executed_at() {
+        let mut missing = transaction(TransactionKind::Buy, 1.0, 1.0);
+        missing.executed_at = 0;
+        assert!(missing.validate().is_err());
+    }
+
+    #[test]
+    fn record_orders_ids_by_execution() {
+        let mut later = transaction(TransactionKind::Sell, 1.0, 1.0);
+        later.executed_at = 1650000100;
+        let mut transactions = [
+            later,
+            transaction(TransactionKind::Buy, 1.0, 1.0),
+            transaction(TransactionKind::Buy, 2.0, 1.0),
+        ];
+        assert!(record_transactions(&mut transactions, 1650000200).is_ok());
+
+        assert!(transactions.iter().all(|t| t.recorded_at == 1650000200));
+        assert!(transactions[1].id < transactions[2].id);
+        assert!(transactions[2].id < transactions[0].id);
+
+        sort_by_execution(&mut transactions);
+        let amounts: Vec<f64> = transactions.iter().map(|t| t.amount).collect();
+        assert_eq!(amounts, [1.0, 2.0, 1.0]);
+        assert_eq!(transactions[2].kind, TransactionKind::Sell);
+    }
+
+    #[test]
+    fn record_rejects_future_transactions() {
+        let mut future = [transaction(TransactionKind::Buy, 1.0, 1.0)];
+        future[0].executed_at = 1650000000 + MAX_CLOCK_SKEW + 1;
+        assert!(record_transactions(&mut future, 1650000000).is_err());
+    }
+
+    #[test]
+    fn validates_corrections() {
+        let mut missing_id = transaction(TransactionKind::Buy, 1.0, 1.0);
+        missing_id.correction = Some(Correction::Void);
+        assert!(missing_id.validate().is_err());
+
+        let mut missing_correction = transaction(TransactionKind::Buy, 1.0, 1.0);
+        missing_correction.corrects = "01G0000000000000000000000".to_string();
+        assert!(missing_correction.validate().is_err());
+
+        missing_correction.correction = Some(Correction::Amend);
+        assert!(missing_correction.validate().is_ok());
+    }
+
+    #[test]
+    fn validates_lots() {
+        let lot = |amount: f64| LotSelection {
+            id: "01G0000000000000000000000".to_string(),
+            amount,
+        };
+
+        let mut sell = transaction(TransactionKind::Sell, 2.0, 1.0);
+        sell.lots = vec![lot(1.5), lot(0.5)];
+        assert!(sell.validate().is_ok());
+
+        sell.lots.push(lot(0.1));
+        assert!(sell.validate().is_err());
+
+        sell.lots = vec![lot(0.0)];
+        assert!(sell.validate().is_err());
+
+        let mut buy = transaction(TransactionKind::Buy, 2.0, 1.0);
+        buy.lots = vec![lot(1.0)];
+        assert!(buy.validate().is_err());
+    }
+
+    #[test]
+    fn income_without_a_price_needs_market_value() {
+        assert!(transaction(TransactionKind::Staking, 1.0, 0.0).needs_market_value());
+        assert!(!transaction(TransactionKind::Airdrop, 1.0, 2.0).needs_market_value());
+        assert!(!transaction(TransactionKind::Deposit, 1.0, 0.0).needs_market_value());
+        assert_eq!(
+            transaction(TransactionKind::Interest, 0.5, 3.0).value(),
+            1.5
+        );
+    }
 }
@@ -1,7 +1,17 @@
 //! Represents an owner of crypto assets
 use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
 
-use super::Coin;
+use super::{Coin, CostBasisMethod, Pnl};
+
+/// Where a user pays tax, decides the rules their gains are reported under
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Jurisdiction {
+    Au,
+    Uk,
+    Us,
+}
 
 /// Each user contains a a vector of how many coins they own
 /// with the total amount and display name. This minimizes the
@@ -12,13 +22,61 @@ pub struct UserGetResponse {
     pub first_name: String,
     pub last_name: String,
     pub coins: Vec<Coin>,
+    // Change in value of all coins held over the last 24 hours
+    #[serde(default)]
+    pub daily_pnl: f64,
+    // Value of all coins held at their price
+    #[serde(default)]
+    pub value: f64,
+    // Profit and loss of all coins held, the totals of each coin's
+    #[serde(default)]
+    pub pnl: Pnl,
+    // Epoch seconds the holdings were folded up to, `None` when they're current
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub as_of: Option<i64>,
 }
 
 ///  Adds a user to dynamodb, if the username already exists it just
 /// updates the first_name and last_name
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Default)]
 pub struct UserPutRequest {
     pub username: String,
     pub first_name: String,
     pub last_name: String,
+    // How sells are matched against buys for cost basis, first in first out if not set
+    #[serde(default)]
+    pub cost_basis_method: CostBasisMethod,
+    // Where the user pays tax, used for tax estimates
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub jurisdiction: Option<Jurisdiction>,
+}
+
+impl Jurisdiction {
+    pub const ALL: [Jurisdiction; 3] = [Jurisdiction::Au, Jurisdiction::Uk, Jurisdiction::Us];
+
+    /// The name used in json and when stored in dynamodb
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Jurisdiction::Au => "au",
+            Jurisdiction::Uk => "uk",
+            Jurisdiction::Us => "us",
+        }
+    }
+}
+
+impl fmt::Display for Jurisdiction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Jurisdiction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Jurisdiction::ALL
+            .into_iter()
+            .find(|jurisdiction| jurisdiction.as_str() == s)
+            .ok_or(format!("unknown jurisdiction {}", s))
+    }
 }